@@ -2,6 +2,13 @@
 
 in layout(location=1) vec3 vertexNormal;
 in layout(location=2) vec4 vertexColour;
+in layout(location=3) vec2 vertexUv;
+in layout(location=4) vec4 vertexTangent;
+
+uniform sampler2D albedo;
+uniform bool textured;
+uniform sampler2D normalMap;
+uniform bool normalMapped;
 
 vec3 lightDirection = normalize(vec3(0.8, -0.5, 0.6));
 
@@ -9,5 +16,16 @@ out vec4 color;
 
 void main()
 {
-    color = vec4(vertexColour.rgb * max(0, dot(vertexNormal, -lightDirection)), 1.0f);
+    vec3 surfaceColour = vertexColour.rgb;
+    if (textured) {
+        surfaceColour *= texture(albedo, vertexUv).rgb;
+    }
+    vec3 normal = normalize(vertexNormal);
+    if (normalMapped) {
+        // The tangent space MikkTSpace tangents were generated for
+        vec3 bitangent = cross(normal, vertexTangent.xyz) * vertexTangent.w;
+        mat3 tangentToWorld = mat3(vertexTangent.xyz, bitangent, normal);
+        normal = normalize(tangentToWorld * (texture(normalMap, vertexUv).xyz * 2.0 - 1.0));
+    }
+    color = vec4(surfaceColour * max(0, dot(normal, -lightDirection)), 1.0f);
 }
\ No newline at end of file
@@ -3,16 +3,25 @@
 in layout(location=0) vec3 position;
 in layout(location=1) vec3 normal;
 in layout(location=2) vec4 colour;
+in layout(location=3) vec2 uv;
+in layout(location=4) vec4 tangent;
 
 uniform layout(location=3) mat4 transform;
 uniform layout(location=4) mat4 model;
 
 out layout(location=1) vec3 outNormal;
 out layout(location=2) vec4 outColour;
+out layout(location=3) vec2 outUv;
+out layout(location=4) vec4 outTangent;
 
 void main()
 {
     gl_Position = transform * vec4(position, 1.0f);
-    outNormal = normalize(mat3(model) * normal);
+    // The inverse transpose keeps normals perpendicular to the surface under non-uniform scaling
+    mat3 normalMatrix = transpose(inverse(mat3(model)));
+    outNormal = normalize(normalMatrix * normal);
     outColour = colour;
+    outUv = uv;
+    // Tangents lie in the surface, so they transform like positions rather than normals
+    outTangent = vec4(normalize(mat3(model) * tangent.xyz), tangent.w);
 }
\ No newline at end of file
@@ -0,0 +1,43 @@
+// Writes mesh caches for OBJ, PLY and STL files, which Model::load reads instead from then on for a faster start-up.
+// Without arguments it converts the models of the lunar scene, with the colours and levels of detail it uses.
+// Otherwise parts without a material are white, and `--lod` gives the fractions of triangles to keep in each
+// level of detail.
+//
+//     cargo run --release --bin convert_models [model.obj... [--lod 0.25,0.05]]
+use gloom_rs::lunar_scene::LunarScene;
+use gloom_rs::mesh_cache;
+
+fn main() {
+    let mut paths = vec![];
+    let mut ratios = vec![];
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--lod" {
+            let list = args.next().unwrap_or_default();
+            ratios = list.split(',').map(|ratio| ratio.trim().parse::<f32>()).collect::<Result<_, _>>()
+                .unwrap_or_else(|e| fail(&format!("--lod takes fractions separated by commas, like 0.25,0.05: {}", e)));
+        } else {
+            paths.push(arg);
+        }
+    }
+
+    if paths.is_empty() {
+        LunarScene::write_mesh_caches().unwrap_or_else(|e| fail(&e));
+    }
+    for path in &paths {
+        match mesh_cache::convert(path, |_| [1.0, 1.0, 1.0, 1.0], &ratios) {
+            Ok((cache, reports)) => {
+                for (part, report) in reports {
+                    println!("Optimized {}: {}.", part, report);
+                }
+                println!("Wrote {}.", cache.display());
+            },
+            Err(e) => fail(&e),
+        }
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    std::process::exit(1);
+}
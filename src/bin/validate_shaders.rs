@@ -0,0 +1,30 @@
+// Validates the shaders in the given directories (./shaders by default) without creating an OpenGL context.
+// Exits with a non-zero status if any shader has errors, so it can run in CI.
+//
+//     cargo run --bin validate_shaders [directory...]
+use std::path::PathBuf;
+
+use gloom_rs::validation;
+
+fn main() {
+    let mut dirs: Vec<PathBuf> = std::env::args().skip(1).map(PathBuf::from).collect();
+    if dirs.is_empty() {
+        dirs.push(PathBuf::from("shaders"));
+    }
+
+    let mut failed = false;
+    for dir in &dirs {
+        let report = validation::validate_directory(dir);
+        for diagnostic in &report.diagnostics {
+            eprintln!("error: {}", diagnostic);
+        }
+        for path in &report.skipped {
+            println!("skipped {} (stage not supported offline)", path.display());
+        }
+        println!("{}: validated {} shaders, {} errors", dir.display(), report.validated.len(), report.diagnostics.len());
+        failed |= !report.is_ok();
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,152 @@
+use std::{
+    ffi::c_void,
+    marker::PhantomData,
+};
+
+// Owned OpenGL objects, deleted when they go out of scope.
+//
+// OpenGL objects belong to the context current on the thread that created them, so these types are neither Send
+// nor Sync: the compiler keeps them on that thread, and they are always deleted where they were made. They must
+// still be dropped before the context itself is destroyed.
+
+// Keeps a type off other threads, raw pointers being neither Send nor Sync
+type ContextThread = PhantomData<*const ()>;
+
+// Updates go through a binding point nothing else uses. Binding an index buffer to gl::ELEMENT_ARRAY_BUFFER would
+// attach it to whichever vertex array happens to be bound.
+const UPLOAD_TARGET: gl::types::GLenum = gl::COPY_WRITE_BUFFER;
+
+pub struct Buffer {
+    id: u32,
+    target: gl::types::GLenum,
+    // In bytes
+    size: usize,
+    _thread: ContextThread,
+}
+
+impl Buffer {
+    pub unsafe fn new(target: gl::types::GLenum) -> Buffer {
+        let mut id = 0;
+        gl::GenBuffers(1, &mut id);
+        Buffer { id, target, size: 0, _thread: PhantomData }
+    }
+
+    // Creates a buffer holding `data`, leaving it bound to `target`
+    pub unsafe fn from_slice<T>(target: gl::types::GLenum, data: &[T], usage: gl::types::GLenum) -> Buffer {
+        let mut buffer = Buffer::new(target);
+        buffer.bind();
+        buffer.size = std::mem::size_of_val(data);
+        gl::BufferData(target, buffer.size as isize, data.as_ptr() as *const c_void, usage);
+        buffer
+    }
+
+    // Overwrites part of the buffer, starting `byte_offset` bytes in. The buffer keeps its size.
+    // If the GPU may still be reading the old contents the driver has to wait for it, see orphan for a way around that.
+    pub unsafe fn update<T>(&self, byte_offset: usize, data: &[T]) {
+        let size = std::mem::size_of_val(data);
+        assert!(byte_offset + size <= self.size,
+            "Updating bytes {}..{} of a buffer of {} bytes", byte_offset, byte_offset + size, self.size);
+        gl::BindBuffer(UPLOAD_TARGET, self.id);
+        gl::BufferSubData(UPLOAD_TARGET, byte_offset as isize, size as isize, data.as_ptr() as *const c_void);
+    }
+
+    // Replaces the whole contents of the buffer with `data`, which may be of a different size.
+    // The old storage is handed back to the driver rather than overwritten, so draws still reading it don't stall
+    // the upload. `usage` is the hint for the new storage, typically gl::STREAM_DRAW or gl::DYNAMIC_DRAW.
+    pub unsafe fn orphan<T>(&mut self, data: &[T], usage: gl::types::GLenum) {
+        let size = std::mem::size_of_val(data);
+        gl::BindBuffer(UPLOAD_TARGET, self.id);
+        if size == self.size {
+            gl::BufferData(UPLOAD_TARGET, size as isize, std::ptr::null(), usage);
+            gl::BufferSubData(UPLOAD_TARGET, 0, size as isize, data.as_ptr() as *const c_void);
+        } else {
+            gl::BufferData(UPLOAD_TARGET, size as isize, data.as_ptr() as *const c_void, usage);
+            self.size = size;
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn target(&self) -> gl::types::GLenum {
+        self.target
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub unsafe fn bind(&self) {
+        gl::BindBuffer(self.target, self.id);
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteBuffers(1, &self.id) };
+    }
+}
+
+// A vertex array object along with the buffers its attributes and indices are read from, which live as long as it does
+pub struct VertexArray {
+    id: u32,
+    buffers: Vec<Buffer>,
+    _thread: ContextThread,
+}
+
+impl VertexArray {
+    pub unsafe fn new() -> VertexArray {
+        let mut id = 0;
+        gl::GenVertexArrays(1, &mut id);
+        VertexArray { id, buffers: vec![], _thread: PhantomData }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub unsafe fn bind(&self) {
+        gl::BindVertexArray(self.id);
+    }
+
+    // Hands a buffer the vertex array refers to over to it, so the buffer is deleted together with the vertex array
+    pub fn attach_buffer(&mut self, buffer: Buffer) {
+        self.buffers.push(buffer);
+    }
+
+    pub fn buffers(&self) -> &[Buffer] {
+        &self.buffers
+    }
+
+    pub fn buffers_mut(&mut self) -> &mut [Buffer] {
+        &mut self.buffers
+    }
+}
+
+impl Drop for VertexArray {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteVertexArrays(1, &self.id) };
+    }
+}
+
+pub struct Program {
+    id: u32,
+    _thread: ContextThread,
+}
+
+impl Program {
+    pub unsafe fn new() -> Program {
+        Program { id: gl::CreateProgram(), _thread: PhantomData }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+impl Drop for Program {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteProgram(self.id) };
+    }
+}
@@ -0,0 +1,333 @@
+extern crate nalgebra_glm as glm;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use base64::Engine;
+
+use crate::mesh::{Material, Mesh};
+use crate::normals::NormalOptions;
+use crate::texture::{Filter, TextureOptions, Wrap};
+
+// glTF 2.0 scenes, from .gltf files with their buffers and images embedded or alongside, or from .glb files.
+//
+// Unlike an OBJ model, a glTF scene says how its nodes are placed relative to each other, so parts that move, like
+// rotors, come with the pivot they turn around. See UploadedScene for putting a scene into a scene graph.
+//
+// Texture coordinates are flipped to have (0, 0) at the bottom left, as Texture expects, where glTF has it at the
+// top left. Skins, morph targets, animations, cameras and lights are left out.
+
+pub struct GltfScene {
+    pub nodes: Vec<GltfNode>,
+    // The nodes at the top of the scene the file shows by default, or of its first scene
+    pub roots: Vec<usize>,
+    // Every glTF mesh, as a list of its primitives
+    pub meshes: Vec<Vec<GltfPrimitive>>,
+    pub materials: Vec<PbrMaterial>,
+    pub textures: Vec<GltfTexture>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GltfNode {
+    // Empty for nodes without a name
+    pub name: String,
+    pub translation: glm::Vec3,
+    pub rotation: glm::Quat,
+    pub scale: glm::Vec3,
+    // Indices into GltfScene::meshes and GltfScene::nodes
+    pub mesh: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+pub struct GltfPrimitive {
+    // Coloured by the base colour of its material, times any vertex colours
+    pub mesh: Mesh,
+    // An index into GltfScene::materials, or None for glTF's default material
+    pub material: Option<usize>,
+}
+
+// glTF's metallic-roughness material. The renderer only uses the base colour and normal map for now.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PbrMaterial {
+    pub name: String,
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive: [f32; 3],
+    // Fragments less opaque than this are discarded, for alpha tested materials
+    pub alpha_cutoff: Option<f32>,
+    pub double_sided: bool,
+    // Indices into GltfScene::textures
+    pub base_color_texture: Option<usize>,
+    // Metalness in the blue channel and roughness in green
+    pub metallic_roughness_texture: Option<usize>,
+    pub normal_texture: Option<usize>,
+    pub normal_scale: f32,
+    pub occlusion_texture: Option<usize>,
+    pub occlusion_strength: f32,
+    pub emissive_texture: Option<usize>,
+}
+
+impl PbrMaterial {
+    // The closest material of the kind OBJ files have, which meshes are coloured by
+    pub fn to_material(&self) -> Material {
+        Material { name: self.name.clone(), ..Material::flat(self.base_color) }
+    }
+}
+
+impl Default for PbrMaterial {
+    // glTF's default material, for primitives without one
+    fn default() -> PbrMaterial {
+        PbrMaterial {
+            name: String::new(),
+            base_color: [1.0, 1.0, 1.0, 1.0],
+            metallic: 1.0,
+            roughness: 1.0,
+            emissive: [0.0; 3],
+            alpha_cutoff: None,
+            double_sided: false,
+            base_color_texture: None,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive_texture: None,
+        }
+    }
+}
+
+// A decoded image along with how its sampler says to sample it, ready for Texture::from_image
+pub struct GltfTexture {
+    pub image: image::RgbaImage,
+    pub options: TextureOptions,
+}
+
+impl GltfScene {
+    // Loads a .gltf or .glb file, along with the buffers and images it refers to
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<GltfScene, String> {
+        let path = path.as_ref();
+        println!("Loading scene {}...", path.display());
+        let before = std::time::Instant::now();
+        let bytes = fs::read(path).map_err(|e| format!("Failed to load glTF scene {}: {}", path.display(), e))?;
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        let scene = GltfScene::from_slice(&bytes, base)
+            .map_err(|e| format!("Failed to load glTF scene {}: {}", path.display(), e))?;
+        let after = std::time::Instant::now();
+        println!("Done in {:.3}ms.", after.duration_since(before).as_micros() as f32 / 1e3);
+        Ok(scene)
+    }
+
+    // Reads a .gltf or .glb file from memory. Buffers and images it refers to by path are looked up in `base`.
+    pub fn from_slice(bytes: &[u8], base: &Path) -> Result<GltfScene, String> {
+        let gltf = gltf::Gltf::from_slice(bytes).map_err(|e| e.to_string())?;
+        let mut blob = gltf.blob;
+        let document = gltf.document;
+
+        let buffers = document.buffers().map(|buffer| {
+            let mut data = match buffer.source() {
+                gltf::buffer::Source::Bin => blob.take().ok_or("the binary chunk is missing")?,
+                gltf::buffer::Source::Uri(uri) => read_uri(uri, base)?,
+            };
+            if data.len() < buffer.length() {
+                return Err(format!("buffer {} is {} bytes short", buffer.index(), buffer.length() - data.len()));
+            }
+            // Alignment padding at the end of the binary chunk is not part of the buffer
+            data.truncate(buffer.length());
+            Ok(data)
+        }).collect::<Result<Vec<Vec<u8>>, String>>()?;
+
+        let textures = document.textures().map(|texture| {
+            let image = texture.source();
+            let bytes = match image.source() {
+                gltf::image::Source::View { view, .. } => {
+                    // gltf doesn't check buffer views against the length of their buffers
+                    buffers[view.buffer().index()].get(view.offset()..view.offset() + view.length())
+                        .ok_or_else(|| format!("image {}: buffer view {} is out of range", image.index(), view.index()))?
+                        .to_vec()
+                }
+                gltf::image::Source::Uri { uri, .. } => read_uri(uri, base)?,
+            };
+            let image = image::load_from_memory(&bytes).map_err(|e| format!("image {}: {}", image.index(), e))?;
+            Ok(GltfTexture { image: image.into_rgba(), options: texture_options(&texture.sampler()) })
+        }).collect::<Result<Vec<GltfTexture>, String>>()?;
+
+        let materials = document.materials().map(|material| {
+            let pbr = material.pbr_metallic_roughness();
+            let texture = |info: Option<gltf::texture::Info>| info.map(|info| texture_index(info.texture(), info.tex_coord()));
+            PbrMaterial {
+                name: material.name().unwrap_or_default().to_string(),
+                base_color: pbr.base_color_factor(),
+                metallic: pbr.metallic_factor(),
+                roughness: pbr.roughness_factor(),
+                emissive: material.emissive_factor(),
+                alpha_cutoff: match material.alpha_mode() {
+                    gltf::material::AlphaMode::Mask => Some(material.alpha_cutoff().unwrap_or(0.5)),
+                    _ => None,
+                },
+                double_sided: material.double_sided(),
+                base_color_texture: texture(pbr.base_color_texture()),
+                metallic_roughness_texture: texture(pbr.metallic_roughness_texture()),
+                normal_texture: material.normal_texture().map(|normals| texture_index(normals.texture(), normals.tex_coord())),
+                normal_scale: material.normal_texture().map_or(1.0, |normals| normals.scale()),
+                occlusion_texture: material.occlusion_texture().map(|occlusion| texture_index(occlusion.texture(), occlusion.tex_coord())),
+                occlusion_strength: material.occlusion_texture().map_or(1.0, |occlusion| occlusion.strength()),
+                emissive_texture: texture(material.emissive_texture()),
+            }
+        }).collect::<Vec<PbrMaterial>>();
+
+        let meshes = document.meshes().map(|mesh| {
+            mesh.primitives()
+                .filter_map(|primitive| read_primitive(&primitive, &buffers, &materials).transpose())
+                .collect::<Result<Vec<GltfPrimitive>, String>>()
+                .map_err(|e| format!("mesh {}: {}", mesh.name().unwrap_or(&mesh.index().to_string()), e))
+        }).collect::<Result<Vec<_>, String>>()?;
+
+        let nodes = document.nodes().map(|node| {
+            let (translation, rotation, scale) = node.transform().decomposed();
+            GltfNode {
+                name: node.name().unwrap_or_default().to_string(),
+                translation: glm::make_vec3(&translation),
+                rotation: glm::quat(rotation[0], rotation[1], rotation[2], rotation[3]),
+                scale: glm::make_vec3(&scale),
+                mesh: node.mesh().map(|mesh| mesh.index()),
+                children: node.children().map(|child| child.index()).collect(),
+            }
+        }).collect();
+
+        let roots = document.default_scene().or_else(|| document.scenes().next())
+            .map_or(vec![], |scene| scene.nodes().map(|node| node.index()).collect());
+
+        Ok(GltfScene { nodes, roots, meshes, materials, textures })
+    }
+
+    // The first node with the given name
+    pub fn node(&self, name: &str) -> Option<usize> {
+        self.nodes.iter().position(|node| node.name == name)
+    }
+}
+
+fn texture_index(texture: gltf::Texture, tex_coord: u32) -> usize {
+    if tex_coord != 0 {
+        println!("WARNING::GLTF: texture {} uses texture coordinates {}, but only the first are loaded", texture.index(), tex_coord);
+    }
+    texture.index()
+}
+
+fn texture_options(sampler: &gltf::texture::Sampler) -> TextureOptions {
+    use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+    // The texture types wrap both directions the same, so the horizontal one decides
+    let wrap = match sampler.wrap_s() {
+        WrappingMode::Repeat => Wrap::Repeat,
+        WrappingMode::MirroredRepeat => Wrap::MirroredRepeat,
+        WrappingMode::ClampToEdge => Wrap::ClampToEdge,
+    };
+    let filter = match sampler.mag_filter() {
+        Some(MagFilter::Nearest) => Filter::Nearest,
+        _ => Filter::Linear,
+    };
+    let mipmaps = !matches!(sampler.min_filter(), Some(MinFilter::Nearest) | Some(MinFilter::Linear));
+    TextureOptions::default().wrap(wrap).filter(filter).mipmaps(mipmaps)
+}
+
+// Triangles, strips and fans become a mesh, while points and lines are skipped
+fn read_primitive(primitive: &gltf::Primitive, buffers: &[Vec<u8>], materials: &[PbrMaterial]) -> Result<Option<GltfPrimitive>, String> {
+    use gltf::mesh::Mode;
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+    let vertices: Vec<f32> = reader.read_positions().ok_or("a primitive has no positions")?.flatten().collect();
+    let vertex_count = vertices.len() as u32 / 3;
+    let indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..vertex_count).collect(),
+    };
+    if let Some(index) = indices.iter().find(|&&index| index >= vertex_count) {
+        return Err(format!("index {} is past the last of {} vertices", index, vertex_count));
+    }
+    let indices = match primitive.mode() {
+        Mode::Triangles => indices,
+        Mode::TriangleStrip => (2..indices.len()).flat_map(|i| {
+            // Every other triangle of a strip is turned around, to keep the winding
+            if i % 2 == 0 { [indices[i - 2], indices[i - 1], indices[i]] } else { [indices[i - 1], indices[i - 2], indices[i]] }
+        }).collect(),
+        Mode::TriangleFan => (2..indices.len()).flat_map(|i| [indices[0], indices[i - 1], indices[i]]).collect(),
+        mode => {
+            println!("WARNING::GLTF: skipping a primitive drawn as {:?}, only triangles are supported", mode);
+            return Ok(None);
+        }
+    };
+
+    let material_index = primitive.material().index();
+    let default_material = PbrMaterial::default();
+    let material = material_index.map_or(&default_material, |index| &materials[index]);
+    let colors = match reader.read_colors(0) {
+        Some(colors) => colors.into_rgba_f32()
+            .flat_map(|color| (0..4).map(move |i| color[i] * material.base_color[i]))
+            .collect(),
+        None => material.base_color.repeat(vertex_count as usize),
+    };
+    let uvs = reader.read_tex_coords(0)
+        .map_or(vec![], |uvs| uvs.into_f32().flat_map(|[u, v]| [u, 1.0 - v]).collect());
+    let normals: Vec<f32> = reader.read_normals().map_or(vec![], |normals| normals.flatten().collect());
+    // Flipping the texture coordinates turns the bitangent around
+    let tangents = match reader.read_tangents() {
+        Some(tangents) if !normals.is_empty() => tangents.flat_map(|[x, y, z, w]| [x, y, z, -w]).collect(),
+        _ => vec![],
+    };
+
+    let mut mesh = Mesh {
+        vertices,
+        normals,
+        uvs,
+        tangents,
+        colors,
+        index_count: indices.len() as i32,
+        indices,
+        material: Some(material.to_material()),
+        levels_of_detail: vec![],
+    };
+    // glTF asks for flat normals when a primitive has none
+    if mesh.normals.is_empty() && vertex_count > 0 {
+        mesh.generate_normals(&NormalOptions::flat());
+    }
+    if material.normal_texture.is_some() && mesh.tangents.is_empty() {
+        if let Err(e) = mesh.generate_tangents() {
+            println!("WARNING::GLTF: the normal map can't be used: {}", e);
+        }
+    }
+    Ok(Some(GltfPrimitive { mesh, material: material_index }))
+}
+
+// Reads a buffer or image from a data URI or a file relative to the scene
+fn read_uri(uri: &str, base: &Path) -> Result<Vec<u8>, String> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, encoded) = data.split_once(";base64,").ok_or_else(|| format!("the data URI {} is not base64", uri))?;
+        return base64::engine::general_purpose::STANDARD.decode(encoded).map_err(|e| format!("a data URI is malformed: {}", e));
+    }
+    let path = base.join(decode_percents(uri));
+    fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// URIs escape spaces and other characters as %XX
+fn decode_percents(uri: &str) -> PathBuf {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes.get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escape) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(String::from_utf8_lossy(&decoded).into_owned())
+}
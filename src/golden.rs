@@ -0,0 +1,116 @@
+use std::path::{Path, PathBuf};
+
+use image::{Rgba, RgbaImage};
+
+// Reference images live in the repository, failed comparisons are written next to the build output
+pub const GOLDEN_DIR: &str = "tests/golden";
+pub const FAILURE_DIR: &str = "target/golden";
+// Set this environment variable to overwrite the stored references with freshly rendered images
+pub const UPDATE_ENV: &str = "GLOOM_UPDATE_GOLDEN";
+
+// How far a rendered image may stray from its reference before the comparison fails.
+// A pixel mismatches if any of its channels differs by more than `channel`, and the comparison fails
+// when more than `max_mismatched_ratio` of all pixels mismatch.
+#[derive(Clone, Copy, Debug)]
+pub struct Tolerance {
+    pub channel: u8,
+    pub max_mismatched_ratio: f32,
+}
+
+impl Default for Tolerance {
+    fn default() -> Tolerance {
+        // Enough to absorb rounding differences between drivers, not enough to hide a moved edge
+        Tolerance { channel: 2, max_mismatched_ratio: 0.001 }
+    }
+}
+
+pub struct Comparison {
+    pub mismatched_pixels: usize,
+    pub total_pixels: usize,
+    pub max_difference: u8,
+    // Mismatched pixels in red over a faded copy of the reference
+    pub diff_image: RgbaImage,
+}
+
+impl Comparison {
+    pub fn passes(&self, tolerance: &Tolerance) -> bool {
+        self.mismatched_pixels as f32 <= tolerance.max_mismatched_ratio * self.total_pixels as f32
+    }
+}
+
+// Compares two images of the same size pixel by pixel
+pub fn compare_images(actual: &RgbaImage, expected: &RgbaImage, tolerance: &Tolerance) -> Comparison {
+    assert_eq!(actual.dimensions(), expected.dimensions(), "Can only compare images of the same size");
+
+    let mut diff_image = RgbaImage::new(actual.width(), actual.height());
+    let mut mismatched_pixels = 0;
+    let mut max_difference = 0;
+    for ((a, e), d) in actual.pixels().zip(expected.pixels()).zip(diff_image.pixels_mut()) {
+        let difference = a.0.iter().zip(e.0.iter())
+            .map(|(&x, &y)| (x as i16 - y as i16).unsigned_abs() as u8)
+            .max()
+            .unwrap_or(0);
+        max_difference = max_difference.max(difference);
+        *d = if difference > tolerance.channel {
+            mismatched_pixels += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let grey = ((e[0] as u16 + e[1] as u16 + e[2] as u16) / 12) as u8;
+            Rgba([grey, grey, grey, 255])
+        };
+    }
+
+    Comparison {
+        mismatched_pixels,
+        total_pixels: (actual.width() * actual.height()) as usize,
+        max_difference,
+        diff_image,
+    }
+}
+
+pub fn golden_path(name: &str) -> PathBuf {
+    Path::new(GOLDEN_DIR).join(format!("{}.png", name))
+}
+
+// Checks `actual` against the reference image stored under `name`.
+// Every reference is recorded from `actual` when UPDATE_ENV is set. Otherwise a missing reference is an error, so
+// a deleted or renamed image can't turn the check into one that passes without comparing anything.
+// On failure the rendered image and a diff image are written to FAILURE_DIR, and the returned error says where.
+pub fn check_golden(name: &str, actual: &RgbaImage, tolerance: &Tolerance) -> Result<(), String> {
+    let reference = golden_path(name);
+    if std::env::var_os(UPDATE_ENV).is_some() {
+        std::fs::create_dir_all(GOLDEN_DIR).map_err(|e| format!("Failed to create {}: {}", GOLDEN_DIR, e))?;
+        actual.save(&reference).map_err(|e| format!("Failed to write {}: {}", reference.display(), e))?;
+        println!("Recorded golden image {}", reference.display());
+        return Ok(());
+    }
+    if !reference.exists() {
+        return Err(format!("{}: there is no reference image {}. Run with {}=1 to record it.",
+            name, reference.display(), UPDATE_ENV));
+    }
+
+    let expected = image::open(&reference)
+        .map_err(|e| format!("Failed to read {}: {}", reference.display(), e))?
+        .into_rgba();
+    if expected.dimensions() != actual.dimensions() {
+        return Err(format!("{}: rendered {:?}, but the reference is {:?}",
+            name, actual.dimensions(), expected.dimensions()));
+    }
+
+    let comparison = compare_images(actual, &expected, tolerance);
+    if comparison.passes(tolerance) {
+        return Ok(());
+    }
+
+    let failure_dir = Path::new(FAILURE_DIR);
+    let actual_path = failure_dir.join(format!("{}.actual.png", name));
+    let diff_path = failure_dir.join(format!("{}.diff.png", name));
+    std::fs::create_dir_all(failure_dir).map_err(|e| format!("Failed to create {}: {}", FAILURE_DIR, e))?;
+    actual.save(&actual_path).map_err(|e| format!("Failed to write {}: {}", actual_path.display(), e))?;
+    comparison.diff_image.save(&diff_path).map_err(|e| format!("Failed to write {}: {}", diff_path.display(), e))?;
+
+    Err(format!(
+        "{}: {} of {} pixels differ from the reference (largest channel difference {}, tolerance {}).\n  rendered: {}\n  diff:     {}\n  Rerun with {}=1 to accept the new image.",
+        name, comparison.mismatched_pixels, comparison.total_pixels, comparison.max_difference, tolerance.channel,
+        actual_path.display(), diff_path.display(), UPDATE_ENV))
+}
@@ -0,0 +1,315 @@
+extern crate nalgebra_glm as glm;
+
+use std::path::{Path, PathBuf};
+
+use glutin::{ContextBuilder, GlRequest, GlProfile, Api, PossiblyCurrent};
+use glutin::dpi::PhysicalSize;
+
+use crate::lunar_scene::{self, LunarScene};
+use crate::render;
+use crate::shader;
+
+// Settings for rendering the scene without a window, one PNG per frame
+pub struct HeadlessOptions {
+    pub width: u32,
+    pub height: u32,
+    pub frames: usize,
+    pub start_time: f32,
+    pub time_step: f32,
+    pub helicopter_count: usize,
+    pub output_dir: PathBuf,
+}
+
+impl Default for HeadlessOptions {
+    fn default() -> HeadlessOptions {
+        HeadlessOptions {
+            width: 800,
+            height: 600,
+            frames: 1,
+            start_time: 0.0,
+            time_step: 1.0 / 60.0,
+            helicopter_count: 5,
+            output_dir: PathBuf::from("frames"),
+        }
+    }
+}
+
+impl HeadlessOptions {
+    // Parses the command line of a headless run. Returns Ok(None) if `--headless` was not given.
+    //   --headless [--frames N] [--size WxH] [--start S] [--time-step S] [--helicopters N] [--output DIR]
+    pub fn from_args<I: Iterator<Item = String>>(args: I) -> Result<Option<HeadlessOptions>, String> {
+        let args: Vec<String> = args.collect();
+        if !args.iter().any(|a| a == "--headless") {
+            return Ok(None);
+        }
+
+        fn parse<T: std::str::FromStr>(flag: &str, value: Option<&String>) -> Result<T, String> {
+            let value = value.ok_or(format!("Missing value for {}", flag))?;
+            value.parse().map_err(|_| format!("Invalid value for {}: {}", flag, value))
+        }
+
+        let mut options = HeadlessOptions::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--headless" => { },
+                "--frames"      => { options.frames = parse(arg, args.next())?; },
+                "--start"       => { options.start_time = parse(arg, args.next())?; },
+                "--time-step"   => { options.time_step = parse(arg, args.next())?; },
+                "--helicopters" => { options.helicopter_count = parse(arg, args.next())?; },
+                "--output"      => { options.output_dir = parse(arg, args.next())?; },
+                "--size" => {
+                    let size: String = parse(arg, args.next())?;
+                    let (w, h) = size.split_once('x').ok_or(format!("Expected WIDTHxHEIGHT, got {}", size))?;
+                    options.width = parse(arg, Some(&w.to_string()))?;
+                    options.height = parse(arg, Some(&h.to_string()))?;
+                },
+                a => { return Err(format!("Unknown argument: {}", a)) },
+            }
+        }
+        Ok(Some(options))
+    }
+}
+
+// The contexts are never read, they only have to outlive the HeadlessContext which owns them
+#[allow(dead_code)]
+enum Backend {
+    Glutin(glutin::Context<PossiblyCurrent>),
+    #[cfg(unix)]
+    Egl(Box<egl_surfaceless::EglContext>),
+}
+
+// An OpenGL context which is not tied to a window. Rendering goes to a framebuffer object of a fixed
+// size, which works the same way no matter whether the platform gave us a default framebuffer or not.
+pub struct HeadlessContext {
+    _backend: Backend,
+    framebuffer: u32,
+    renderbuffers: [u32; 2],
+    width: u32,
+    height: u32,
+}
+
+impl HeadlessContext {
+    // Creates a context, makes it current on this thread and loads the GL function pointers.
+    // On unix OSMesa is tried first, then EGL without a surface (Mesa's llvmpipe works for both),
+    // elsewhere the platform's regular hidden-window headless context is used.
+    pub fn new(width: u32, height: u32) -> Result<HeadlessContext, String> {
+        let backend = HeadlessContext::create_backend(width, height)?;
+        unsafe {
+            let mut framebuffer = 0;
+            let mut renderbuffers = [0; 2];
+            gl::GenFramebuffers(1, &mut framebuffer);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
+            gl::GenRenderbuffers(2, renderbuffers.as_mut_ptr());
+            gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffers[0]);
+            gl::RenderbufferStorage(gl::RENDERBUFFER, gl::RGBA8, width as i32, height as i32);
+            gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::RENDERBUFFER, renderbuffers[0]);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffers[1]);
+            gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH_COMPONENT24, width as i32, height as i32);
+            gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::RENDERBUFFER, renderbuffers[1]);
+
+            let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+            let context = HeadlessContext { _backend: backend, framebuffer, renderbuffers, width, height };
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                return Err(format!("Offscreen framebuffer is incomplete (status 0x{:x})", status));
+            }
+            gl::Viewport(0, 0, width as i32, height as i32);
+            Ok(context)
+        }
+    }
+
+    pub fn width(&self) -> u32 { self.width }
+    pub fn height(&self) -> u32 { self.height }
+
+    fn builder<'a>() -> ContextBuilder<'a, glutin::NotCurrent> {
+        ContextBuilder::new()
+            .with_gl(GlRequest::Specific(Api::OpenGl, (4, 3)))
+            .with_gl_profile(GlProfile::Core)
+    }
+
+    fn make_current(context: glutin::Context<glutin::NotCurrent>) -> Result<Backend, String> {
+        let context = unsafe { context.make_current() }.map_err(|(_, e)| e.to_string())?;
+        gl::load_with(|symbol| context.get_proc_address(symbol) as *const _);
+        Ok(Backend::Glutin(context))
+    }
+
+    #[cfg(unix)]
+    fn create_backend(width: u32, height: u32) -> Result<Backend, String> {
+        use glutin::platform::unix::HeadlessContextExt;
+
+        let osmesa_error = match HeadlessContext::builder().build_osmesa(PhysicalSize::new(width, height)) {
+            Ok(context) => return HeadlessContext::make_current(context),
+            Err(e) => e,
+        };
+        match egl_surfaceless::EglContext::new() {
+            Ok(context) => Ok(Backend::Egl(Box::new(context))),
+            Err(egl_error) => Err(format!(
+                "Could not create a headless OpenGL context.\n  OSMesa: {}\n  EGL: {}", osmesa_error, egl_error)),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn create_backend(width: u32, height: u32) -> Result<Backend, String> {
+        let event_loop = glutin::event_loop::EventLoop::new();
+        let context = HeadlessContext::builder()
+            .build_headless(&event_loop, PhysicalSize::new(width, height))
+            .map_err(|e| format!("Could not create a headless OpenGL context: {}", e))?;
+        HeadlessContext::make_current(context)
+    }
+
+    // Reads back the colour buffer, flipped so the first row is the top of the image
+    pub unsafe fn read_pixels(&self) -> image::RgbaImage {
+        let mut pixels = vec![0u8; (self.width * self.height * 4) as usize];
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.framebuffer);
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::ReadPixels(0, 0, self.width as i32, self.height as i32, gl::RGBA, gl::UNSIGNED_BYTE,
+                       pixels.as_mut_ptr() as *mut std::ffi::c_void);
+        let image = image::RgbaImage::from_raw(self.width, self.height, pixels)
+            .expect("Pixel buffer does not match the framebuffer size");
+        image::imageops::flip_vertical(&image)
+    }
+}
+
+impl Drop for HeadlessContext {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.framebuffer);
+            gl::DeleteRenderbuffers(2, self.renderbuffers.as_ptr());
+        }
+    }
+}
+
+// A lunar scene together with the context it is rendered in
+// Fields are dropped in order, and the GL objects of the scene and shader need the context to still be alive
+pub struct HeadlessRenderer {
+    pub scene: LunarScene,
+    pub shader: shader::Shader,
+    pub context: HeadlessContext,
+}
+
+impl HeadlessRenderer {
+    pub fn new(width: u32, height: u32, helicopter_count: usize) -> Result<HeadlessRenderer, String> {
+        HeadlessRenderer::with_scene(width, height, || unsafe { LunarScene::load(helicopter_count) })
+    }
+
+    // Like new, but the scene is built by `create_scene` once the context is current, e.g. from generated meshes
+    pub fn with_scene<F: FnOnce() -> LunarScene>(width: u32, height: u32, create_scene: F) -> Result<HeadlessRenderer, String> {
+        let context = HeadlessContext::new(width, height)?;
+        unsafe {
+            render::init_gl_state();
+            render::print_gl_diagnostics();
+
+            let scene = create_scene();
+            let shader = shader::ShaderBuilder::new().attach_file("./shaders/simple.vert")
+                .and_then(|builder| builder.attach_file("./shaders/simple.frag"))
+                .and_then(|builder| builder.link())
+                .map_err(|e| e.to_string())?;
+            Ok(HeadlessRenderer { scene, shader, context })
+        }
+    }
+
+    // Renders the scene as it looks `elapsed` seconds into the animation, seen from the default camera
+    pub fn render(&mut self, elapsed: f32) -> image::RgbaImage {
+        let aspect = self.context.width() as f32 / self.context.height() as f32;
+        let transform = lunar_scene::camera_transform(aspect, &glm::zero(), &glm::zero());
+        self.scene.animate(elapsed);
+        unsafe {
+            self.shader.activate();
+            gl::ClearColor(0.163, 0.163, 0.163, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        }
+        self.scene.draw(&transform, &self.shader);
+        unsafe {
+            gl::Finish();
+            self.context.read_pixels()
+        }
+    }
+}
+
+// Renders `options.frames` frames and writes them to `options.output_dir` as frame_0000.png, frame_0001.png, ...
+pub fn render_frames(options: &HeadlessOptions) -> Result<Vec<PathBuf>, String> {
+    let mut renderer = HeadlessRenderer::new(options.width, options.height, options.helicopter_count)?;
+    std::fs::create_dir_all(&options.output_dir)
+        .map_err(|e| format!("Failed to create {}: {}", options.output_dir.display(), e))?;
+
+    let mut written = vec![];
+    for frame in 0..options.frames {
+        let elapsed = options.start_time + frame as f32 * options.time_step;
+        let image = renderer.render(elapsed);
+        let path = frame_path(&options.output_dir, frame);
+        image.save(&path).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+fn frame_path(dir: &Path, frame: usize) -> PathBuf {
+    dir.join(format!("frame_{:04}.png", frame))
+}
+
+// A bare EGL context without any surface, as offered by Mesa's EGL_MESA_platform_surfaceless.
+// Needs neither a display server nor a GPU when Mesa falls back to llvmpipe.
+#[cfg(unix)]
+mod egl_surfaceless {
+    use khronos_egl as egl;
+
+    const PLATFORM_SURFACELESS_MESA: egl::Enum = 0x31DD;
+
+    pub struct EglContext {
+        egl: egl::DynamicInstance<egl::EGL1_5>,
+        display: egl::Display,
+        context: egl::Context,
+    }
+
+    impl EglContext {
+        pub fn new() -> Result<EglContext, String> {
+            let egl = unsafe { egl::DynamicInstance::<egl::EGL1_5>::load_required() }
+                .map_err(|e| format!("Failed to load libEGL: {}", e))?;
+            let display = unsafe {
+                egl.get_platform_display(PLATFORM_SURFACELESS_MESA, egl::DEFAULT_DISPLAY, &[egl::ATTRIB_NONE])
+            }.map_err(|e| format!("No surfaceless EGL display: {}", e))?;
+            egl.initialize(display).map_err(|e| format!("Failed to initialize EGL: {}", e))?;
+
+            let context = EglContext::create_context(&egl, display);
+            let context = match context {
+                Ok(context) => context,
+                Err(e) => {
+                    let _ = egl.terminate(display);
+                    return Err(e);
+                },
+            };
+            gl::load_with(|symbol| {
+                egl.get_proc_address(symbol).map_or(std::ptr::null(), |f| f as *const _)
+            });
+            Ok(EglContext { egl, display, context })
+        }
+
+        fn create_context(egl: &egl::DynamicInstance<egl::EGL1_5>, display: egl::Display) -> Result<egl::Context, String> {
+            egl.bind_api(egl::OPENGL_API).map_err(|e| format!("EGL has no desktop OpenGL: {}", e))?;
+            let config = egl.choose_first_config(display, &[
+                egl::SURFACE_TYPE, 0,
+                egl::RENDERABLE_TYPE, egl::OPENGL_BIT,
+                egl::NONE,
+            ]).map_err(|e| e.to_string())?.ok_or("No EGL config supports desktop OpenGL")?;
+            let context = egl.create_context(display, config, None, &[
+                egl::CONTEXT_MAJOR_VERSION, 4,
+                egl::CONTEXT_MINOR_VERSION, 3,
+                egl::CONTEXT_OPENGL_PROFILE_MASK, egl::CONTEXT_OPENGL_CORE_PROFILE_BIT,
+                egl::NONE,
+            ]).map_err(|e| format!("Failed to create an OpenGL 4.3 core context: {}", e))?;
+            if let Err(e) = egl.make_current(display, None, None, Some(context)) {
+                let _ = egl.destroy_context(display, context);
+                return Err(format!("Failed to make the surfaceless context current: {}", e));
+            }
+            Ok(context)
+        }
+    }
+
+    impl Drop for EglContext {
+        fn drop(&mut self) {
+            let _ = self.egl.make_current(self.display, None, None, None);
+            let _ = self.egl.destroy_context(self.display, self.context);
+            let _ = self.egl.terminate(self.display);
+        }
+    }
+}
@@ -0,0 +1,31 @@
+// Nearly every unsafe function in this crate is unsafe for the same reason: it calls into OpenGL, and is only
+// sound when called from the thread which holds the current context, after the function pointers are loaded.
+#![allow(clippy::missing_safety_doc)]
+
+extern crate nalgebra_glm as glm;
+
+pub mod gl_objects;
+pub mod shader;
+pub mod preprocessor;
+pub mod reflection;
+pub mod program_cache;
+pub mod validation;
+pub mod util;
+pub mod mesh;
+pub mod normals;
+pub mod tangents;
+pub mod simplify;
+pub mod optimize;
+pub mod mesh_cache;
+pub mod ply;
+pub mod stl;
+pub mod gltf_scene;
+pub mod vertex_layout;
+pub mod streaming;
+pub mod texture;
+pub mod scene_graph;
+pub mod toolbox;
+pub mod render;
+pub mod lunar_scene;
+pub mod headless;
+pub mod golden;
@@ -0,0 +1,147 @@
+extern crate nalgebra_glm as glm;
+
+use std::f32::consts::PI;
+
+use crate::mesh::Model;
+use crate::mesh_cache;
+use crate::render::{self, LodLevel, UploadedModel};
+use crate::scene_graph::{SceneGraph, SceneNode, NodeHandle};
+use crate::shader::Shader;
+use crate::toolbox::simple_heading_animation;
+
+pub const TERRAIN_PATH: &str = "resources/lunarsurface.obj";
+pub const HELICOPTER_PATH: &str = "resources/helicopter.obj";
+
+// The lunar surface is simplified for when it is seen from afar
+const TERRAIN_LEVELS_OF_DETAIL: [LodLevel; 2] = [
+    LodLevel { triangle_ratio: 0.25, max_screen_size: 1.0 },
+    LodLevel { triangle_ratio: 0.05, max_screen_size: 0.3 },
+];
+
+// The parts of the helicopter model the scene poses or colours
+pub const HELICOPTER_BODY: &str = "Body_body";
+pub const HELICOPTER_MAIN_ROTOR: &str = "Main_Rotor_main_rotor";
+pub const HELICOPTER_TAIL_ROTOR: &str = "Tail_Rotor_tail_rotor";
+pub const HELICOPTER_DOOR: &str = "Door_door";
+
+// The lunar surface with a small squadron of helicopters circling above it.
+// Everything about the scene is a function of the elapsed time, so any frame can be reproduced exactly.
+pub struct LunarScene {
+    pub graph: SceneGraph,
+    pub root: NodeHandle,
+    pub helicopters: Vec<NodeHandle>,
+    // The main and tail rotor of each helicopter, for models that have them
+    rotors: Vec<(Option<NodeHandle>, Option<NodeHandle>)>,
+    // The nodes only refer to the vertex arrays and textures of the models by id, the scene owns them
+    models: Vec<UploadedModel>,
+}
+
+impl LunarScene {
+    pub unsafe fn load(helicopter_count: usize) -> LunarScene {
+        let terrain = Model::load(TERRAIN_PATH, terrain_color).unwrap_or_else(|e| panic!("{}", e));
+        let helicopter = Model::load(HELICOPTER_PATH, helicopter_color).unwrap_or_else(|e| panic!("{}", e));
+        LunarScene::from_models(&terrain, &helicopter, helicopter_count)
+    }
+
+    pub unsafe fn from_models(terrain: &Model, helicopter: &Model, helicopter_count: usize) -> LunarScene {
+        let terrain_model = UploadedModel::with_levels_of_detail(terrain, &TERRAIN_LEVELS_OF_DETAIL);
+        let helicopter_model = UploadedModel::new(helicopter);
+
+        // Set up scene graph
+        let mut graph = SceneGraph::new();
+        let root = graph.add(SceneNode::new());
+        let terrain_node = terrain_model.instantiate(&mut graph).root;
+        graph.add_child(root, terrain_node);
+
+        let mut helicopters = Vec::<NodeHandle>::new();
+        let mut rotors = vec![];
+        for _ in 0..helicopter_count {
+            let instance = helicopter_model.instantiate(&mut graph);
+            let main_rotor = instance.part(HELICOPTER_MAIN_ROTOR);
+            let tail_rotor = instance.part(HELICOPTER_TAIL_ROTOR);
+            if let Some(node) = main_rotor {
+                graph[node].reference_point = glm::vec3(0.0, 2.2, 0.0);
+            }
+            if let Some(node) = tail_rotor {
+                graph[node].reference_point = glm::vec3(0.35, 2.3, 10.4);
+            }
+
+            graph.add_child(terrain_node, instance.root);
+            helicopters.push(instance.root);
+            rotors.push((main_rotor, tail_rotor));
+        }
+
+        LunarScene { graph, root, helicopters, rotors, models: vec![terrain_model, helicopter_model] }
+    }
+
+    // Writes mesh caches of the scene's models, along with the terrain's levels of detail, which `load` then
+    // reads instead of the OBJ files
+    pub fn write_mesh_caches() -> Result<(), String> {
+        let terrain_ratios: Vec<f32> = TERRAIN_LEVELS_OF_DETAIL.iter().map(|level| level.triangle_ratio).collect();
+        for (path, fallback_color, ratios) in [
+            (TERRAIN_PATH, terrain_color as fn(&str) -> [f32; 4], &terrain_ratios[..]),
+            (HELICOPTER_PATH, helicopter_color, &[]),
+        ] {
+            let (cache, reports) = mesh_cache::convert(path, fallback_color, ratios)?;
+            for (part, report) in reports {
+                println!("Optimized {}: {}.", part, report);
+            }
+            println!("Wrote {}.", cache.display());
+        }
+        Ok(())
+    }
+
+    pub fn models(&self) -> &[UploadedModel] {
+        &self.models
+    }
+
+    // Poses every helicopter for the given number of seconds since the start of the animation
+    pub fn animate(&mut self, elapsed: f32) {
+        for (i, &helicopter) in self.helicopters.iter().enumerate() {
+            let heading = simple_heading_animation(elapsed + 0.8 * i as f32);
+
+            let (main_rotor, tail_rotor) = self.rotors[i];
+            if let Some(main_rotor) = main_rotor {
+                self.graph[main_rotor].rotation = glm::vec3(0.0, 10.0 * elapsed, 0.0);
+            }
+            if let Some(tail_rotor) = tail_rotor {
+                self.graph[tail_rotor].rotation = glm::vec3(10.0 * elapsed, 0.0, 0.0);
+            }
+
+            self.graph[helicopter].position = glm::vec3(heading.x, 1.0, heading.z);
+            self.graph[helicopter].rotation = glm::vec3(heading.yaw, heading.pitch, heading.roll);
+        }
+    }
+
+    pub fn draw(&mut self, view_projection_matrix: &glm::Mat4, shader: &Shader) {
+        render::update_node_transformations(&mut self.graph, self.root, &glm::identity());
+        render::draw_scene(&self.graph, self.root, view_projection_matrix, shader);
+    }
+}
+
+// The view-projection matrix for a camera at `position`, turned by the yaw (x) and pitch (y) in `angles`
+pub fn camera_transform(aspect: f32, position: &glm::Vec3, angles: &glm::Vec2) -> glm::Mat4 {
+    let mut transform: glm::Mat4 = glm::identity();
+
+    transform *= glm::perspective(aspect, PI / 2.0, 1.0, 1000.0);
+    transform *= glm::translation(&glm::vec3(0.0, 0.0, -1.2));
+    transform *= glm::rotation(angles.y, &glm::vec3(1.0, 0.0, 0.0));
+    transform *= glm::rotation(angles.x, &glm::vec3(0.0, 1.0, 0.0));
+    transform *= glm::translation(position);
+
+    transform
+}
+
+fn terrain_color(_part: &str) -> [f32; 4] {
+    [1.0, 1.0, 1.0, 1.0]
+}
+
+// The colours helicopter parts without a material have always been drawn in
+fn helicopter_color(part: &str) -> [f32; 4] {
+    match part {
+        HELICOPTER_MAIN_ROTOR => [0.3, 0.1, 0.1, 1.0],
+        HELICOPTER_TAIL_ROTOR => [0.1, 0.3, 0.1, 1.0],
+        HELICOPTER_DOOR => [0.1, 0.1, 0.3, 1.0],
+        _ => [0.3, 0.3, 0.3, 1.0],
+    }
+}
@@ -1,125 +1,31 @@
 extern crate nalgebra_glm as glm;
-use std::{ mem, ptr, os::raw::c_void };
 use std::thread;
 use std::sync::{Mutex, Arc, RwLock};
 
-mod shader;
-mod util;
-mod mesh;
-mod scene_graph;
-mod toolbox;
-
 use glutin::event::{Event, WindowEvent, DeviceEvent, KeyboardInput, ElementState::{Pressed, Released}, VirtualKeyCode::{self, *}};
 use glutin::event_loop::ControlFlow;
-use glm::{Vec3, Vec2, identity};
-use crate::shader::Shader;
-use std::f32::consts::PI;
-use crate::toolbox::simple_heading_animation;
+use gloom_rs::{shader, render, headless, lunar_scene, program_cache};
+use gloom_rs::program_cache::ProgramCache;
+use gloom_rs::shader::Shader;
+use gloom_rs::lunar_scene::LunarScene;
 
 const SCREEN_W: u32 = 800;
 const SCREEN_H: u32 = 600;
 
-// == // Helper functions to make interacting with OpenGL a little bit prettier. You *WILL* need these! // == //
-// The names should be pretty self explanatory
-fn byte_size_of_array<T>(val: &[T]) -> isize {
-    std::mem::size_of_val(&val[..]) as isize
-}
-
-// Get the OpenGL-compatible pointer to an arbitrary array of numbers
-fn pointer_to_array<T>(val: &[T]) -> *const c_void {
-    &val[0] as *const T as *const c_void
-}
-
-// Get the size of the given type in bytes
-fn size_of<T>() -> i32 {
-    mem::size_of::<T>() as i32
-}
-
-// Get an offset in bytes for n units of type T
-fn offset<T>(n: u32) -> *const c_void {
-    (n * mem::size_of::<T>() as u32) as *const T as *const c_void
-}
-
-// Get a null pointer (equivalent to an offset of 0)
-// ptr::null()
-
-
-
-// == // Modify and complete the function below for the first task
-unsafe fn create_mesh_vao(mesh: &mesh::Mesh) -> u32 {
-    create_vao(&mesh.vertices, &mesh.normals, &mesh.colors, &mesh.indices)
-}
-
-unsafe fn create_vao(vertices: &Vec<f32>, normals: &Vec<f32>, colours: &Vec<f32>, indices: &Vec<u32>) -> u32 {
-    let mut vao_index: u32 = 0;
-    gl::GenVertexArrays(1, &mut vao_index);
-    gl::BindVertexArray(vao_index);
-
-    let mut buffer_index: u32 = 0;
-    gl::GenBuffers(1, &mut buffer_index);
-    gl::BindBuffer(gl::ARRAY_BUFFER, buffer_index);
-    gl::BufferData(gl::ARRAY_BUFFER, byte_size_of_array(&vertices), pointer_to_array(&vertices), gl::STATIC_DRAW);
-    gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, 0, ptr::null());
-    gl::EnableVertexAttribArray(0);
-
-    gl::GenBuffers(1, &mut buffer_index);
-    gl::BindBuffer(gl::ARRAY_BUFFER, buffer_index);
-    gl::BufferData(gl::ARRAY_BUFFER, byte_size_of_array(&normals), pointer_to_array(&normals), gl::STATIC_DRAW);
-    gl::VertexAttribPointer(1, 3, gl::FLOAT, gl::FALSE, 0, ptr::null());
-    gl::EnableVertexAttribArray(1);
-
-    gl::GenBuffers(1, &mut buffer_index);
-    gl::BindBuffer(gl::ARRAY_BUFFER, buffer_index);
-    gl::BufferData(gl::ARRAY_BUFFER, byte_size_of_array(&colours), pointer_to_array(&colours), gl::STATIC_DRAW);
-    gl::VertexAttribPointer(2, 4, gl::FLOAT, gl::FALSE, 0, ptr::null());
-    gl::EnableVertexAttribArray(2);
-
-    gl::GenBuffers(1, &mut buffer_index);
-    gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, buffer_index);
-    gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, byte_size_of_array(&indices), pointer_to_array(&indices), gl::STATIC_DRAW);
-
-    vao_index
-}
-
-unsafe fn update_node_transformations(root: &mut scene_graph::SceneNode, transformation_so_far: &glm::Mat4) {
-    // Construct the correct transformation matrix
-    let mut transform: glm::Mat4 = glm::identity();
-
-    transform *= transformation_so_far;
-    transform *= glm::translation(&root.position);
-    transform *= glm::translation(&root.reference_point);
-    transform *= glm::rotation(root.rotation.z, &glm::vec3(0.0, 0.0, 1.0));
-    transform *= glm::rotation(root.rotation.y, &glm::vec3(0.0, 1.0, 0.0));
-    transform *= glm::rotation(root.rotation.x, &glm::vec3(1.0, 0.0, 0.0));
-    transform *= glm::translation(&(&root.reference_point * -1.0));
-
-    // Update the node's transformation matrix
-    root.current_transformation_matrix = transform;
-
-    // Recurse
-    for &child in &root.children {
-        update_node_transformations(&mut *child,
-                                    &root.current_transformation_matrix);
-    }
-}
-
-unsafe fn draw_scene(root: &scene_graph::SceneNode, view_projection_matrix: &glm::Mat4) {
-    // Check if node is drawable, set uniforms, draw
-    if root.index_count != -1 {
-        let mut transform: glm::Mat4 = view_projection_matrix * &root.current_transformation_matrix;
-
-        gl::UniformMatrix4fv(3, 1, gl::FALSE, transform.as_ptr());
-        gl::UniformMatrix4fv(4, 1, gl::FALSE, root.current_transformation_matrix.as_ptr());
-        gl::BindVertexArray(root.vao_id);
-        gl::DrawElements(gl::TRIANGLES, 3 * root.index_count, gl::UNSIGNED_INT, ptr::null());
-    }
-    // Recurse
-    for &child in &root.children {
-        draw_scene(&*child, view_projection_matrix);
+fn main() {
+    // Render to PNG files instead of a window if asked to, see HeadlessOptions::from_args for the flags
+    match headless::HeadlessOptions::from_args(std::env::args().skip(1)) {
+        Ok(Some(options)) => {
+            match headless::render_frames(&options) {
+                Ok(frames) => println!("Wrote {} frames to {}", frames.len(), options.output_dir.display()),
+                Err(e) => { eprintln!("{}", e); std::process::exit(1); },
+            }
+            return;
+        },
+        Ok(None) => { },
+        Err(e) => { eprintln!("{}", e); std::process::exit(1); },
     }
-}
 
-fn main() {
     // Set up the necessary objects to deal with windows and event handling
     let el = glutin::event_loop::EventLoop::new();
     let wb = glutin::window::WindowBuilder::new()
@@ -155,67 +61,30 @@ fn main() {
 
         // Set up openGL
         unsafe {
-            gl::Enable(gl::DEPTH_TEST);
-            gl::DepthFunc(gl::LESS);
-            gl::Enable(gl::CULL_FACE);
-            gl::Disable(gl::MULTISAMPLE);
-            gl::Enable(gl::BLEND);
-            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
-            gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
-            gl::DebugMessageCallback(Some(util::debug_callback), ptr::null());
+            render::init_gl_state();
 
             // Print some diagnostics
-            println!("{}: {}", util::get_gl_string(gl::VENDOR), util::get_gl_string(gl::RENDERER));
-            println!("OpenGL\t: {}", util::get_gl_string(gl::VERSION));
-            println!("GLSL\t: {}", util::get_gl_string(gl::SHADING_LANGUAGE_VERSION));
+            render::print_gl_diagnostics();
         }
 
         // == // Set up your VAO here
-        let terrain = mesh::Terrain::load("resources/lunarsurface.obj");
-        let helicopter = mesh::Helicopter::load("resources/helicopter.obj");
         let helicopter_count: usize = 5;
-        let mut vao_indices = Vec::<u32>::new();
-        let mut index_counts = Vec::<i32>::new();
-        unsafe {
-            vao_indices.push(create_mesh_vao(&terrain));
-            index_counts.push(terrain.index_count);
-            for i in 0..4 {
-                vao_indices.push(create_mesh_vao(&helicopter[i]));
-                index_counts.push(helicopter[i].index_count);
-            }
-        }
-
-        // Set up scene graph
-        let mut root_node = scene_graph::SceneNode::new();
-        let mut terrain_node = scene_graph::SceneNode::from_vao(vao_indices[0], index_counts[0]);
-        root_node.add_child(&terrain_node);
-
-        let mut helicopters = Vec::<scene_graph::Node>::new();
-        for i in 0..helicopter_count {
-            let mut body_node = scene_graph::SceneNode::from_vao(vao_indices[1], index_counts[1]);
-            let mut main_rotor_node = scene_graph::SceneNode::from_vao(vao_indices[2], index_counts[2]);
-            let mut tail_rotor_node = scene_graph::SceneNode::from_vao(vao_indices[3], index_counts[3]);
-            let mut door_node = scene_graph::SceneNode::from_vao(vao_indices[4], index_counts[4]);
-            tail_rotor_node.reference_point = glm::vec3(0.35, 2.3, 10.4);
-            main_rotor_node.reference_point = glm::vec3(0.0, 2.2, 0.0);
-            body_node.add_child(&main_rotor_node);
-            body_node.add_child(&tail_rotor_node);
-            body_node.add_child(&door_node);
-
-            terrain_node.add_child(&body_node);
-            helicopters.push(body_node);
-        }
+        let mut scene = unsafe { LunarScene::load(helicopter_count) };
 
 
         // Basic usage of shader helper
-        // The code below returns a shader object, which contains the field .program_id
+        // The code below returns a shader object wrapped in a Result, the shader owns the program behind .program_id()
         // The snippet is not enough to do the assignment, and will need to be modified (outside of just using the correct path), but it only needs to be called once
-        // shader::ShaderBuilder::new().attach_file("./path/to/shader").link();
+        // shader::ShaderBuilder::new().attach_file("./path/to/shader")?.link()?;
         let mut simple_shader: Shader;
         unsafe {
-            simple_shader = shader::ShaderBuilder::new().attach_file("./shaders/simple.vert")
-                .attach_file("./shaders/simple.frag").link();
-            gl::UseProgram(simple_shader.program_id);
+            simple_shader = shader::ShaderBuilder::new()
+                .cache(ProgramCache::new(program_cache::DEFAULT_CACHE_DIR))
+                .attach_file("./shaders/simple.vert")
+                .and_then(|builder| builder.attach_file("./shaders/simple.frag"))
+                .and_then(|builder| builder.link())
+                .unwrap_or_else(|e| panic!("{}", e));
+            simple_shader.activate();
         }
 
         // Used to demonstrate keyboard handling -- feel free to remove
@@ -240,17 +109,10 @@ fn main() {
             let delta_time = now.duration_since(last_frame_time).as_secs_f32();
             last_frame_time = now;
 
-            for i in 0..helicopters.len() {
-                let heading = simple_heading_animation(elapsed + 0.8 * i as f32);
+            // Pick up any edits to the shader sources
+            unsafe { simple_shader.reload_if_changed(); }
 
-                unsafe {
-                    (*helicopters[i].children[0]).rotation += glm::vec3(0.0, 10.0 * delta_time, 0.0);
-                    (*helicopters[i].children[1]).rotation += glm::vec3(10.0 * delta_time, 0.0, 0.0);
-                }
-
-                helicopters[i].position = glm::vec3(heading.x, 1.0, heading.z);
-                helicopters[i].rotation = glm::vec3(heading.yaw, heading.pitch, heading.roll);
-            }
+            scene.animate(elapsed);
 
             // Handle keyboard input
             if let Ok(keys) = pressed_keys.lock() {
@@ -305,19 +167,11 @@ fn main() {
             unsafe {
                 gl::ClearColor(0.163, 0.163, 0.163, 1.0);
                 gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-
-                let mut transform: glm::Mat4 = glm::identity();
-
-                transform *= glm::perspective(1.0, PI / 2.0, 1.0, 1000.0);
-                transform *= glm::translation(&glm::vec3(0.0, 0.0, -1.2));
-                transform *= glm::rotation(ang.y, &glm::vec3(1.0, 0.0, 0.0));
-                transform *= glm::rotation(ang.x, &glm::vec3(0.0, 1.0, 0.0));
-                transform *= glm::translation(&pos);
-
-                update_node_transformations(&mut root_node, &glm::identity());
-                draw_scene(&root_node, &transform);
             }
 
+            let transform = lunar_scene::camera_transform(1.0, &pos, &ang);
+            scene.draw(&transform, &simple_shader);
+
             context.swap_buffers().unwrap();
         }
     });
@@ -326,7 +180,7 @@ fn main() {
     let render_thread_healthy = Arc::new(RwLock::new(true));
     let render_thread_watchdog = Arc::clone(&render_thread_healthy);
     thread::spawn(move || {
-        if !render_thread.join().is_ok() {
+        if render_thread.join().is_err() {
             if let Ok(mut health) = render_thread_watchdog.write() {
                 println!("Render thread panicked!");
                 *health = false;
@@ -340,7 +194,7 @@ fn main() {
 
         // Terminate program if render thread panics
         if let Ok(health) = render_thread_healthy.read() {
-            if *health == false {
+            if !*health {
                 *control_flow = ControlFlow::Exit;
             }
         }
@@ -370,11 +224,8 @@ fn main() {
                 }
 
                 // Handle escape separately
-                match keycode {
-                    Escape => {
-                        *control_flow = ControlFlow::Exit;
-                    },
-                    _ => { }
+                if keycode == Escape {
+                    *control_flow = ControlFlow::Exit;
                 }
             },
             Event::DeviceEvent { event: DeviceEvent::MouseMotion { delta }, .. } => {
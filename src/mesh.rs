@@ -1,92 +1,441 @@
-use tobj;
-
-fn generate_color_vec(color: [f32; 4], num: usize) -> Vec<f32> {
-    color.iter().cloned().cycle().take(num*4).collect()
-}
-
-pub struct Mesh {
-    pub vertices: Vec<f32>,
-    pub normals: Vec<f32>,
-    pub colors: Vec<f32>,
-    pub indices: Vec<u32>,
-    pub index_count: i32,
-}
-
-impl Mesh {
-    pub fn from(mesh: tobj::Mesh, color: [f32; 4]) -> Self {
-        let num_verts = mesh.positions.len() / 3;
-        let index_count = mesh.indices.len() as i32;
-        Mesh {
-            vertices: mesh.positions,
-            normals: mesh.normals,
-            indices: mesh.indices,
-            colors: generate_color_vec(color, num_verts),
-            index_count,
-        }
-    }
-}
-
-pub struct Terrain;
-impl Terrain {
-    pub fn load(path: &str) -> Mesh {
-        println!("Loading terrain model...");
-        let before = std::time::Instant::now();
-        let (models, _materials) = tobj::load_obj(path, true).expect("Failed to load terrain model");
-        let after = std::time::Instant::now();
-        println!("Done in {:.3}ms.", after.duration_since(before).as_micros() as f32 / 1e3);
-        if models.len() > 1 || models.len() == 0 { panic!("Please use a model with a single mesh") }
-
-        let terrain = models[0].to_owned();
-        println!("Loaded {} with {} points and {} triangles.", terrain.name, terrain.mesh.positions.len() /3, terrain.mesh.indices.len() / 3);
-
-        Mesh::from(terrain.mesh, [1.0, 1.0, 1.0, 1.0])
-    }
-}
-
-use std::ops::Index;
-pub struct Helicopter {
-    pub body: Mesh,
-    pub main_rotor: Mesh,
-    pub tail_rotor: Mesh,
-    pub door: Mesh,
-}
-
-// You can use square brackets to access the components of the helicopter, if you want to use loops!
-impl Index<usize> for Helicopter {
-    type Output = Mesh;
-    fn index<'a>(&'a self, i: usize) -> &'a Mesh {
-        match i {
-            0 => &self.body,
-            1 => &self.main_rotor,
-            2 => &self.tail_rotor,
-            3 => &self.door,
-            _ => panic!("Invalid index, try [0,3]"),
-        }
-    }
-}
-
-impl Helicopter {
-    pub fn load(path: &str) -> Self {
-        println!("Loading helicopter model...");
-        let before = std::time::Instant::now();
-        let (models, _materials) = tobj::load_obj(path, true).expect("Failed to load helicopter model");
-        let after = std::time::Instant::now();
-        println!("Done in {:.3}ms.", after.duration_since(before).as_micros() as f32 / 1e3);
-
-        for model in &models {
-            println!("Loaded {} with {} points and {} triangles.", model.name, model.mesh.positions.len() / 3, model.mesh.indices.len() / 3);
-        }
-
-        let body_model = models.iter().find(|m| m.name == "Body_body").expect("Incorrect model file!").to_owned();
-        let main_rotor_model = models.iter().find(|m| m.name == "Main_Rotor_main_rotor").expect("Incorrect model file!").to_owned();
-        let tail_rotor_model = models.iter().find(|m| m.name == "Tail_Rotor_tail_rotor").expect("Incorrect model file!").to_owned();
-        let door_model = models.iter().find(|m| m.name == "Door_door").expect("Incorrect model file!").to_owned();
-
-        Helicopter {
-            body:       Mesh::from(body_model.mesh,         [0.3, 0.3, 0.3, 1.0]),
-            main_rotor: Mesh::from(main_rotor_model.mesh,   [0.3, 0.1, 0.1, 1.0]),
-            tail_rotor: Mesh::from(tail_rotor_model.mesh,   [0.1, 0.3, 0.1, 1.0]),
-            door:       Mesh::from(door_model.mesh,         [0.1, 0.1, 0.3, 1.0]),
-        }
-    }
-}
\ No newline at end of file
+extern crate nalgebra_glm as glm;
+
+use std::{
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::normals::{self, NormalOptions};
+use crate::mesh_cache::{self, MeshCache};
+use crate::optimize::{self, OptimizationReport};
+use crate::ply;
+use crate::simplify::{self, SimplifyOptions};
+use crate::stl;
+use crate::tangents;
+
+fn generate_color_vec(color: [f32; 4], num: usize) -> Vec<f32> {
+    color.iter().cloned().cycle().take(num*4).collect()
+}
+
+// The surface properties of a mesh, as given by an MTL file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pub name: String,
+    pub ambient: [f32; 3],
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+    pub shininess: f32,
+    // Opacity, from 0 for invisible to 1 for opaque
+    pub dissolve: f32,
+    // Texture paths are relative to the working directory, like the model path, and None when the MTL file has none
+    pub ambient_texture: Option<PathBuf>,
+    pub diffuse_texture: Option<PathBuf>,
+    pub specular_texture: Option<PathBuf>,
+    pub normal_texture: Option<PathBuf>,
+}
+
+impl Material {
+    // A material of a single colour, for models that come without one
+    pub fn flat(color: [f32; 4]) -> Material {
+        let diffuse = [color[0], color[1], color[2]];
+        Material {
+            name: String::new(),
+            ambient: diffuse,
+            diffuse,
+            specular: [0.0; 3],
+            shininess: 0.0,
+            dissolve: color[3],
+            ambient_texture: None,
+            diffuse_texture: None,
+            specular_texture: None,
+            normal_texture: None,
+        }
+    }
+
+    // MTL files name textures relative to themselves, which for tobj means relative to the model in `model_dir`
+    pub fn from(material: tobj::Material, model_dir: &Path) -> Material {
+        let texture = |name: String| if name.is_empty() { None } else { Some(model_dir.join(name)) };
+        Material {
+            ambient_texture: texture(material.ambient_texture),
+            diffuse_texture: texture(material.diffuse_texture),
+            specular_texture: texture(material.specular_texture),
+            normal_texture: texture(material.normal_texture),
+            name: material.name,
+            ambient: material.ambient,
+            diffuse: material.diffuse,
+            specular: material.specular,
+            shininess: material.shininess,
+            dissolve: material.dissolve,
+        }
+    }
+
+    // The diffuse colour with the dissolve as alpha, which meshes are coloured with
+    pub fn color(&self) -> [f32; 4] {
+        [self.diffuse[0], self.diffuse[1], self.diffuse[2], self.dissolve]
+    }
+
+    // The material as an MTL file would have it, with texture paths made relative to `mtl_dir` where they can be
+    pub fn to_mtl(&self, mtl_dir: &Path) -> String {
+        let name = if self.name.is_empty() { "default" } else { &self.name };
+        let mut mtl = format!("newmtl {}\n", name);
+        for (keyword, [r, g, b]) in [("Ka", self.ambient), ("Kd", self.diffuse), ("Ks", self.specular)] {
+            writeln!(mtl, "{} {} {} {}", keyword, r, g, b).unwrap();
+        }
+        writeln!(mtl, "Ns {}\nd {}", self.shininess, self.dissolve).unwrap();
+        let textures = [
+            ("map_Ka", &self.ambient_texture),
+            ("map_Kd", &self.diffuse_texture),
+            ("map_Ks", &self.specular_texture),
+            ("map_Bump", &self.normal_texture),
+        ];
+        for (keyword, texture) in textures {
+            if let Some(path) = texture {
+                writeln!(mtl, "{} {}", keyword, path.strip_prefix(mtl_dir).unwrap_or(path).display()).unwrap();
+            }
+        }
+        mtl
+    }
+}
+
+// How PLY and STL files are written
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Ascii,
+    Binary,
+}
+
+pub struct Mesh {
+    pub vertices: Vec<f32>,
+    pub normals: Vec<f32>,
+    // Two per vertex, or empty when the model has no texture coordinates
+    pub uvs: Vec<f32>,
+    // Four per vertex, see GeneratedTangents, or empty until generate_tangents is called
+    pub tangents: Vec<f32>,
+    pub colors: Vec<f32>,
+    pub indices: Vec<u32>,
+    pub index_count: i32,
+    pub material: Option<Material>,
+    // Coarser versions of the mesh from the finest, each with the fraction of the mesh's triangles it was made for,
+    // as made by generate_levels_of_detail or loaded from a mesh cache. UploadedModel::with_levels_of_detail uses
+    // them rather than simplify the mesh itself when they were made for the fractions it asks for.
+    pub levels_of_detail: Vec<(f32, Mesh)>,
+}
+
+impl Mesh {
+    // Takes the mesh's material out of `materials`, as loaded along with it, and colours the mesh with it.
+    // Meshes without a material are given `fallback_color` instead, and meshes without normals get smooth ones.
+    // Tangents are generated for meshes with a normal map.
+    pub fn from(mesh: tobj::Mesh, materials: &[Material], fallback_color: [f32; 4]) -> Self {
+        let num_verts = mesh.positions.len() / 3;
+        let index_count = mesh.indices.len() as i32;
+        let material = mesh.material_id.and_then(|id| materials.get(id)).cloned();
+        let color = material.as_ref().map_or(fallback_color, Material::color);
+        let mut result = Mesh {
+            vertices: mesh.positions,
+            normals: mesh.normals,
+            uvs: mesh.texcoords,
+            tangents: vec![],
+            indices: mesh.indices,
+            colors: generate_color_vec(color, num_verts),
+            index_count,
+            material,
+            levels_of_detail: vec![],
+        };
+        if result.normals.is_empty() && num_verts > 0 {
+            result.generate_normals(&NormalOptions::default());
+        }
+        let normal_mapped = result.material.as_ref().is_some_and(|material| material.normal_texture.is_some());
+        if normal_mapped {
+            if let Err(e) = result.generate_tangents() {
+                println!("WARNING::MESH: the normal map can't be used: {}", e);
+            }
+        }
+        result
+    }
+
+    // Replaces the normals, splitting vertices along hard edges. Any tangents are dropped, as they were made
+    // for the old normals.
+    pub fn generate_normals(&mut self, options: &NormalOptions) {
+        let generated = normals::generate(&self.vertices, &self.indices, options);
+        self.vertices = normals::remap(&self.vertices, 3, &generated.remap);
+        self.colors = normals::remap(&self.colors, 4, &generated.remap);
+        if !self.uvs.is_empty() {
+            self.uvs = normals::remap(&self.uvs, 2, &generated.remap);
+        }
+        self.normals = generated.normals;
+        self.tangents.clear();
+        self.indices = generated.indices;
+    }
+
+    // Replaces the tangents with MikkTSpace ones, which needs normals and texture coordinates
+    pub fn generate_tangents(&mut self) -> Result<(), String> {
+        let generated = tangents::generate(&self.vertices, &self.normals, &self.uvs, &self.indices)?;
+        self.vertices = normals::remap(&self.vertices, 3, &generated.remap);
+        self.normals = normals::remap(&self.normals, 3, &generated.remap);
+        self.uvs = normals::remap(&self.uvs, 2, &generated.remap);
+        self.colors = normals::remap(&self.colors, 4, &generated.remap);
+        self.tangents = generated.tangents;
+        self.indices = generated.indices;
+        Ok(())
+    }
+
+    // A copy of the mesh with at most `target_triangles` triangles, or as few as the options allow
+    pub fn simplified(&self, target_triangles: usize, options: &SimplifyOptions) -> Mesh {
+        let simplified = simplify::simplify(&self.vertices, &self.indices, target_triangles, options);
+        self.with_indices(simplified.indices)
+    }
+
+    // Ever coarser versions of the mesh, one for each triangle count. Each level is simplified from the one before,
+    // and optimized.
+    pub fn lod_chain(&self, triangle_counts: &[usize], options: &SimplifyOptions) -> Vec<Mesh> {
+        let mut levels: Vec<Mesh> = vec![];
+        for &count in triangle_counts {
+            let mut level = levels.last().unwrap_or(self).simplified(count, options);
+            level.optimize();
+            levels.push(level);
+        }
+        levels
+    }
+
+    // Keeps simplified and optimized versions of the mesh with the given fractions of its triangles, see lod_chain
+    pub fn generate_levels_of_detail(&mut self, triangle_ratios: &[f32]) {
+        let triangle_counts: Vec<usize> = triangle_ratios.iter()
+            .map(|ratio| (self.indices.len() as f32 / 3.0 * ratio) as usize)
+            .collect();
+        let levels = self.lod_chain(&triangle_counts, &SimplifyOptions::default());
+        self.levels_of_detail = triangle_ratios.iter().copied().zip(levels).collect();
+    }
+
+    // A copy made of the given triangles, keeping only the vertices they use
+    pub fn with_indices(&self, indices: Vec<u32>) -> Mesh {
+        let (remap, indices) = optimize::optimize_vertex_fetch(&indices, self.vertices.len() / 3);
+        Mesh { index_count: indices.len() as i32, indices, material: self.material.clone(), ..self.remapped(&remap) }
+    }
+
+    // Merges identical vertices and reorders the triangles and vertices to draw faster, see the optimize module
+    pub fn optimize(&mut self) -> OptimizationReport {
+        let vertices_before = self.vertices.len() / 3;
+        let before = optimize::analyze_vertex_cache(&self.indices, vertices_before, optimize::DEFAULT_CACHE_SIZE);
+
+        let streams = [(&self.vertices[..], 3), (&self.normals[..], 3), (&self.uvs[..], 2), (&self.tangents[..], 4), (&self.colors[..], 4)];
+        let (remap, indices) = optimize::deduplicate(&streams, &self.indices);
+        let indices = optimize::optimize_vertex_cache(&indices, remap.len());
+        let vertices = normals::remap(&self.vertices, 3, &remap);
+        let indices = optimize::optimize_overdraw(&vertices, &indices, optimize::DEFAULT_OVERDRAW_THRESHOLD);
+        let (fetch_remap, indices) = optimize::optimize_vertex_fetch(&indices, remap.len());
+        let remap: Vec<u32> = fetch_remap.iter().map(|&vertex| remap[vertex as usize]).collect();
+
+        let material = self.material.take();
+        *self = Mesh { index_count: indices.len() as i32, indices, material, ..self.remapped(&remap) };
+        let vertices_after = self.vertices.len() / 3;
+        OptimizationReport {
+            vertices_before,
+            vertices_after,
+            before,
+            after: optimize::analyze_vertex_cache(&self.indices, vertices_after, optimize::DEFAULT_CACHE_SIZE),
+        }
+    }
+
+    // The vertices listed in `remap`, see normals::remap, with no triangles or material
+    fn remapped(&self, remap: &[u32]) -> Mesh {
+        let attribute = |data: &[f32], components: usize| {
+            if data.is_empty() { vec![] } else { normals::remap(data, components, remap) }
+        };
+        Mesh {
+            vertices: attribute(&self.vertices, 3),
+            normals: attribute(&self.normals, 3),
+            uvs: attribute(&self.uvs, 2),
+            tangents: attribute(&self.tangents, 4),
+            colors: attribute(&self.colors, 4),
+            indices: vec![],
+            index_count: 0,
+            material: None,
+            levels_of_detail: vec![],
+        }
+    }
+
+    // Writes the mesh as an OBJ, PLY or STL file by the extension of `path`, PLY and STL in binary. An OBJ file gets
+    // an MTL file of the same name for the mesh's material, if it has one, and saving fails rather than overwrite an
+    // MTL file that is already there. See the ply and stl modules for ASCII.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        match extension(path).as_deref() {
+            Some("ply") => ply::save(self, path, Encoding::Binary),
+            Some("stl") => stl::save(self, path, Encoding::Binary),
+            Some("obj") => {
+                let error = |e: std::io::Error| format!("Failed to write model {}: {}", path.display(), e);
+                let name = path.file_stem().map_or("mesh".into(), |stem| stem.to_string_lossy());
+                let mtl_path = path.with_extension("mtl");
+                if self.material.is_some() && mtl_path.exists() {
+                    return Err(format!("Failed to write model {}: {} already exists", path.display(), mtl_path.display()));
+                }
+                if let Some(material) = &self.material {
+                    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+                    fs::write(&mtl_path, material.to_mtl(dir)).map_err(error)?;
+                }
+                let mtl_name = mtl_path.file_name().map(|name| name.to_string_lossy());
+                fs::write(path, self.to_obj(&name, mtl_name.as_deref().filter(|_| self.material.is_some()))).map_err(error)
+            }
+            _ => Err(format!("Can't write {}, only .obj, .ply and .stl files", path.display())),
+        }
+    }
+
+    // The mesh as an OBJ object called `name`, using its material from `mtl_file` if given. Vertex colours are left
+    // out, as OBJ has none, so the colour of a mesh without a material is lost.
+    pub fn to_obj(&self, name: &str, mtl_file: Option<&str>) -> String {
+        let mut obj = "# written by gloom-rs\n".to_string();
+        if let Some(mtl_file) = mtl_file {
+            writeln!(obj, "mtllib {}", mtl_file).unwrap();
+        }
+        writeln!(obj, "o {}", name).unwrap();
+        for v in self.vertices.chunks_exact(3) {
+            writeln!(obj, "v {} {} {}", v[0], v[1], v[2]).unwrap();
+        }
+        for vt in self.uvs.chunks_exact(2) {
+            writeln!(obj, "vt {} {}", vt[0], vt[1]).unwrap();
+        }
+        for vn in self.normals.chunks_exact(3) {
+            writeln!(obj, "vn {} {} {}", vn[0], vn[1], vn[2]).unwrap();
+        }
+        if let (Some(material), Some(_)) = (&self.material, mtl_file) {
+            writeln!(obj, "usemtl {}", if material.name.is_empty() { "default" } else { &material.name }).unwrap();
+        }
+        // OBJ counts from 1, and a corner names its texture coordinate and normal after its position
+        let corner = |vertex: u32| {
+            let i = vertex + 1;
+            match (self.uvs.is_empty(), self.normals.is_empty()) {
+                (false, false) => format!("{}/{}/{}", i, i, i),
+                (false, true) => format!("{}/{}", i, i),
+                (true, false) => format!("{}//{}", i, i),
+                (true, true) => i.to_string(),
+            }
+        };
+        for triangle in self.indices.chunks_exact(3) {
+            writeln!(obj, "f {} {} {}", corner(triangle[0]), corner(triangle[1]), corner(triangle[2])).unwrap();
+        }
+        obj
+    }
+
+    // The center and radius of a sphere around every vertex, not the smallest one but close
+    pub fn bounding_sphere(&self) -> (glm::Vec3, f32) {
+        let points = self.vertices.chunks_exact(3).map(glm::make_vec3);
+        let (min, max) = points.clone().fold(
+            (glm::vec3(f32::MAX, f32::MAX, f32::MAX), glm::vec3(f32::MIN, f32::MIN, f32::MIN)),
+            |(min, max), p| (glm::min2(&min, &p), glm::max2(&max, &p)),
+        );
+        if self.vertices.is_empty() {
+            return (glm::zero(), 0.0);
+        }
+        let center = (min + max) / 2.0;
+        let radius = points.map(|p| glm::distance(&p, &center)).fold(0.0, f32::max);
+        (center, radius)
+    }
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension().map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+}
+
+// Loads every model in an OBJ file along with the materials of its MTL files, reporting how long it took
+pub fn load_obj(path: &str) -> Result<(Vec<tobj::Model>, Vec<Material>), String> {
+    println!("Loading model {}...", path);
+    let before = std::time::Instant::now();
+    let (models, materials) = tobj::load_obj(path, true).map_err(|e| format!("Failed to load model {}: {}", path, e))?;
+    let after = std::time::Instant::now();
+    println!("Done in {:.3}ms.", after.duration_since(before).as_micros() as f32 / 1e3);
+
+    let model_dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+    let materials = materials.into_iter().map(|material| Material::from(material, model_dir)).collect();
+    Ok((models, materials))
+}
+
+// A named piece of a model, such as a helicopter's main rotor, which can be posed on its own.
+// An OBJ object that switches material partway through is still one part, with a mesh for each material.
+pub struct ModelPart {
+    pub name: String,
+    pub meshes: Vec<Mesh>,
+}
+
+impl ModelPart {
+    pub fn new(name: &str, mesh: Mesh) -> ModelPart {
+        ModelPart { name: name.to_string(), meshes: vec![mesh] }
+    }
+}
+
+// Every part of a model file, in the order they appear in it
+pub struct Model {
+    pub parts: Vec<ModelPart>,
+    pub materials: Vec<Material>,
+}
+
+impl Model {
+    // Loads the model's mesh cache instead of the model file when it is fresh and was written with the same fallback
+    // colours, see mesh_cache. Otherwise parts without a material are coloured by `fallback_color`, which is given
+    // the name of the part.
+    pub fn load<F: Fn(&str) -> [f32; 4]>(path: &str, fallback_color: F) -> Result<Model, String> {
+        if mesh_cache::is_fresh(path) {
+            let cache_path = mesh_cache::cache_path(path);
+            let before = std::time::Instant::now();
+            match MeshCache::open(&cache_path) {
+                Ok(cache) if !cache.has_fallback_colors(&fallback_color) => {
+                    println!("WARNING::MESH_CACHE: {} was written with other fallback colours, loading {} instead",
+                        cache_path.display(), path);
+                }
+                Ok(cache) => {
+                    let model = cache.model();
+                    let after = std::time::Instant::now();
+                    println!("Loaded {} in {:.3}ms.", cache_path.display(), after.duration_since(before).as_micros() as f32 / 1e3);
+                    return Ok(model);
+                }
+                Err(e) => println!("WARNING::MESH_CACHE: {}, loading {} instead", e, path),
+            }
+        }
+        Model::from_file(path, fallback_color)
+    }
+
+    // Parses an OBJ, PLY or STL file by its extension, ignoring any mesh cache. PLY and STL files hold a single mesh,
+    // which becomes a part named after the file. The meshes keep the order of the file, see Mesh::optimize.
+    pub fn from_file<F: Fn(&str) -> [f32; 4]>(path: &str, fallback_color: F) -> Result<Model, String> {
+        let name = Path::new(path).file_stem().map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+        let mesh = match extension(Path::new(path)).as_deref() {
+            Some("ply") => ply::load(path, fallback_color(&name))?,
+            Some("stl") => stl::load(path, fallback_color(&name))?,
+            _ => return Model::from_obj(path, fallback_color),
+        };
+        println!("Loaded {} with {} points and {} triangles.", path, mesh.vertices.len() / 3, mesh.indices.len() / 3);
+        Ok(Model::from_parts(vec![ModelPart::new(&name, mesh)]))
+    }
+
+    // Parses an OBJ file, ignoring any mesh cache
+    pub fn from_obj<F: Fn(&str) -> [f32; 4]>(path: &str, fallback_color: F) -> Result<Model, String> {
+        let (models, materials) = load_obj(path)?;
+        let mut parts = Vec::<ModelPart>::new();
+        for model in models {
+            println!("Loaded {} with {} points and {} triangles.", model.name, model.mesh.positions.len() / 3, model.mesh.indices.len() / 3);
+            let mesh = Mesh::from(model.mesh, &materials, fallback_color(&model.name));
+            // tobj splits objects at every change of material, under the same name
+            match parts.last_mut() {
+                Some(part) if part.name == model.name => part.meshes.push(mesh),
+                _ => parts.push(ModelPart { name: model.name, meshes: vec![mesh] }),
+            }
+        }
+        if parts.is_empty() {
+            return Err(format!("The model {} has no meshes", path));
+        }
+        Ok(Model { parts, materials })
+    }
+
+    // A model made in code rather than loaded from a file
+    pub fn from_parts(parts: Vec<ModelPart>) -> Model {
+        Model { parts, materials: vec![] }
+    }
+
+    pub fn part(&self, name: &str) -> Option<&ModelPart> {
+        self.parts.iter().find(|part| part.name == name)
+    }
+
+    pub fn part_names(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().map(|part| part.name.as_str())
+    }
+}
@@ -0,0 +1,491 @@
+extern crate nalgebra_glm as glm;
+
+use std::{
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use crate::mesh::{Material, Mesh, Model, ModelPart};
+use crate::optimize::OptimizationReport;
+
+// A binary cache of a loaded model, so start-up doesn't have to parse large OBJ files every time.
+//
+// Model::load uses the cache next to a model file, with the extension .meshcache, whenever it is at least as new
+// as the model file and the material libraries it names, and was written with the fallback colours Model::load is
+// given. Caches are written by `convert`, see also the convert_models binary.
+//
+// Everything is little-endian and four bytes wide, strings being padded to a multiple of four bytes, so the
+// vertex data of a mapped file can be used where it lies:
+//
+//     header    b"GLOOMMSH", version, source count, material count, part count, then the paths of the material
+//               libraries the model was read with, relative to the model file
+//     material  name, ambient [3], diffuse [3], specular [3], shininess, dissolve, then the ambient, diffuse,
+//               specular and normal texture paths
+//     part      name, fallback colour [4], mesh count, then its meshes
+//     mesh      material index (u32::MAX for none), vertex count, index count, attributes, level of detail count,
+//               bounding sphere center [3] and radius, then the positions, the normals, uvs, tangents and colours
+//               it has attributes for, the indices, and finally the levels of detail, each its triangle ratio
+//               followed by a mesh without levels
+//
+// Strings are a byte length followed by UTF-8, and paths that aren't there have the length u32::MAX.
+
+pub const VERSION: u32 = 2;
+
+const MAGIC: &[u8; 8] = b"GLOOMMSH";
+const NONE: u32 = u32::MAX;
+
+// Which attributes a mesh has, besides positions and indices
+const NORMALS: u32 = 1;
+const UVS: u32 = 2;
+const TANGENTS: u32 = 4;
+const COLORS: u32 = 8;
+
+// Where the cache of a model file goes
+pub fn cache_path<P: AsRef<Path>>(model_path: P) -> PathBuf {
+    model_path.as_ref().with_extension("meshcache")
+}
+
+// Whether the model has a readable cache at least as new as itself and every material library it was read with.
+// Equal times count, as some file systems only keep whole seconds.
+pub fn is_fresh<P: AsRef<Path>>(model_path: P) -> bool {
+    let model_path = model_path.as_ref();
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    let cache_path = cache_path(model_path);
+    let (cache, sources) = match (modified(&cache_path), read_sources(&cache_path)) {
+        (Some(cache), Ok(sources)) => (cache, sources),
+        _ => return false,
+    };
+    let model_dir = model_path.parent().unwrap_or_else(|| Path::new(""));
+    std::iter::once(model_path.to_path_buf()).chain(sources.iter().map(|source| model_dir.join(source)))
+        .all(|source| modified(&source).is_some_and(|source| cache >= source))
+}
+
+// Parses a model file, optimizes its meshes, simplifies them to the given fractions of their triangles and writes
+// the result to its cache. Parts without a material are coloured by `fallback_color` as in Model::load, which is
+// baked into the cache. Returns the path of the cache along with what optimizing did to each mesh, by part name.
+pub fn convert<F: Fn(&str) -> [f32; 4]>(model_path: &str, fallback_color: F, triangle_ratios: &[f32])
+    -> Result<(PathBuf, Vec<(String, OptimizationReport)>), String> {
+    let mut model = Model::from_file(model_path, &fallback_color)?;
+    let mut reports = vec![];
+    for part in &mut model.parts {
+        for mesh in &mut part.meshes {
+            reports.push((part.name.clone(), mesh.optimize()));
+            mesh.generate_levels_of_detail(triangle_ratios);
+        }
+    }
+    let path = cache_path(model_path);
+    write(&model, &fallback_color, &material_libraries(Path::new(model_path))?, &path)?;
+    Ok((path, reports))
+}
+
+// The material libraries an OBJ file names, as written in it. tobj reads the first name of each mtllib line.
+fn material_libraries(model_path: &Path) -> Result<Vec<PathBuf>, String> {
+    if model_path.extension().is_none_or(|extension| !extension.eq_ignore_ascii_case("obj")) {
+        return Ok(vec![]);
+    }
+    let text = fs::read_to_string(model_path).map_err(|e| format!("Failed to load model {}: {}", model_path.display(), e))?;
+    Ok(text.lines()
+        .filter_map(|line| line.trim().strip_prefix("mtllib"))
+        .filter_map(|names| names.split_whitespace().next())
+        .map(PathBuf::from)
+        .collect())
+}
+
+// Writes `model` to `path`, recording the fallback colour of each part and the material libraries given by
+// `sources`, relative to the model file, for Model::load to check
+pub fn write<F: Fn(&str) -> [f32; 4], P: AsRef<Path>>(model: &Model, fallback_color: F, sources: &[PathBuf], path: P) -> Result<(), String> {
+    let path = path.as_ref();
+    let error = |e: std::io::Error| format!("Failed to write mesh cache {}: {}", path.display(), e);
+    // Replace the file rather than write over it, as an open MeshCache may have the old one mapped, see MeshCache
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    fs::write(&temporary, encode(model, fallback_color, sources)).map_err(error)?;
+    fs::rename(&temporary, path).map_err(error)
+}
+
+// The bytes of a cache file holding `model`, see `write`
+pub fn encode<F: Fn(&str) -> [f32; 4]>(model: &Model, fallback_color: F, sources: &[PathBuf]) -> Vec<u8> {
+    // Materials of meshes made in code may not be in the model's list
+    let mut materials = model.materials.clone();
+    for material in model.parts.iter().flat_map(|part| &part.meshes).filter_map(|mesh| mesh.material.as_ref()) {
+        if !materials.contains(material) {
+            materials.push(material.clone());
+        }
+    }
+
+    let mut writer = Writer(MAGIC.to_vec());
+    writer.u32(VERSION);
+    writer.u32(sources.len() as u32);
+    writer.u32(materials.len() as u32);
+    writer.u32(model.parts.len() as u32);
+    for source in sources {
+        writer.string(Some(&source.to_string_lossy()));
+    }
+    for material in &materials {
+        writer.string(Some(&material.name));
+        writer.floats(&material.ambient);
+        writer.floats(&material.diffuse);
+        writer.floats(&material.specular);
+        writer.floats(&[material.shininess, material.dissolve]);
+        for texture in &[&material.ambient_texture, &material.diffuse_texture, &material.specular_texture, &material.normal_texture] {
+            writer.string(texture.as_ref().map(|path| path.to_string_lossy()).as_deref());
+        }
+    }
+    for part in &model.parts {
+        writer.string(Some(&part.name));
+        writer.floats(&fallback_color(&part.name));
+        writer.u32(part.meshes.len() as u32);
+        for mesh in &part.meshes {
+            let material = mesh.material.as_ref().and_then(|material| materials.iter().position(|m| m == material));
+            writer.mesh(mesh, material.map_or(NONE, |index| index as u32), &mesh.levels_of_detail);
+        }
+    }
+    writer.0
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn floats(&mut self, values: &[f32]) {
+        for value in values {
+            self.0.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    fn string(&mut self, string: Option<&str>) {
+        match string {
+            Some(string) => {
+                self.u32(string.len() as u32);
+                self.0.extend_from_slice(string.as_bytes());
+                self.0.resize(self.0.len() + padding(string.len()), 0);
+            }
+            None => self.u32(NONE),
+        }
+    }
+
+    fn mesh(&mut self, mesh: &Mesh, material: u32, levels: &[(f32, Mesh)]) {
+        let attributes = [(NORMALS, &mesh.normals), (UVS, &mesh.uvs), (TANGENTS, &mesh.tangents), (COLORS, &mesh.colors)];
+        let present = attributes.iter().filter(|(_, data)| !data.is_empty()).fold(0, |flags, (flag, _)| flags | flag);
+        let (center, radius) = mesh.bounding_sphere();
+        self.u32(material);
+        self.u32(mesh.vertices.len() as u32 / 3);
+        self.u32(mesh.indices.len() as u32);
+        self.u32(present);
+        self.u32(levels.len() as u32);
+        self.floats(center.as_slice());
+        self.floats(&[radius]);
+        self.floats(&mesh.vertices);
+        for (_, data) in &attributes {
+            self.floats(data);
+        }
+        for &index in &mesh.indices {
+            self.u32(index);
+        }
+        for (ratio, level) in levels {
+            self.floats(&[*ratio]);
+            self.mesh(level, material, &[]);
+        }
+    }
+}
+
+fn padding(length: usize) -> usize {
+    (4 - length % 4) % 4
+}
+
+// A cache file mapped into memory, whose meshes can be read in place or copied into a Model.
+//
+// The file must not be changed while it is open, which would change the data under the slices handed out, so
+// `write` only ever replaces caches with a new file.
+pub struct MeshCache {
+    map: memmap2::Mmap,
+    sources: Vec<PathBuf>,
+    materials: Vec<Material>,
+    parts: Vec<PartRecord>,
+}
+
+struct PartRecord {
+    name: String,
+    fallback_color: [f32; 4],
+    meshes: Vec<MeshRecord>,
+}
+
+// Where the data of a mesh is in the file, as byte ranges
+struct MeshRecord {
+    material: Option<usize>,
+    positions: Range<usize>,
+    normals: Range<usize>,
+    uvs: Range<usize>,
+    tangents: Range<usize>,
+    colors: Range<usize>,
+    indices: Range<usize>,
+    bounding_sphere: (glm::Vec3, f32),
+    levels: Vec<(f32, MeshRecord)>,
+}
+
+// A mesh in a mapped cache file. Attributes the mesh doesn't have are empty.
+pub struct MeshView<'a> {
+    pub positions: &'a [f32],
+    pub normals: &'a [f32],
+    pub uvs: &'a [f32],
+    pub tangents: &'a [f32],
+    pub colors: &'a [f32],
+    pub indices: &'a [u32],
+    pub material: Option<&'a Material>,
+    pub bounding_sphere: (glm::Vec3, f32),
+    // From the finest to the coarsest, each with the fraction of the mesh's triangles it was made for
+    pub levels_of_detail: Vec<(f32, MeshView<'a>)>,
+}
+
+impl MeshCache {
+    // Maps the file and checks it is a complete cache of this version, with every index in range
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<MeshCache, String> {
+        let path = path.as_ref();
+        let error = |e: String| format!("Failed to read mesh cache {}: {}", path.display(), e);
+        if cfg!(target_endian = "big") {
+            return Err(error("mesh caches are little-endian and can't be read in place here".to_string()));
+        }
+        let file = fs::File::open(path).map_err(|e| error(e.to_string()))?;
+        // Safety: see the comment on MeshCache about changing the file
+        let map = unsafe { memmap2::Mmap::map(&file) }.map_err(|e| error(e.to_string()))?;
+        let (sources, materials, parts) = Reader { bytes: &map[..], offset: 0 }.contents().map_err(error)?;
+        Ok(MeshCache { map, sources, materials, parts })
+    }
+
+    // The material libraries the model was read with, relative to the model file
+    pub fn sources(&self) -> &[PathBuf] {
+        &self.sources
+    }
+
+    pub fn materials(&self) -> &[Material] {
+        &self.materials
+    }
+
+    pub fn part_names(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().map(|part| part.name.as_str())
+    }
+
+    // Whether every part was written with the colour `fallback_color` gives it
+    pub fn has_fallback_colors<F: Fn(&str) -> [f32; 4]>(&self, fallback_color: F) -> bool {
+        self.parts.iter().all(|part| fallback_color(&part.name) == part.fallback_color)
+    }
+
+    // The meshes of the part at `index`, in the order of the model's parts
+    pub fn meshes(&self, part: usize) -> Vec<MeshView<'_>> {
+        self.parts[part].meshes.iter().map(|record| self.view(record)).collect()
+    }
+
+    // Copies every part out of the file
+    pub fn model(&self) -> Model {
+        let parts = (0..self.parts.len()).map(|part| ModelPart {
+            name: self.parts[part].name.clone(),
+            meshes: self.meshes(part).iter().map(MeshView::to_mesh).collect(),
+        }).collect();
+        Model { parts, materials: self.materials.clone() }
+    }
+
+    fn view(&self, record: &MeshRecord) -> MeshView<'_> {
+        MeshView {
+            positions: self.floats(&record.positions),
+            normals: self.floats(&record.normals),
+            uvs: self.floats(&record.uvs),
+            tangents: self.floats(&record.tangents),
+            colors: self.floats(&record.colors),
+            indices: self.u32s(&record.indices),
+            material: record.material.map(|index| &self.materials[index]),
+            bounding_sphere: record.bounding_sphere,
+            levels_of_detail: record.levels.iter().map(|(ratio, level)| (*ratio, self.view(level))).collect(),
+        }
+    }
+
+    fn floats(&self, range: &Range<usize>) -> &[f32] {
+        let bytes = &self.map[range.clone()];
+        // Safety: the reader made sure the range is in the file and a multiple of four bytes long, every field is
+        // at a multiple of four bytes from the start of the file, and maps start on a page boundary
+        unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const f32, bytes.len() / 4) }
+    }
+
+    fn u32s(&self, range: &Range<usize>) -> &[u32] {
+        let bytes = &self.map[range.clone()];
+        // Safety: as for floats
+        unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const u32, bytes.len() / 4) }
+    }
+}
+
+impl MeshView<'_> {
+    pub fn to_mesh(&self) -> Mesh {
+        Mesh {
+            vertices: self.positions.to_vec(),
+            normals: self.normals.to_vec(),
+            uvs: self.uvs.to_vec(),
+            tangents: self.tangents.to_vec(),
+            colors: self.colors.to_vec(),
+            indices: self.indices.to_vec(),
+            index_count: self.indices.len() as i32,
+            material: self.material.cloned(),
+            levels_of_detail: self.levels_of_detail.iter().map(|(ratio, level)| (*ratio, level.to_mesh())).collect(),
+        }
+    }
+}
+
+type Contents = (Vec<PathBuf>, Vec<Material>, Vec<PartRecord>);
+
+// Reads only as far as the material libraries in the header, see is_fresh
+fn read_sources(path: &Path) -> Result<Vec<PathBuf>, String> {
+    let file = fs::File::open(path).map_err(|e| e.to_string())?;
+    // Safety: see the comment on MeshCache about changing the file
+    let map = unsafe { memmap2::Mmap::map(&file) }.map_err(|e| e.to_string())?;
+    Reader { bytes: &map[..], offset: 0 }.header().map(|(sources, _, _)| sources)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    // The material libraries, and the number of materials and parts
+    fn header(&mut self) -> Result<(Vec<PathBuf>, u32, u32), String> {
+        if self.take(MAGIC.len())? != MAGIC {
+            return Err("not a mesh cache".to_string());
+        }
+        let version = self.u32()?;
+        if version != VERSION {
+            return Err(format!("version {} is not supported, only {}", version, VERSION));
+        }
+        let source_count = self.u32()?;
+        let material_count = self.u32()?;
+        let part_count = self.u32()?;
+        let sources = (0..source_count)
+            .map(|_| self.string()?.map(PathBuf::from).ok_or_else(|| "a material library has no path".to_string()))
+            .collect::<Result<_, _>>()?;
+        Ok((sources, material_count, part_count))
+    }
+
+    fn contents(&mut self) -> Result<Contents, String> {
+        let (sources, material_count, part_count) = self.header()?;
+
+        let mut materials = vec![];
+        for _ in 0..material_count {
+            let name = self.string()?.ok_or("a material has no name")?;
+            let [ambient, diffuse, specular] = [self.vec3()?, self.vec3()?, self.vec3()?];
+            let (shininess, dissolve) = (self.f32()?, self.f32()?);
+            let mut texture = || self.string().map(|path| path.map(PathBuf::from));
+            materials.push(Material {
+                name,
+                ambient,
+                diffuse,
+                specular,
+                shininess,
+                dissolve,
+                ambient_texture: texture()?,
+                diffuse_texture: texture()?,
+                specular_texture: texture()?,
+                normal_texture: texture()?,
+            });
+        }
+
+        let mut parts = vec![];
+        for _ in 0..part_count {
+            let name = self.string()?.ok_or("a part has no name")?;
+            let fallback_color = [self.f32()?, self.f32()?, self.f32()?, self.f32()?];
+            let mesh_count = self.u32()?;
+            let meshes = (0..mesh_count).map(|_| self.mesh(materials.len(), true)).collect::<Result<_, _>>()?;
+            parts.push(PartRecord { name, fallback_color, meshes });
+        }
+        if self.offset != self.bytes.len() {
+            return Err("there is data after the last part".to_string());
+        }
+        Ok((sources, materials, parts))
+    }
+
+    fn mesh(&mut self, material_count: usize, with_levels: bool) -> Result<MeshRecord, String> {
+        let material = match self.u32()? {
+            NONE => None,
+            index if (index as usize) < material_count => Some(index as usize),
+            index => return Err(format!("material {} is out of range", index)),
+        };
+        let vertex_count = self.u32()? as usize;
+        let index_count = self.u32()? as usize;
+        let attributes = self.u32()?;
+        let level_count = self.u32()?;
+        if level_count > 0 && !with_levels {
+            return Err("a level of detail has levels of detail".to_string());
+        }
+        let center = self.vec3()?;
+        let radius = self.f32()?;
+
+        let positions = self.range(vertex_count * 3)?;
+        let mut attribute = |flag: u32, components: usize| {
+            if attributes & flag != 0 { self.range(vertex_count * components) } else { Ok(self.offset..self.offset) }
+        };
+        let normals = attribute(NORMALS, 3)?;
+        let uvs = attribute(UVS, 2)?;
+        let tangents = attribute(TANGENTS, 4)?;
+        let colors = attribute(COLORS, 4)?;
+        let indices = self.range(index_count)?;
+        // Indices past the end would have the GPU read outside the vertex buffers
+        let out_of_range = self.bytes[indices.clone()].chunks_exact(4)
+            .any(|index| u32::from_le_bytes([index[0], index[1], index[2], index[3]]) as usize >= vertex_count);
+        if out_of_range {
+            return Err("a mesh has indices past its last vertex".to_string());
+        }
+
+        let levels = (0..level_count).map(|_| Ok((self.f32()?, self.mesh(material_count, false)?))).collect::<Result<_, String>>()?;
+        Ok(MeshRecord {
+            material,
+            positions,
+            normals,
+            uvs,
+            tangents,
+            colors,
+            indices,
+            bounding_sphere: (glm::make_vec3(&center), radius),
+            levels,
+        })
+    }
+
+    fn take(&mut self, length: usize) -> Result<&[u8], String> {
+        let range = self.range_of_bytes(length)?;
+        Ok(&self.bytes[range])
+    }
+
+    fn range_of_bytes(&mut self, length: usize) -> Result<Range<usize>, String> {
+        let end = self.offset.checked_add(length).filter(|&end| end <= self.bytes.len()).ok_or("the file is truncated")?;
+        let range = self.offset..end;
+        self.offset = end;
+        Ok(range)
+    }
+
+    // The bytes of `count` four byte values
+    fn range(&mut self, count: usize) -> Result<Range<usize>, String> {
+        self.range_of_bytes(count.checked_mul(4).ok_or("the file is truncated")?)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn f32(&mut self) -> Result<f32, String> {
+        self.u32().map(f32::from_bits)
+    }
+
+    fn vec3(&mut self) -> Result<[f32; 3], String> {
+        Ok([self.f32()?, self.f32()?, self.f32()?])
+    }
+
+    fn string(&mut self) -> Result<Option<String>, String> {
+        let length = self.u32()?;
+        if length == NONE {
+            return Ok(None);
+        }
+        let string = String::from_utf8(self.take(length as usize)?.to_vec()).map_err(|_| "a string is not UTF-8")?;
+        self.take(padding(length as usize))?;
+        Ok(Some(string))
+    }
+}
@@ -0,0 +1,140 @@
+extern crate nalgebra_glm as glm;
+
+use std::collections::HashMap;
+
+// Vertex normals for triangle meshes that come without them.
+//
+// Every corner of every triangle gets the weighted average of the normals of the triangles around its position
+// which meet its own triangle at no more than the crease angle. Corners sharing a vertex but ending up with
+// different normals, on either side of a hard edge, split the vertex in two. Vertices at the same position are
+// smoothed across even when they are separate vertices, as at a texture seam.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Weighting {
+    // Every triangle counts the same, which lets finely tessellated areas dominate
+    Uniform,
+    // Larger triangles count more
+    Area,
+    // Triangles count by the angle of their corner at the vertex, which depends least on how the surface is
+    // triangulated
+    Angle,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NormalOptions {
+    // In radians. Triangles meeting at a sharper angle than this are shaded as separate surfaces, so 0 gives
+    // flat shading and PI smooths across every edge.
+    pub crease_angle: f32,
+    pub weighting: Weighting,
+}
+
+impl NormalOptions {
+    pub fn flat() -> NormalOptions {
+        NormalOptions { crease_angle: 0.0, weighting: Weighting::Angle }
+    }
+
+    pub fn smooth(crease_angle: f32) -> NormalOptions {
+        NormalOptions { crease_angle, weighting: Weighting::Angle }
+    }
+
+    pub fn weighting(mut self, weighting: Weighting) -> NormalOptions {
+        self.weighting = weighting;
+        self
+    }
+}
+
+impl Default for NormalOptions {
+    // Smooth, keeping edges sharper than 60 degrees hard
+    fn default() -> NormalOptions {
+        NormalOptions::smooth(std::f32::consts::FRAC_PI_3)
+    }
+}
+
+// The result of generating normals, in which vertices may have been split
+pub struct GeneratedNormals {
+    // Three per vertex
+    pub normals: Vec<f32>,
+    // The vertex of the input each vertex was made from, see remap
+    pub remap: Vec<u32>,
+    pub indices: Vec<u32>,
+}
+
+pub fn generate(positions: &[f32], indices: &[u32], options: &NormalOptions) -> GeneratedNormals {
+    let position = |vertex: u32| glm::make_vec3(&positions[vertex as usize * 3..vertex as usize * 3 + 3]);
+    let triangles: Vec<[u32; 3]> = indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect();
+    // Twice the area in length
+    let face_normals: Vec<glm::Vec3> = triangles.iter()
+        .map(|t| (position(t[1]) - position(t[0])).cross(&(position(t[2]) - position(t[0]))))
+        .collect();
+    let unit_normals: Vec<glm::Vec3> = face_normals.iter()
+        .map(|n| if n.norm() > 0.0 { n.normalize() } else { glm::zero() })
+        .collect();
+
+    // The triangle corners at each distinct position. Adding 0 turns -0 into 0, so the two compare equal.
+    let mut groups = HashMap::<[u32; 3], usize>::new();
+    let mut vertex_groups = vec![usize::MAX; positions.len() / 3];
+    let mut corners_at = Vec::<Vec<(usize, usize)>>::new();
+    for (t, triangle) in triangles.iter().enumerate() {
+        for (k, &vertex) in triangle.iter().enumerate() {
+            let key = [0, 1, 2].map(|i| (positions[vertex as usize * 3 + i] + 0.0).to_bits());
+            let group = *groups.entry(key).or_insert_with(|| {
+                corners_at.push(vec![]);
+                corners_at.len() - 1
+            });
+            vertex_groups[vertex as usize] = group;
+            corners_at[group].push((t, k));
+        }
+    }
+
+    let corner_angle = |t: usize, k: usize| {
+        let triangle = triangles[t];
+        let corner = position(triangle[k]);
+        glm::angle(&(position(triangle[(k + 1) % 3]) - corner), &(position(triangle[(k + 2) % 3]) - corner))
+    };
+    // Compared with a little slack, so faces in the same plane still smooth together with a crease angle of 0
+    let min_cosine = options.crease_angle.cos() - 1e-5;
+
+    let mut normals = vec![];
+    let mut remap = vec![];
+    let mut output_indices = Vec::with_capacity(indices.len());
+    let mut split_vertices = HashMap::<(u32, [u32; 3]), u32>::new();
+    for (t, triangle) in triangles.iter().enumerate() {
+        for &vertex in triangle {
+            let mut normal = glm::Vec3::zeros();
+            for &(other, k) in &corners_at[vertex_groups[vertex as usize]] {
+                if unit_normals[other] == glm::Vec3::zeros() {
+                    continue;
+                }
+                if other != t && unit_normals[t].dot(&unit_normals[other]) < min_cosine {
+                    continue;
+                }
+                normal += match options.weighting {
+                    Weighting::Uniform => unit_normals[other],
+                    Weighting::Area => face_normals[other],
+                    Weighting::Angle => unit_normals[other] * corner_angle(other, k),
+                };
+            }
+            // Degenerate triangles on their own are left with a zero normal
+            let normal = if normal.norm() > 0.0 { normal.normalize() } else { normal };
+
+            let key = (vertex, [normal.x.to_bits(), normal.y.to_bits(), normal.z.to_bits()]);
+            let index = *split_vertices.entry(key).or_insert_with(|| {
+                normals.extend_from_slice(normal.as_slice());
+                remap.push(vertex);
+                remap.len() as u32 - 1
+            });
+            output_indices.push(index);
+        }
+    }
+
+    GeneratedNormals { normals, remap, indices: output_indices }
+}
+
+// Picks out the values of the vertices listed in `remap`, for carrying the other attributes of a mesh over to
+// the vertices of GeneratedNormals
+pub fn remap<T: Copy>(data: &[T], components: usize, remap: &[u32]) -> Vec<T> {
+    remap.iter()
+        .flat_map(|&vertex| &data[vertex as usize * components..(vertex as usize + 1) * components])
+        .copied()
+        .collect()
+}
@@ -0,0 +1,290 @@
+extern crate nalgebra_glm as glm;
+
+use std::{collections::HashMap, fmt};
+
+// Reordering of indexed triangle meshes so the GPU does less work drawing them.
+//
+// Merging identical vertices and ordering triangles for the post-transform vertex cache both cut down on how
+// often the vertex shader runs, ordering triangles front to back cuts down on shaded pixels that end up hidden,
+// and ordering vertices by first use makes fetching them more cache friendly. None of it changes what is drawn,
+// except where triangles overlap at exactly the same depth.
+//
+// How well the vertex cache is used is measured with a simulated FIFO cache, as the average number of vertices
+// transformed per triangle (ACMR, between 0.5 for an ideal large grid and 3) and per vertex (ATVR, 1 at best).
+
+// The size of the FIFO cache `analyze_vertex_cache` is usually asked to simulate, about what GPUs have had
+pub const DEFAULT_CACHE_SIZE: usize = 16;
+
+// How much worse the vertex cache may get, as a factor of the ACMR, for optimize_overdraw to split the mesh into
+// more clusters
+pub const DEFAULT_OVERDRAW_THRESHOLD: f32 = 1.05;
+
+// The size of the LRU cache the vertex cache optimization scores vertices by
+const SCORED_CACHE_SIZE: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CacheStatistics {
+    // Average cache miss ratio, the vertices transformed per triangle
+    pub acmr: f32,
+    // Average transformed vertex ratio, the vertices transformed per vertex
+    pub atvr: f32,
+}
+
+pub fn analyze_vertex_cache(indices: &[u32], vertex_count: usize, cache_size: usize) -> CacheStatistics {
+    let mut cache = FifoCache::new(vertex_count, cache_size);
+    let misses: usize = indices.iter().map(|&vertex| cache.touch(vertex) as usize).sum();
+    let triangle_count = indices.len() / 3;
+    CacheStatistics {
+        acmr: if triangle_count == 0 { 0.0 } else { misses as f32 / triangle_count as f32 },
+        atvr: if vertex_count == 0 { 0.0 } else { misses as f32 / vertex_count as f32 },
+    }
+}
+
+// What Mesh::optimize did to a mesh
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OptimizationReport {
+    pub vertices_before: usize,
+    pub vertices_after: usize,
+    pub before: CacheStatistics,
+    pub after: CacheStatistics,
+}
+
+impl fmt::Display for OptimizationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} vertices to {}, ACMR {:.3} to {:.3}, ATVR {:.3} to {:.3}", self.vertices_before,
+            self.vertices_after, self.before.acmr, self.after.acmr, self.before.atvr, self.after.atvr)
+    }
+}
+
+// Merges vertices that are the same in every attribute. `streams` are the attributes of the vertices along with
+// their number of components, empty ones being skipped. Returns a remap, see normals::remap, and the new indices.
+pub fn deduplicate(streams: &[(&[f32], usize)], indices: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    let vertex_count = streams.iter().find(|(data, _)| !data.is_empty()).map_or(0, |(data, components)| data.len() / components);
+    let mut remap = vec![];
+    let mut new_indices = Vec::with_capacity(vertex_count);
+    let mut unique = HashMap::<Vec<u32>, u32>::new();
+    for vertex in 0..vertex_count {
+        // Adding 0 turns -0 into 0, so the two compare equal
+        let key: Vec<u32> = streams.iter()
+            .filter(|(data, _)| !data.is_empty())
+            .flat_map(|&(data, components)| data[vertex * components..(vertex + 1) * components].iter().map(|x| (x + 0.0).to_bits()))
+            .collect();
+        let index = *unique.entry(key).or_insert_with(|| {
+            remap.push(vertex as u32);
+            remap.len() as u32 - 1
+        });
+        new_indices.push(index);
+    }
+    (remap, indices.iter().map(|&vertex| new_indices[vertex as usize]).collect())
+}
+
+// Reorders the triangles so consecutive triangles share vertices, with Tom Forsyth's linear-speed vertex cache
+// optimisation. Each triangle keeps its winding.
+pub fn optimize_vertex_cache(indices: &[u32], vertex_count: usize) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+
+    // The triangles not yet emitted around each vertex
+    let mut offsets = vec![0; vertex_count + 1];
+    for &vertex in indices {
+        offsets[vertex as usize + 1] += 1;
+    }
+    for v in 0..vertex_count {
+        offsets[v + 1] += offsets[v];
+    }
+    let mut remaining = vec![0; vertex_count];
+    let mut adjacent = vec![0; indices.len()];
+    for (corner, &vertex) in indices.iter().enumerate() {
+        let v = vertex as usize;
+        adjacent[offsets[v] + remaining[v]] = (corner / 3) as u32;
+        remaining[v] += 1;
+    }
+
+    let mut cache_position = vec![None; vertex_count];
+    let mut vertex_scores: Vec<f32> = remaining.iter().map(|&valence| vertex_score(None, valence)).collect();
+    let mut triangle_scores: Vec<f32> = indices.chunks_exact(3)
+        .map(|t| t.iter().map(|&v| vertex_scores[v as usize]).sum())
+        .collect();
+    let mut emitted = vec![false; triangle_count];
+    let mut cache = Vec::<u32>::with_capacity(SCORED_CACHE_SIZE + 3);
+    let mut output = Vec::with_capacity(indices.len());
+    let mut next_unemitted = 0;
+    let mut best = None;
+
+    for _ in 0..triangle_count {
+        let triangle = match best {
+            Some(triangle) => triangle,
+            // Nothing around the cached vertices is left, so carry on with the first triangle not yet emitted
+            None => {
+                while emitted[next_unemitted] {
+                    next_unemitted += 1;
+                }
+                next_unemitted
+            }
+        };
+        emitted[triangle] = true;
+        let corners = &indices[triangle * 3..triangle * 3 + 3];
+        output.extend_from_slice(corners);
+
+        for &vertex in corners {
+            let v = vertex as usize;
+            let around = &mut adjacent[offsets[v]..offsets[v] + remaining[v]];
+            let position = around.iter().position(|&t| t as usize == triangle).unwrap();
+            around.swap(position, remaining[v] - 1);
+            remaining[v] -= 1;
+        }
+
+        // The triangle's vertices go to the front of the cache, pushing the others back and maybe out
+        let mut new_cache: Vec<u32> = corners.to_vec();
+        new_cache.extend(cache.iter().filter(|v| !corners.contains(v)));
+        for (position, &vertex) in new_cache.iter().enumerate() {
+            cache_position[vertex as usize] = if position < SCORED_CACHE_SIZE { Some(position) } else { None };
+        }
+        cache = new_cache;
+
+        best = None;
+        let mut best_score = f32::MIN;
+        for &vertex in &cache {
+            let v = vertex as usize;
+            let score = vertex_score(cache_position[v], remaining[v]);
+            let change = score - vertex_scores[v];
+            vertex_scores[v] = score;
+            for &t in &adjacent[offsets[v]..offsets[v] + remaining[v]] {
+                let t = t as usize;
+                triangle_scores[t] += change;
+                if triangle_scores[t] > best_score {
+                    best_score = triangle_scores[t];
+                    best = Some(t);
+                }
+            }
+        }
+        cache.truncate(SCORED_CACHE_SIZE);
+    }
+    output
+}
+
+// Forsyth's score for a vertex, higher for vertices recently used and for those with few triangles left, so
+// isolated triangles don't get left behind
+fn vertex_score(cache_position: Option<usize>, remaining_triangles: usize) -> f32 {
+    if remaining_triangles == 0 {
+        return -1.0;
+    }
+    let cache_score = match cache_position {
+        None => 0.0,
+        // The vertices of the triangle just emitted are scored the same, so the next one can go either way
+        Some(position) if position < 3 => 0.75,
+        Some(position) => (1.0 - (position - 3) as f32 / (SCORED_CACHE_SIZE - 3) as f32).powf(1.5),
+    };
+    cache_score + 2.0 * (remaining_triangles as f32).powf(-0.5)
+}
+
+// Reorders clusters of triangles so the ones facing out of the mesh come first, and so tend to hide the others
+// behind them, after Sander, Nehab and Barczak's "Fast triangle reordering for vertex locality and reduced
+// overdraw". Meant to follow optimize_vertex_cache, whose order is kept within each cluster. Clusters start where
+// the cache was missed entirely and, as long as the ACMR stays within `threshold` times what it was, more often.
+pub fn optimize_overdraw(positions: &[f32], indices: &[u32], threshold: f32) -> Vec<u32> {
+    let vertex_count = positions.len() / 3;
+    let triangle_count = indices.len() / 3;
+    if triangle_count == 0 {
+        return vec![];
+    }
+
+    let mut hard_boundaries = vec![];
+    let mut cache = FifoCache::new(vertex_count, DEFAULT_CACHE_SIZE);
+    for (t, triangle) in indices.chunks_exact(3).enumerate() {
+        if triangle.iter().map(|&v| cache.touch(v) as usize).sum::<usize>() == 3 {
+            hard_boundaries.push(t);
+        }
+    }
+    hard_boundaries.push(triangle_count);
+    if hard_boundaries[0] != 0 {
+        hard_boundaries.insert(0, 0);
+    }
+
+    let mut clusters = vec![];
+    for range in hard_boundaries.windows(2) {
+        let (start, end) = (range[0], range[1]);
+        let cluster = &indices[start * 3..end * 3];
+        let allowed_acmr = analyze_vertex_cache(cluster, vertex_count, DEFAULT_CACHE_SIZE).acmr * threshold;
+        let mut cache = FifoCache::new(vertex_count, DEFAULT_CACHE_SIZE);
+        let (mut cluster_start, mut misses) = (start, 0);
+        for t in start..end {
+            misses += indices[t * 3..t * 3 + 3].iter().map(|&v| cache.touch(v) as usize).sum::<usize>();
+            if t + 1 < end && misses as f32 / (t + 1 - cluster_start) as f32 <= allowed_acmr {
+                clusters.push(cluster_start..t + 1);
+                cache = FifoCache::new(vertex_count, DEFAULT_CACHE_SIZE);
+                cluster_start = t + 1;
+                misses = 0;
+            }
+        }
+        clusters.push(cluster_start..end);
+    }
+
+    // Each cluster is sorted by how far it is in front of the middle of the mesh, along its average normal
+    let position = |vertex: u32| glm::make_vec3(&positions[vertex as usize * 3..vertex as usize * 3 + 3]);
+    let centroid = |triangles: &[u32]| {
+        let mut weighted = glm::Vec3::zeros();
+        let mut normal = glm::Vec3::zeros();
+        let mut area = 0.0;
+        for t in triangles.chunks_exact(3) {
+            let (a, b, c) = (position(t[0]), position(t[1]), position(t[2]));
+            // Twice the area in length
+            let n = (b - a).cross(&(c - a));
+            weighted += (a + b + c) / 3.0 * n.norm();
+            area += n.norm();
+            normal += n;
+        }
+        let center = if area > 0.0 { weighted / area } else { glm::zero() };
+        (center, normal)
+    };
+    let (mesh_center, _) = centroid(indices);
+    let mut sort_keys: Vec<(f32, std::ops::Range<usize>)> = clusters.into_iter().map(|cluster| {
+        let (center, normal) = centroid(&indices[cluster.start * 3..cluster.end * 3]);
+        let normal = if normal.norm() > 0.0 { normal.normalize() } else { normal };
+        ((center - mesh_center).dot(&normal), cluster)
+    }).collect();
+    sort_keys.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+
+    sort_keys.into_iter().flat_map(|(_, cluster)| indices[cluster.start * 3..cluster.end * 3].iter().copied()).collect()
+}
+
+// Renumbers the vertices in the order the triangles first use them, dropping unused ones. Returns a remap, see
+// normals::remap, and the new indices.
+pub fn optimize_vertex_fetch(indices: &[u32], vertex_count: usize) -> (Vec<u32>, Vec<u32>) {
+    let mut new_indices = vec![u32::MAX; vertex_count];
+    let mut remap = vec![];
+    let indices = indices.iter().map(|&vertex| {
+        let new_index = &mut new_indices[vertex as usize];
+        if *new_index == u32::MAX {
+            *new_index = remap.len() as u32;
+            remap.push(vertex);
+        }
+        *new_index
+    }).collect();
+    (remap, indices)
+}
+
+// A FIFO vertex cache, which only notes when vertices were added to it
+struct FifoCache {
+    added: Vec<usize>,
+    time: usize,
+    size: usize,
+}
+
+impl FifoCache {
+    fn new(vertex_count: usize, size: usize) -> FifoCache {
+        // Starting the clock past the cache size makes every vertex miss at first
+        FifoCache { added: vec![0; vertex_count], time: size + 1, size }
+    }
+
+    // Uses a vertex, returning whether it had to be transformed
+    fn touch(&mut self, vertex: u32) -> bool {
+        let added = &mut self.added[vertex as usize];
+        if self.time - *added > self.size {
+            *added = self.time;
+            self.time += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
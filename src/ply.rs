@@ -0,0 +1,364 @@
+use std::{fs, path::Path};
+
+use crate::mesh::{Encoding, Mesh};
+use crate::normals::NormalOptions;
+
+// Stanford PLY meshes, as 3D scanners and photogrammetry tools write them, in ASCII or binary of either byte order.
+//
+// Vertices are read from the `vertex` element, with positions in x, y and z, and optionally normals in nx, ny and
+// nz, texture coordinates in s and t (or u and v) and colours in red, green, blue and alpha. Integer colours are
+// taken as fractions of their largest value. Polygons in the `face` element are split into triangle fans, and
+// every other element is skipped. Meshes without normals get smooth ones.
+
+pub fn load<P: AsRef<Path>>(path: P, fallback_color: [f32; 4]) -> Result<Mesh, String> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).map_err(|e| format!("Failed to load PLY file {}: {}", path.display(), e))?;
+    parse(&bytes, fallback_color).map_err(|e| format!("Failed to load PLY file {}: {}", path.display(), e))
+}
+
+// Vertices without a colour are given `fallback_color`
+pub fn parse(bytes: &[u8], fallback_color: [f32; 4]) -> Result<Mesh, String> {
+    let (header, body_start) = Header::parse(bytes)?;
+    let body = &bytes[body_start..];
+    let mut values = match header.format {
+        Format::Ascii => Values::Ascii(std::str::from_utf8(body).map_err(|_| "the body is not text")?.split_ascii_whitespace()),
+        Format::BinaryLittleEndian => Values::Binary { bytes: body, offset: 0, little_endian: true },
+        Format::BinaryBigEndian => Values::Binary { bytes: body, offset: 0, little_endian: false },
+    };
+
+    let mut vertices = vec![];
+    let mut normals = vec![];
+    let mut uvs = vec![];
+    let mut colors = vec![];
+    let mut indices = vec![];
+    let mut vertex_count = 0;
+    for element in &header.elements {
+        match element.name.as_str() {
+            "vertex" => {
+                vertex_count = element.count;
+                let slot = |names: &[&str]| element.properties.iter().position(|p| names.contains(&p.name.as_str()));
+                let position = [slot(&["x"]), slot(&["y"]), slot(&["z"])];
+                let normal = [slot(&["nx"]), slot(&["ny"]), slot(&["nz"])];
+                let uv = [slot(&["s", "u", "texture_s", "texture_u"]), slot(&["t", "v", "texture_t", "texture_v"])];
+                let color = [slot(&["red", "r", "diffuse_red"]), slot(&["green", "g", "diffuse_green"]), slot(&["blue", "b", "diffuse_blue"]), slot(&["alpha", "a"])];
+                if position.contains(&None) {
+                    return Err("the vertices have no x, y and z".to_string());
+                }
+                let has_normals = !normal.contains(&None);
+                let has_uvs = !uv.contains(&None);
+                let has_colors = !color[..3].contains(&None);
+
+                let mut row = vec![0.0; element.properties.len()];
+                for _ in 0..element.count {
+                    for (value, property) in row.iter_mut().zip(&element.properties) {
+                        *value = match property.kind {
+                            Kind::Scalar(ty) => values.read(ty)?,
+                            Kind::List(..) => {
+                                values.skip_list(&property.kind)?;
+                                0.0
+                            }
+                        };
+                    }
+                    let value = |slot: Option<usize>| row[slot.unwrap()] as f32;
+                    vertices.extend(position.iter().map(|&slot| value(slot)));
+                    if has_normals {
+                        normals.extend(normal.iter().map(|&slot| value(slot)));
+                    }
+                    if has_uvs {
+                        uvs.extend(uv.iter().map(|&slot| value(slot)));
+                    }
+                    if has_colors {
+                        // Colours without alpha are opaque
+                        colors.extend(color.iter().map(|&slot| {
+                            slot.map_or(1.0, |slot| element.properties[slot].kind.normalize(row[slot]))
+                        }));
+                    }
+                }
+            }
+            "face" => {
+                let polygon = element.properties.iter()
+                    .position(|p| p.name == "vertex_indices" || p.name == "vertex_index")
+                    .ok_or("the faces have no vertex_indices")?;
+                for _ in 0..element.count {
+                    for (i, property) in element.properties.iter().enumerate() {
+                        match property.kind {
+                            Kind::List(count_type, index_type) if i == polygon => {
+                                let count = values.read(count_type)? as usize;
+                                let corners = (0..count).map(|_| values.read(index_type).map(|index| index as u32)).collect::<Result<Vec<u32>, String>>()?;
+                                for k in 2..corners.len() {
+                                    indices.extend_from_slice(&[corners[0], corners[k - 1], corners[k]]);
+                                }
+                            }
+                            Kind::List(..) => values.skip_list(&property.kind)?,
+                            Kind::Scalar(ty) => {
+                                values.read(ty)?;
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {
+                for _ in 0..element.count {
+                    for property in &element.properties {
+                        match property.kind {
+                            Kind::Scalar(ty) => {
+                                values.read(ty)?;
+                            }
+                            Kind::List(..) => values.skip_list(&property.kind)?,
+                        }
+                    }
+                }
+            }
+        }
+    }
+    if let Some(index) = indices.iter().find(|&&index| index as usize >= vertex_count) {
+        return Err(format!("face index {} is past the last of {} vertices", index, vertex_count));
+    }
+
+    let mut mesh = Mesh {
+        colors: if colors.is_empty() { fallback_color.repeat(vertex_count) } else { colors },
+        vertices,
+        normals,
+        uvs,
+        tangents: vec![],
+        index_count: indices.len() as i32,
+        indices,
+        material: None,
+        levels_of_detail: vec![],
+    };
+    if mesh.normals.is_empty() && vertex_count > 0 {
+        mesh.generate_normals(&NormalOptions::default());
+    }
+    Ok(mesh)
+}
+
+pub fn save<P: AsRef<Path>>(mesh: &Mesh, path: P, encoding: Encoding) -> Result<(), String> {
+    let path = path.as_ref();
+    fs::write(path, encode(mesh, encoding)).map_err(|e| format!("Failed to write PLY file {}: {}", path.display(), e))
+}
+
+// Writes the positions along with whichever normals, texture coordinates and colours the mesh has. Colours are
+// stored as bytes, binary files are little-endian.
+pub fn encode(mesh: &Mesh, encoding: Encoding) -> Vec<u8> {
+    let vertex_count = mesh.vertices.len() / 3;
+    let format = match encoding {
+        Encoding::Ascii => "ascii",
+        Encoding::Binary => "binary_little_endian",
+    };
+    let mut header = format!("ply\nformat {} 1.0\ncomment written by gloom-rs\nelement vertex {}\n", format, vertex_count);
+    header += "property float x\nproperty float y\nproperty float z\n";
+    let has_normals = !mesh.normals.is_empty();
+    let has_uvs = !mesh.uvs.is_empty();
+    let has_colors = !mesh.colors.is_empty();
+    if has_normals {
+        header += "property float nx\nproperty float ny\nproperty float nz\n";
+    }
+    if has_uvs {
+        header += "property float s\nproperty float t\n";
+    }
+    if has_colors {
+        header += "property uchar red\nproperty uchar green\nproperty uchar blue\nproperty uchar alpha\n";
+    }
+    header += &format!("element face {}\nproperty list uchar uint vertex_indices\nend_header\n", mesh.indices.len() / 3);
+
+    let mut bytes = header.into_bytes();
+    for v in 0..vertex_count {
+        let mut floats = mesh.vertices[v * 3..v * 3 + 3].to_vec();
+        if has_normals {
+            floats.extend_from_slice(&mesh.normals[v * 3..v * 3 + 3]);
+        }
+        if has_uvs {
+            floats.extend_from_slice(&mesh.uvs[v * 2..v * 2 + 2]);
+        }
+        let color: Vec<u8> = if has_colors {
+            mesh.colors[v * 4..v * 4 + 4].iter().map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8).collect()
+        } else {
+            vec![]
+        };
+        match encoding {
+            Encoding::Ascii => {
+                let fields: Vec<String> = floats.iter().map(f32::to_string).chain(color.iter().map(u8::to_string)).collect();
+                bytes.extend_from_slice(fields.join(" ").as_bytes());
+                bytes.push(b'\n');
+            }
+            Encoding::Binary => {
+                bytes.extend(floats.iter().flat_map(|x| x.to_le_bytes()));
+                bytes.extend_from_slice(&color);
+            }
+        }
+    }
+    for triangle in mesh.indices.chunks_exact(3) {
+        match encoding {
+            Encoding::Ascii => bytes.extend_from_slice(format!("3 {} {} {}\n", triangle[0], triangle[1], triangle[2]).as_bytes()),
+            Encoding::Binary => {
+                bytes.push(3);
+                bytes.extend(triangle.iter().flat_map(|index| index.to_le_bytes()));
+            }
+        }
+    }
+    bytes
+}
+
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Scalar, String> {
+        Ok(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return Err(format!("unknown property type {}", name)),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+}
+
+enum Kind {
+    Scalar(Scalar),
+    // The type of the count, then of the items
+    List(Scalar, Scalar),
+}
+
+impl Kind {
+    // Colours of integer types go from 0 to their largest value
+    fn normalize(&self, value: f64) -> f32 {
+        let max = match self {
+            Kind::Scalar(Scalar::U8) => 255.0,
+            Kind::Scalar(Scalar::U16) => 65535.0,
+            Kind::Scalar(Scalar::U32) => u32::MAX as f64,
+            _ => 1.0,
+        };
+        (value / max) as f32
+    }
+}
+
+struct Property {
+    name: String,
+    kind: Kind,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+struct Header {
+    format: Format,
+    elements: Vec<Element>,
+}
+
+impl Header {
+    // Returns the header and where the body starts
+    fn parse(bytes: &[u8]) -> Result<(Header, usize), String> {
+        if !bytes.starts_with(b"ply") {
+            return Err("not a PLY file".to_string());
+        }
+        let end = bytes.windows(10).position(|window| window == b"end_header").ok_or("the header has no end_header")?;
+        let newline = bytes[end..].iter().position(|&b| b == b'\n').ok_or("the header has no end_header")?;
+        let text = std::str::from_utf8(&bytes[..end]).map_err(|_| "the header is not text")?;
+
+        let mut format = None;
+        let mut elements = Vec::<Element>::new();
+        for line in text.lines().skip(1) {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["format", "ascii", _] => format = Some(Format::Ascii),
+                ["format", "binary_little_endian", _] => format = Some(Format::BinaryLittleEndian),
+                ["format", "binary_big_endian", _] => format = Some(Format::BinaryBigEndian),
+                ["element", name, count] => elements.push(Element {
+                    name: name.to_string(),
+                    count: count.parse().map_err(|_| format!("element {} has no count", name))?,
+                    properties: vec![],
+                }),
+                ["property", "list", count_type, item_type, name] => {
+                    let element = elements.last_mut().ok_or("a property comes before any element")?;
+                    let kind = Kind::List(Scalar::parse(count_type)?, Scalar::parse(item_type)?);
+                    element.properties.push(Property { name: name.to_string(), kind });
+                }
+                ["property", ty, name] => {
+                    let element = elements.last_mut().ok_or("a property comes before any element")?;
+                    element.properties.push(Property { name: name.to_string(), kind: Kind::Scalar(Scalar::parse(ty)?) });
+                }
+                ["comment", ..] | ["obj_info", ..] | [] => {}
+                _ => return Err(format!("the header line \"{}\" is not understood", line)),
+            }
+        }
+        let format = format.ok_or("the header has no format")?;
+        Ok((Header { format, elements }, end + newline + 1))
+    }
+}
+
+enum Values<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary { bytes: &'a [u8], offset: usize, little_endian: bool },
+}
+
+impl Values<'_> {
+    fn read(&mut self, ty: Scalar) -> Result<f64, String> {
+        match self {
+            Values::Ascii(words) => {
+                let word = words.next().ok_or("the file is truncated")?;
+                word.parse::<f64>().map_err(|_| format!("{} is not a number", word))
+            }
+            Values::Binary { bytes, offset, little_endian } => {
+                let value = bytes.get(*offset..*offset + ty.size()).ok_or("the file is truncated")?;
+                *offset += ty.size();
+                let mut buffer = [0; 8];
+                buffer[..value.len()].copy_from_slice(value);
+                if !*little_endian {
+                    buffer[..value.len()].reverse();
+                }
+                Ok(match ty {
+                    Scalar::I8 => buffer[0] as i8 as f64,
+                    Scalar::U8 => buffer[0] as f64,
+                    Scalar::I16 => i16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+                    Scalar::U16 => u16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+                    Scalar::I32 => i32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64,
+                    Scalar::U32 => u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64,
+                    Scalar::F32 => f32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64,
+                    Scalar::F64 => f64::from_le_bytes(buffer),
+                })
+            }
+        }
+    }
+
+    fn skip_list(&mut self, kind: &Kind) -> Result<(), String> {
+        if let Kind::List(count_type, item_type) = *kind {
+            let count = self.read(count_type)? as usize;
+            for _ in 0..count {
+                self.read(item_type)?;
+            }
+        }
+        Ok(())
+    }
+}
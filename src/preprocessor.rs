@@ -0,0 +1,184 @@
+use std::path::{Path, PathBuf};
+
+use crate::shader::ShaderError;
+
+// GLSL source after `#include`s are expanded and `#define`s injected, ready for glShaderSource.
+// `files` lists every file which went into the source, in the order they were first read. A file's index
+// in this list is the source string number used in the `#line` directives, and therefore in error logs.
+pub struct PreprocessedSource {
+    pub source: String,
+    pub files: Vec<PathBuf>,
+}
+
+impl PreprocessedSource {
+    // Explains which file each source string number in a compiler log refers to
+    pub fn source_string_legend(&self) -> String {
+        self.files.iter().enumerate()
+            .map(|(i, file)| format!("  {} = {}", i, file.display()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+// Reads and preprocesses the shader at `path`, see preprocess_source
+pub fn preprocess_file(path: &Path, defines: &[(String, String)]) -> Result<PreprocessedSource, ShaderError> {
+    let source = read(path)?;
+    preprocess_source(&source, Some(path), defines)
+}
+
+// Expands `#include "file.glsl"` directives, with paths relative to the including file (or the working
+// directory for sources without a file), and inserts a `#define NAME VALUE` for each entry of `defines` right
+// after the `#version` line. `#line` directives keep the line numbers in compiler errors pointing at the
+// original files. Circular includes are an error; including the same file twice elsewhere is not.
+pub fn preprocess_source(source: &str, origin: Option<&Path>, defines: &[(String, String)]) -> Result<PreprocessedSource, ShaderError> {
+    let mut preprocessor = Preprocessor { output: String::new(), files: vec![], stack: vec![] };
+    let origin = origin.map(Path::to_path_buf).unwrap_or_else(|| PathBuf::from("<source>"));
+    preprocessor.files.push(origin.clone());
+
+    let mut lines = source.lines().enumerate().peekable();
+    // The #version directive has to stay in front of everything else, defines included
+    let mut next_line = 1;
+    while let Some((_, line)) = lines.peek() {
+        let trimmed = line.trim_start();
+        let is_preamble = trimmed.is_empty() || trimmed.starts_with("//");
+        if !is_preamble && !trimmed.starts_with("#version") {
+            break;
+        }
+        preprocessor.output.push_str(line);
+        preprocessor.output.push('\n');
+        lines.next();
+        next_line += 1;
+        if trimmed.starts_with("#version") {
+            break;
+        }
+    }
+    for (name, value) in defines {
+        preprocessor.output.push_str(&format!("#define {} {}\n", name, value));
+    }
+    preprocessor.output.push_str(&format!("#line {} 0\n", next_line));
+
+    preprocessor.stack.push(canonical(&origin));
+    let lines: Vec<(usize, &str)> = lines.map(|(i, line)| (i + 1, line)).collect();
+    preprocessor.expand(&lines, &origin, 0)?;
+
+    Ok(PreprocessedSource { source: preprocessor.output, files: preprocessor.files })
+}
+
+struct Preprocessor {
+    output: String,
+    files: Vec<PathBuf>,
+    // The chain of files currently being included, used to detect cycles
+    stack: Vec<PathBuf>,
+}
+
+impl Preprocessor {
+    // Appends `lines`, given as (line number, text) pairs, to the output with their includes expanded. Includes
+    // inside block comments and `#if 0` regions are left alone; other conditions are up to the compiler, so the
+    // includes they guard are always expanded.
+    fn expand(&mut self, lines: &[(usize, &str)], file: &Path, file_index: usize) -> Result<(), ShaderError> {
+        let mut in_comment = false;
+        let mut disabled = Disabled::default();
+        for &(line_number, line) in lines {
+            let trimmed = line.trim_start();
+            let is_directive = !in_comment && trimmed.starts_with('#');
+            in_comment = ends_in_comment(line, in_comment);
+            if is_directive {
+                disabled.directive(trimmed);
+            }
+            if !is_directive || disabled.is_disabled() || !trimmed.starts_with("#include") {
+                self.output.push_str(line);
+                self.output.push('\n');
+                continue;
+            }
+
+            let include_error = |message: String| ShaderError::Include { file: file.to_path_buf(), line: line_number, message };
+            let argument = trimmed["#include".len()..].trim();
+            let name = argument.strip_prefix('"').and_then(|rest| rest.strip_suffix('"'))
+                .ok_or_else(|| include_error(format!("expected #include \"file\", found {}", trimmed)))?;
+
+            let included = file.parent().unwrap_or_else(|| Path::new("")).join(name);
+            let canonical_path = canonical(&included);
+            if self.stack.contains(&canonical_path) {
+                return Err(include_error(format!("{} is included recursively", included.display())));
+            }
+            let source = read(&included)?;
+
+            let included_index = match self.files.iter().position(|f| canonical(f) == canonical_path) {
+                Some(index) => index,
+                None => {
+                    self.files.push(included.clone());
+                    self.files.len() - 1
+                },
+            };
+
+            self.stack.push(canonical_path);
+            self.output.push_str(&format!("#line 1 {}\n", included_index));
+            // An included file can't change the version of the file including it
+            let body: Vec<(usize, &str)> = source.lines().enumerate()
+                .map(|(i, line)| (i + 1, if line.trim_start().starts_with("#version") { "" } else { line }))
+                .collect();
+            self.expand(&body, &included, included_index)?;
+            self.output.push_str(&format!("#line {} {}\n", line_number + 1, file_index));
+            self.stack.pop();
+        }
+        Ok(())
+    }
+}
+
+// Tracks `#if 0` regions through the conditional directives nested in them
+#[derive(Default)]
+struct Disabled {
+    // How deep the innermost `#if 0` is nested in conditionals opened inside it, None outside any
+    depth: Option<usize>,
+}
+
+impl Disabled {
+    fn is_disabled(&self) -> bool {
+        self.depth.is_some()
+    }
+
+    fn directive(&mut self, directive: &str) {
+        let mut words = directive.trim_start_matches('#').split_whitespace();
+        let (keyword, argument) = (words.next().unwrap_or(""), words.next());
+        self.depth = match (keyword, self.depth) {
+            ("if", None) if argument == Some("0") => Some(0),
+            ("if" | "ifdef" | "ifndef", Some(depth)) => Some(depth + 1),
+            ("else" | "elif", Some(0)) | ("endif", Some(0)) => None,
+            ("endif", Some(depth)) => Some(depth - 1),
+            (_, depth) => depth,
+        };
+    }
+}
+
+// Whether a block comment is still open at the end of `line`, given whether one was open at its start
+fn ends_in_comment(line: &str, mut in_comment: bool) -> bool {
+    let mut rest = line;
+    loop {
+        if in_comment {
+            match rest.find("*/") {
+                Some(end) => {
+                    rest = &rest[end + 2..];
+                    in_comment = false;
+                },
+                None => return true,
+            }
+        } else {
+            match (rest.find("/*"), rest.find("//")) {
+                (Some(start), line_comment) if line_comment.is_none_or(|l| start < l) => {
+                    rest = &rest[start + 2..];
+                    in_comment = true;
+                },
+                _ => return false,
+            }
+        }
+    }
+}
+
+fn read(path: &Path) -> Result<String, ShaderError> {
+    std::fs::read_to_string(path).map_err(|error| ShaderError::Io { path: path.to_path_buf(), error })
+}
+
+// Paths are compared in canonical form so "a/../b.glsl" and "b.glsl" count as the same file
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
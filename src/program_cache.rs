@@ -0,0 +1,168 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::util;
+
+// Where the application keeps its cached programs, next to the build output so `cargo clean` clears it
+pub const DEFAULT_CACHE_DIR: &str = "target/program_cache";
+
+// Identifies cache files, followed by a version to bump whenever the file layout changes
+const MAGIC: &[u8; 4] = b"GLPB";
+const FILE_VERSION: u32 = 2;
+// The magic, the version, the digest of the key and the binary format come before the binary itself
+const HEADER_SIZE: usize = 28;
+
+// An on-disk cache of linked programs, as returned by glGetProgramBinary.
+// Entries are keyed by the preprocessed sources of every stage and the driver that produced them, so
+// editing a shader or updating the driver simply misses the cache. The keys are FNV-1a hashes, which stay the same
+// across Rust releases, and each entry also holds a wider digest of the same inputs, so two programs whose keys
+// collide never load each other's binary. A failed load is never an error, the caller compiles the program from
+// source instead.
+#[derive(Clone, Debug)]
+pub struct ProgramCache {
+    dir: PathBuf,
+}
+
+// Names a cache entry: `id` picks the file and `digest` has to match the one stored in it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CacheKey {
+    pub id: u64,
+    pub digest: u128,
+}
+
+// A program binary along with the driver-specific format it is in
+pub struct ProgramBinary {
+    pub format: gl::types::GLenum,
+    pub data: Vec<u8>,
+}
+
+impl ProgramCache {
+    pub fn new<P: AsRef<Path>>(dir: P) -> ProgramCache {
+        ProgramCache { dir: dir.as_ref().to_path_buf() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // Drivers may not support saving programs at all, in which case caching is pointless
+    pub unsafe fn is_supported() -> bool {
+        let mut formats = 0;
+        gl::GetIntegerv(gl::NUM_PROGRAM_BINARY_FORMATS, &mut formats);
+        formats > 0
+    }
+
+    // The cache key for a program built from `sources`, given as (shader stage, preprocessed source) pairs
+    // in the order they are attached. Needs a current context to identify the driver.
+    pub unsafe fn key(sources: &[(gl::types::GLenum, &str)]) -> CacheKey {
+        let driver: Vec<String> = [gl::VENDOR, gl::RENDERER, gl::VERSION].iter().map(|&name| util::get_gl_string(name)).collect();
+        let driver: Vec<&str> = driver.iter().map(String::as_str).collect();
+        ProgramCache::key_for_driver(&driver, sources)
+    }
+
+    // The cache key for `sources` built by the driver identified by `driver`, which `key` asks the context for
+    pub fn key_for_driver(driver: &[&str], sources: &[(gl::types::GLenum, &str)]) -> CacheKey {
+        // Every string is preceded by its length, so moving text from one to the next changes the key
+        let mut input = FILE_VERSION.to_le_bytes().to_vec();
+        let mut push = |bytes: &[u8]| {
+            input.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+            input.extend_from_slice(bytes);
+        };
+        for name in driver {
+            push(name.as_bytes());
+        }
+        for (stage, source) in sources {
+            push(&stage.to_le_bytes());
+            push(source.as_bytes());
+        }
+        CacheKey { id: fnv1a_64(&input), digest: fnv1a_128(&input) }
+    }
+
+    pub fn path(&self, key: CacheKey) -> PathBuf {
+        self.dir.join(format!("{:016x}.bin", key.id))
+    }
+
+    // Returns None when there is no entry for `key`, or it is unreadable, truncated or for other sources
+    pub fn load(&self, key: CacheKey) -> Option<ProgramBinary> {
+        let bytes = std::fs::read(self.path(key)).ok()?;
+        if bytes.len() < HEADER_SIZE || &bytes[..4] != MAGIC {
+            return None;
+        }
+        let word = |offset: usize| u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+        if word(4) != FILE_VERSION {
+            return None;
+        }
+        let mut digest = [0; 16];
+        digest.copy_from_slice(&bytes[8..24]);
+        if u128::from_le_bytes(digest) != key.digest {
+            return None;
+        }
+        Some(ProgramBinary { format: word(24), data: bytes[HEADER_SIZE..].to_vec() })
+    }
+
+    pub fn store(&self, key: CacheKey, binary: &ProgramBinary) -> io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + binary.data.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FILE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&key.digest.to_le_bytes());
+        bytes.extend_from_slice(&binary.format.to_le_bytes());
+        bytes.extend_from_slice(&binary.data);
+        // Write to a temporary file first so a crash never leaves a half-written entry behind
+        let path = self.path(key);
+        let temporary = path.with_extension("tmp");
+        std::fs::write(&temporary, &bytes)?;
+        std::fs::rename(&temporary, &path)
+    }
+
+    // Removes every cached program
+    pub fn clear(&self) -> io::Result<()> {
+        match std::fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
+
+fn fnv1a_64(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3))
+}
+
+fn fnv1a_128(bytes: &[u8]) -> u128 {
+    bytes.iter().fold(0x6c62_272e_07bb_0142_62b8_2175_6295_c58d, |hash, &byte| {
+        (hash ^ u128::from(byte)).wrapping_mul(0x0000_0000_0100_0000_0000_0000_0000_013b)
+    })
+}
+
+// Retrieves the binary of a linked program, which should have been linked with PROGRAM_BINARY_RETRIEVABLE_HINT set
+pub unsafe fn get_program_binary(program_id: u32) -> Option<ProgramBinary> {
+    let mut length = 0;
+    gl::GetProgramiv(program_id, gl::PROGRAM_BINARY_LENGTH, &mut length);
+    if length <= 0 {
+        return None;
+    }
+    let mut data = vec![0u8; length as usize];
+    let mut written = 0;
+    let mut format = 0;
+    gl::GetProgramBinary(program_id, length, &mut written, &mut format, data.as_mut_ptr() as *mut std::ffi::c_void);
+    data.truncate(written as usize);
+    Some(ProgramBinary { format, data })
+}
+
+// Loads a binary into `program_id`, returning whether the driver accepted it
+pub unsafe fn load_program_binary(program_id: u32, binary: &ProgramBinary) -> bool {
+    // An unknown format is a GL error rather than a failed load, so it has to be ruled out first
+    let mut format_count = 0;
+    gl::GetIntegerv(gl::NUM_PROGRAM_BINARY_FORMATS, &mut format_count);
+    let mut formats = vec![0; format_count.max(1) as usize];
+    gl::GetIntegerv(gl::PROGRAM_BINARY_FORMATS, formats.as_mut_ptr());
+    if !formats[..format_count.max(0) as usize].contains(&(binary.format as i32)) {
+        return false;
+    }
+    gl::ProgramBinary(program_id, binary.format, binary.data.as_ptr() as *const std::ffi::c_void, binary.data.len() as i32);
+    let mut success = i32::from(gl::FALSE);
+    gl::GetProgramiv(program_id, gl::LINK_STATUS, &mut success);
+    success == i32::from(gl::TRUE)
+}
@@ -0,0 +1,216 @@
+use std::{
+    fmt,
+    cell::RefCell,
+    collections::HashMap,
+    ffi::CString,
+    ptr,
+};
+
+// An active uniform or vertex attribute of a linked program, as reported by OpenGL
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShaderVariable {
+    // Array names are stored without the "[0]" OpenGL appends to them
+    pub name: String,
+    pub gl_type: gl::types::GLenum,
+    pub location: i32,
+    // 1 unless the variable is an array
+    pub array_size: i32,
+}
+
+#[derive(Debug)]
+pub enum UniformError {
+    // No active uniform (or storage block, for the storage buffer helpers) has this name. It may be misspelled, or unused and optimized away by the compiler.
+    Inactive { name: String },
+    TypeMismatch { name: String, expected: gl::types::GLenum, actual: gl::types::GLenum },
+    IndexOutOfBounds { name: String, index: i32, array_size: i32 },
+}
+
+impl fmt::Display for UniformError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UniformError::Inactive { name } =>
+                write!(f, "The shader program has no active uniform or storage block named {}", name),
+            UniformError::TypeMismatch { name, expected, actual } =>
+                write!(f, "Uniform {} is a {}, but was set as a {}", name, type_name(*actual), type_name(*expected)),
+            UniformError::IndexOutOfBounds { name, index, array_size } =>
+                write!(f, "Index {} is out of bounds for uniform {} of length {}", index, name, array_size),
+        }
+    }
+}
+
+impl std::error::Error for UniformError {}
+
+// Everything OpenGL tells us about the interface of a linked program
+#[derive(Debug, Default)]
+pub struct ProgramReflection {
+    pub uniforms: HashMap<String, ShaderVariable>,
+    pub attributes: Vec<ShaderVariable>,
+    // Shader storage blocks by name, with the buffer binding point each one reads from
+    pub storage_blocks: HashMap<String, u32>,
+    // The local size of a compute program, None for programs without a compute stage
+    pub work_group_size: Option<[u32; 3]>,
+    // Locations of individual array elements, which are looked up the first time they are used
+    element_locations: RefCell<HashMap<String, i32>>,
+}
+
+impl ProgramReflection {
+    // `is_compute` tells whether the program has a compute stage, which can't be queried from a program
+    // loaded from a binary
+    pub unsafe fn new(program_id: u32, is_compute: bool) -> ProgramReflection {
+        let mut uniforms = HashMap::new();
+        for i in 0..program_parameter(program_id, gl::ACTIVE_UNIFORMS) {
+            let (name, gl_type, array_size) = active_variable(program_id, i as u32, gl::ACTIVE_UNIFORM_MAX_LENGTH, gl::GetActiveUniform);
+            let location = gl::GetUniformLocation(program_id, CString::new(name.as_str()).unwrap().as_ptr());
+            // Members of uniform blocks have no location and can't be set through glUniform*
+            if location == -1 { continue }
+            let name = strip_array_suffix(&name).to_string();
+            uniforms.insert(name.clone(), ShaderVariable { name, gl_type, location, array_size });
+        }
+
+        let mut attributes = vec![];
+        for i in 0..program_parameter(program_id, gl::ACTIVE_ATTRIBUTES) {
+            let (name, gl_type, array_size) = active_variable(program_id, i as u32, gl::ACTIVE_ATTRIBUTE_MAX_LENGTH, gl::GetActiveAttrib);
+            let location = gl::GetAttribLocation(program_id, CString::new(name.as_str()).unwrap().as_ptr());
+            attributes.push(ShaderVariable { name: strip_array_suffix(&name).to_string(), gl_type, location, array_size });
+        }
+        attributes.sort_by_key(|a| a.location);
+
+        let mut storage_blocks = HashMap::new();
+        let mut block_count = 0;
+        gl::GetProgramInterfaceiv(program_id, gl::SHADER_STORAGE_BLOCK, gl::ACTIVE_RESOURCES, &mut block_count);
+        let mut max_length = 0;
+        gl::GetProgramInterfaceiv(program_id, gl::SHADER_STORAGE_BLOCK, gl::MAX_NAME_LENGTH, &mut max_length);
+        for i in 0..block_count as u32 {
+            let mut name = vec![0u8; max_length.max(1) as usize];
+            let mut length = 0;
+            gl::GetProgramResourceName(program_id, gl::SHADER_STORAGE_BLOCK, i, name.len() as i32, &mut length, name.as_mut_ptr() as *mut gl::types::GLchar);
+            name.truncate(length as usize);
+            let mut binding = 0;
+            gl::GetProgramResourceiv(program_id, gl::SHADER_STORAGE_BLOCK, i, 1, &gl::BUFFER_BINDING, 1, ptr::null_mut(), &mut binding);
+            storage_blocks.insert(String::from_utf8_lossy(&name).to_string(), binding as u32);
+        }
+
+        // Asking a program without a compute stage for its work group size is an error
+        let mut work_group_size = None;
+        if is_compute {
+            let mut size = [0; 3];
+            gl::GetProgramiv(program_id, gl::COMPUTE_WORK_GROUP_SIZE, size.as_mut_ptr());
+            work_group_size = Some([size[0] as u32, size[1] as u32, size[2] as u32]);
+        }
+
+        ProgramReflection {
+            uniforms,
+            attributes,
+            storage_blocks,
+            work_group_size,
+            element_locations: RefCell::new(HashMap::new()),
+        }
+    }
+
+    // Finds the location to set the uniform `name` through, checking it has one of the `expected` types.
+    // Besides plain names this accepts single elements of arrays, such as "lights[2]".
+    pub unsafe fn uniform_location(&self, program_id: u32, name: &str, expected: &[gl::types::GLenum]) -> Result<i32, UniformError> {
+        let (base, index) = split_array_index(name);
+        let uniform = self.uniforms.get(base)
+            .ok_or_else(|| UniformError::Inactive { name: name.to_string() })?;
+        if !expected.contains(&uniform.gl_type) {
+            return Err(UniformError::TypeMismatch { name: name.to_string(), expected: expected[0], actual: uniform.gl_type });
+        }
+        match index {
+            None | Some(0) => Ok(uniform.location),
+            Some(index) if index < uniform.array_size => {
+                let mut cache = self.element_locations.borrow_mut();
+                let location = cache.entry(name.to_string()).or_insert_with(|| {
+                    gl::GetUniformLocation(program_id, CString::new(name).unwrap().as_ptr())
+                });
+                Ok(*location)
+            },
+            Some(index) => Err(UniformError::IndexOutOfBounds { name: base.to_string(), index, array_size: uniform.array_size }),
+        }
+    }
+}
+
+type GetActiveVariable = unsafe fn(u32, u32, i32, *mut i32, *mut i32, *mut gl::types::GLenum, *mut gl::types::GLchar);
+
+unsafe fn program_parameter(program_id: u32, parameter: gl::types::GLenum) -> i32 {
+    let mut value = 0;
+    gl::GetProgramiv(program_id, parameter, &mut value);
+    value
+}
+
+unsafe fn active_variable(program_id: u32, index: u32, max_length: gl::types::GLenum, get: GetActiveVariable) -> (String, gl::types::GLenum, i32) {
+    let mut name = vec![0u8; program_parameter(program_id, max_length).max(1) as usize];
+    let mut length = 0;
+    let mut array_size = 0;
+    let mut gl_type = 0;
+    get(program_id, index, name.len() as i32, &mut length, &mut array_size, &mut gl_type, name.as_mut_ptr() as *mut gl::types::GLchar);
+    name.truncate(length as usize);
+    (String::from_utf8_lossy(&name).to_string(), gl_type, array_size)
+}
+
+fn strip_array_suffix(name: &str) -> &str {
+    name.strip_suffix("[0]").unwrap_or(name)
+}
+
+// "lights[2]" -> ("lights", Some(2)), anything else is returned as is
+fn split_array_index(name: &str) -> (&str, Option<i32>) {
+    if let Some(open) = name.rfind('[') {
+        if let Some(index) = name[open + 1..].strip_suffix(']').and_then(|i| i.parse().ok()) {
+            return (&name[..open], Some(index));
+        }
+    }
+    (name, None)
+}
+
+pub fn is_sampler(gl_type: gl::types::GLenum) -> bool {
+    SAMPLER_TYPES.contains(&gl_type)
+}
+
+pub const SAMPLER_TYPES: &[gl::types::GLenum] = &[
+    gl::SAMPLER_1D, gl::SAMPLER_2D, gl::SAMPLER_3D, gl::SAMPLER_CUBE,
+    gl::SAMPLER_1D_SHADOW, gl::SAMPLER_2D_SHADOW, gl::SAMPLER_CUBE_SHADOW,
+    gl::SAMPLER_1D_ARRAY, gl::SAMPLER_2D_ARRAY, gl::SAMPLER_1D_ARRAY_SHADOW, gl::SAMPLER_2D_ARRAY_SHADOW,
+    gl::SAMPLER_2D_MULTISAMPLE, gl::SAMPLER_2D_MULTISAMPLE_ARRAY, gl::SAMPLER_BUFFER, gl::SAMPLER_2D_RECT,
+    gl::INT_SAMPLER_1D, gl::INT_SAMPLER_2D, gl::INT_SAMPLER_3D, gl::INT_SAMPLER_CUBE, gl::INT_SAMPLER_2D_ARRAY,
+    gl::UNSIGNED_INT_SAMPLER_1D, gl::UNSIGNED_INT_SAMPLER_2D, gl::UNSIGNED_INT_SAMPLER_3D,
+    gl::UNSIGNED_INT_SAMPLER_CUBE, gl::UNSIGNED_INT_SAMPLER_2D_ARRAY,
+];
+
+pub fn is_image(gl_type: gl::types::GLenum) -> bool {
+    IMAGE_TYPES.contains(&gl_type)
+}
+
+pub const IMAGE_TYPES: &[gl::types::GLenum] = &[
+    gl::IMAGE_1D, gl::IMAGE_2D, gl::IMAGE_3D, gl::IMAGE_CUBE, gl::IMAGE_BUFFER, gl::IMAGE_2D_RECT,
+    gl::IMAGE_1D_ARRAY, gl::IMAGE_2D_ARRAY, gl::IMAGE_2D_MULTISAMPLE, gl::IMAGE_2D_MULTISAMPLE_ARRAY,
+    gl::INT_IMAGE_1D, gl::INT_IMAGE_2D, gl::INT_IMAGE_3D, gl::INT_IMAGE_CUBE, gl::INT_IMAGE_2D_ARRAY,
+    gl::UNSIGNED_INT_IMAGE_1D, gl::UNSIGNED_INT_IMAGE_2D, gl::UNSIGNED_INT_IMAGE_3D,
+    gl::UNSIGNED_INT_IMAGE_CUBE, gl::UNSIGNED_INT_IMAGE_2D_ARRAY,
+];
+
+// The GLSL name of a type, for error messages
+pub fn type_name(gl_type: gl::types::GLenum) -> String {
+    let name = match gl_type {
+        gl::FLOAT => "float",
+        gl::FLOAT_VEC2 => "vec2",
+        gl::FLOAT_VEC3 => "vec3",
+        gl::FLOAT_VEC4 => "vec4",
+        gl::INT => "int",
+        gl::INT_VEC2 => "ivec2",
+        gl::INT_VEC3 => "ivec3",
+        gl::INT_VEC4 => "ivec4",
+        gl::UNSIGNED_INT => "uint",
+        gl::BOOL => "bool",
+        gl::FLOAT_MAT2 => "mat2",
+        gl::FLOAT_MAT3 => "mat3",
+        gl::FLOAT_MAT4 => "mat4",
+        gl::SAMPLER_2D => "sampler2D",
+        gl::SAMPLER_3D => "sampler3D",
+        gl::SAMPLER_CUBE => "samplerCube",
+        gl::IMAGE_2D => "image2D",
+        t if is_sampler(t) => "sampler",
+        t if is_image(t) => "image",
+        t => return format!("type 0x{:x}", t),
+    };
+    name.to_string()
+}
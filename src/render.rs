@@ -0,0 +1,446 @@
+extern crate nalgebra_glm as glm;
+
+use std::{ffi::c_void, ops::Range, ptr};
+
+use crate::gl_objects::{Buffer, VertexArray};
+use crate::gltf_scene::{GltfNode, GltfScene};
+use crate::mesh;
+use crate::util;
+use crate::reflection::UniformError;
+use crate::scene_graph::{LevelOfDetail, SceneGraph, SceneNode, NodeHandle};
+use crate::shader::Shader;
+use crate::simplify::SimplifyOptions;
+use crate::texture::{Texture, TextureOptions};
+use crate::vertex_layout::{self, VertexLayout, VertexSource, VertexStreams};
+
+// Global state every renderer in the crate expects, whether it draws to a window or offscreen
+pub unsafe fn init_gl_state() {
+    gl::Enable(gl::DEPTH_TEST);
+    gl::DepthFunc(gl::LESS);
+    gl::Enable(gl::CULL_FACE);
+    gl::Disable(gl::MULTISAMPLE);
+    gl::Enable(gl::BLEND);
+    gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+    gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+    gl::DebugMessageCallback(Some(util::debug_callback), ptr::null());
+}
+
+pub unsafe fn print_gl_diagnostics() {
+    println!("{}: {}", util::get_gl_string(gl::VENDOR), util::get_gl_string(gl::RENDERER));
+    println!("OpenGL\t: {}", util::get_gl_string(gl::VERSION));
+    println!("GLSL\t: {}", util::get_gl_string(gl::SHADING_LANGUAGE_VERSION));
+}
+
+pub unsafe fn create_mesh_vao(mesh: &mesh::Mesh) -> VertexArray {
+    create_vao_with_layout(mesh, &mesh.indices, &VertexLayout::standard())
+        .unwrap_or_else(|e| panic!("{}", e))
+}
+
+// Positions, normals and colours in the standard layout, see VertexLayout::standard
+pub unsafe fn create_vao(vertices: &[f32], normals: &[f32], colours: &[f32], indices: &[u32]) -> VertexArray {
+    let streams = VertexStreams::new()
+        .with(vertex_layout::POSITION, vertices, 3)
+        .with(vertex_layout::NORMAL, normals, 3)
+        .with(vertex_layout::COLOUR, colours, 4);
+    create_vao_with_layout(&streams, indices, &VertexLayout::standard())
+        .unwrap_or_else(|e| panic!("{}", e))
+}
+
+// Uploads the vertex data of `source` in the given layout.
+// The returned vertex array owns its buffers, and frees them along with itself when dropped.
+pub unsafe fn create_vao_with_layout(source: &dyn VertexSource, indices: &[u32], layout: &VertexLayout) -> Result<VertexArray, String> {
+    let buffers = layout.pack(source)?;
+
+    let mut vao = VertexArray::new();
+    vao.bind();
+    for packed in buffers {
+        vao.attach_buffer(Buffer::from_slice(gl::ARRAY_BUFFER, &packed.data, gl::STATIC_DRAW));
+        for (attribute, offset) in &packed.attributes {
+            let components = attribute.components as i32;
+            let component_type = attribute.component_type.into();
+            let stride = packed.stride as i32;
+            if attribute.integer {
+                gl::VertexAttribIPointer(attribute.location, components, component_type, stride, *offset as *const c_void);
+            } else {
+                let normalized = if attribute.normalized { gl::TRUE } else { gl::FALSE };
+                gl::VertexAttribPointer(attribute.location, components, component_type, normalized, stride, *offset as *const c_void);
+            }
+            gl::EnableVertexAttribArray(attribute.location);
+        }
+    }
+    vao.attach_buffer(Buffer::from_slice(gl::ELEMENT_ARRAY_BUFFER, indices, gl::STATIC_DRAW));
+
+    Ok(vao)
+}
+
+// Overwrites the given vertices of a vertex array made by create_vao_with_layout, which keeps its other vertices.
+// `layout` has to be the one the vertex array was created with, and `source` must have the same attributes.
+pub unsafe fn update_vertices(vao: &VertexArray, source: &dyn VertexSource, layout: &VertexLayout, vertices: Range<usize>) -> Result<(), String> {
+    let packed = layout.pack_range(source, vertices.clone())?;
+    let buffers = vertex_buffers(vao.buffers(), packed.len())?;
+    for (buffer, packed) in buffers.iter().zip(&packed) {
+        let byte_offset = vertices.start * packed.stride;
+        if byte_offset + packed.data.len() > buffer.size() {
+            return Err(format!("Vertices {:?} are beyond the end of the vertex array", vertices));
+        }
+        buffer.update(byte_offset, &packed.data);
+    }
+    Ok(())
+}
+
+// Replaces all vertex data of a vertex array made by create_vao_with_layout, orphaning the old buffers so frames
+// still drawing from them don't stall the upload. The vertex count may change, in which case the indices probably
+// should too, see replace_indices.
+pub unsafe fn replace_vertices(vao: &mut VertexArray, source: &dyn VertexSource, layout: &VertexLayout, usage: gl::types::GLenum) -> Result<(), String> {
+    let packed = layout.pack(source)?;
+    let count = packed.len();
+    let buffers = vertex_buffers_mut(vao.buffers_mut(), count)?;
+    for (buffer, packed) in buffers.iter_mut().zip(&packed) {
+        buffer.orphan(&packed.data, usage);
+    }
+    Ok(())
+}
+
+pub unsafe fn replace_indices(vao: &mut VertexArray, indices: &[u32], usage: gl::types::GLenum) {
+    let index_buffer = vao.buffers_mut().last_mut().expect("The vertex array has no index buffer");
+    index_buffer.orphan(indices, usage);
+}
+
+// The vertex buffers of a vertex array made by create_vao_with_layout, which come before the index buffer
+fn vertex_buffers(buffers: &[Buffer], expected: usize) -> Result<&[Buffer], String> {
+    if buffers.len() != expected + 1 {
+        return Err(format!("The layout has {} vertex buffers, but the vertex array {}", expected, buffers.len().saturating_sub(1)));
+    }
+    Ok(&buffers[..expected])
+}
+
+fn vertex_buffers_mut(buffers: &mut [Buffer], expected: usize) -> Result<&mut [Buffer], String> {
+    vertex_buffers(buffers, expected)?;
+    Ok(&mut buffers[..expected])
+}
+
+// A model's meshes uploaded to the GPU, each with a vertex array and its diffuse texture if it has one.
+// The scene graph only refers to them by id, so this has to outlive every node instantiated from it.
+pub struct UploadedModel {
+    parts: Vec<(String, Vec<UploadedMesh>)>,
+}
+
+// How to make one level of detail of every mesh in a model: the fraction of the mesh's triangles it keeps, and
+// how much of the viewport height the mesh must cover less of for it to be drawn
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LodLevel {
+    pub triangle_ratio: f32,
+    pub max_screen_size: f32,
+}
+
+struct UploadedMesh {
+    vertex_array: VertexArray,
+    index_count: i32,
+    texture: Option<Texture>,
+    normal_map: Option<Texture>,
+    // Coarser versions of the mesh, along with the screen size they are drawn below
+    levels: Vec<(VertexArray, i32, f32)>,
+    bounding_sphere: (glm::Vec3, f32),
+}
+
+// The nodes of one copy of a model in a scene graph: a root to place it by, with a child for each part
+pub struct ModelInstance {
+    pub root: NodeHandle,
+    pub parts: Vec<(String, NodeHandle)>,
+}
+
+impl ModelInstance {
+    pub fn part(&self, name: &str) -> Option<NodeHandle> {
+        self.parts.iter().find(|(part, _)| part == name).map(|&(_, node)| node)
+    }
+}
+
+impl UploadedModel {
+    pub unsafe fn new(model: &mesh::Model) -> UploadedModel {
+        UploadedModel::with_levels_of_detail(model, &[])
+    }
+
+    // Uploads simplified versions of every mesh along with it, for nodes to switch to as they get smaller on screen.
+    // `levels` go from the finest to the coarsest.
+    pub unsafe fn with_levels_of_detail(model: &mesh::Model, levels: &[LodLevel]) -> UploadedModel {
+        let parts = model.parts.iter().map(|part| {
+            let meshes = part.meshes.iter().map(|mesh| {
+                // Levels loaded along with the mesh are used as they are, as long as they were made for the same ratios
+                let loaded_ratios = mesh.levels_of_detail.iter().map(|(ratio, _)| *ratio);
+                let generated: Vec<mesh::Mesh>;
+                let chain: Vec<&mesh::Mesh> = if loaded_ratios.eq(levels.iter().map(|level| level.triangle_ratio)) {
+                    mesh.levels_of_detail.iter().map(|(_, level)| level).collect()
+                } else {
+                    let triangle_counts: Vec<usize> = levels.iter()
+                        .map(|level| (mesh.indices.len() as f32 / 3.0 * level.triangle_ratio) as usize)
+                        .collect();
+                    generated = mesh.lod_chain(&triangle_counts, &SimplifyOptions::default());
+                    generated.iter().collect()
+                };
+                UploadedMesh {
+                    vertex_array: create_mesh_vao(mesh),
+                    index_count: mesh.index_count,
+                    texture: load_diffuse_texture(mesh),
+                    normal_map: load_normal_map(mesh),
+                    levels: chain.iter().zip(levels)
+                        .map(|(lod, level)| (create_mesh_vao(lod), lod.index_count, level.max_screen_size))
+                        .collect(),
+                    bounding_sphere: mesh.bounding_sphere(),
+                }
+            }).collect();
+            (part.name.clone(), meshes)
+        }).collect();
+        UploadedModel { parts }
+    }
+
+    pub fn vertex_arrays(&self) -> impl Iterator<Item = &VertexArray> {
+        self.parts.iter().flat_map(|(_, meshes)| meshes.iter().flat_map(|mesh| {
+            std::iter::once(&mesh.vertex_array).chain(mesh.levels.iter().map(|(vertex_array, _, _)| vertex_array))
+        }))
+    }
+
+    pub fn textures(&self) -> impl Iterator<Item = &Texture> {
+        self.parts.iter().flat_map(|(_, meshes)| meshes.iter().flat_map(|mesh| mesh.texture.iter().chain(&mesh.normal_map)))
+    }
+
+    // Adds a copy of the model to `graph`, left unattached so the caller can put it wherever it belongs.
+    // A part with a single mesh draws it itself, otherwise each of its meshes gets a child node.
+    pub fn instantiate(&self, graph: &mut SceneGraph) -> ModelInstance {
+        let root = graph.add(SceneNode::new());
+        let mut parts = vec![];
+        for (name, meshes) in &self.parts {
+            let part = match meshes.as_slice() {
+                [mesh] => graph.add(mesh.node()),
+                _ => {
+                    let part = graph.add(SceneNode::new());
+                    for mesh in meshes {
+                        let child = graph.add(mesh.node());
+                        graph.add_child(part, child);
+                    }
+                    part
+                },
+            };
+            graph.add_child(root, part);
+            parts.push((name.clone(), part));
+        }
+        ModelInstance { root, parts }
+    }
+}
+
+impl UploadedMesh {
+    fn node(&self) -> SceneNode {
+        let mut node = SceneNode::from_vao(self.vertex_array.id(), self.index_count);
+        node.texture_id = self.texture.as_ref().map_or(0, Texture::id);
+        node.normal_map_id = self.normal_map.as_ref().map_or(0, Texture::id);
+        node.levels_of_detail = self.levels.iter()
+            .map(|(vertex_array, index_count, max_screen_size)| LevelOfDetail {
+                vao_id: vertex_array.id(),
+                index_count: *index_count,
+                max_screen_size: *max_screen_size,
+            })
+            .collect();
+        node.bounding_sphere = self.bounding_sphere;
+        node
+    }
+}
+
+// A glTF scene's meshes and textures uploaded to the GPU. Like UploadedModel, it has to outlive every node
+// instantiated from it.
+pub struct UploadedScene {
+    nodes: Vec<GltfNode>,
+    roots: Vec<usize>,
+    // The primitives of each glTF mesh
+    meshes: Vec<Vec<UploadedPrimitive>>,
+    textures: Vec<Texture>,
+}
+
+// Textures are shared between primitives in glTF, so primitives refer to them by their index in the scene
+struct UploadedPrimitive {
+    mesh: UploadedMesh,
+    base_color: Option<usize>,
+    normal_map: Option<usize>,
+}
+
+impl UploadedScene {
+    pub unsafe fn new(scene: &GltfScene) -> UploadedScene {
+        let textures = scene.textures.iter()
+            .map(|texture| Texture::from_image(&texture.image, &texture.options.clone().anisotropy(Texture::max_anisotropy())))
+            .collect();
+        let meshes = scene.meshes.iter().map(|primitives| primitives.iter().map(|primitive| {
+            let mesh = &primitive.mesh;
+            let material = primitive.material.map(|index| &scene.materials[index]);
+            let uploaded = UploadedMesh {
+                vertex_array: create_mesh_vao(mesh),
+                index_count: mesh.index_count,
+                texture: None,
+                normal_map: None,
+                levels: vec![],
+                bounding_sphere: mesh.bounding_sphere(),
+            };
+            UploadedPrimitive {
+                mesh: uploaded,
+                base_color: material.and_then(|material| material.base_color_texture).filter(|_| !mesh.uvs.is_empty()),
+                normal_map: material.and_then(|material| material.normal_texture).filter(|_| !mesh.tangents.is_empty()),
+            }
+        }).collect()).collect();
+        UploadedScene { nodes: scene.nodes.clone(), roots: scene.roots.clone(), meshes, textures }
+    }
+
+    pub fn vertex_arrays(&self) -> impl Iterator<Item = &VertexArray> {
+        self.meshes.iter().flatten().map(|primitive| &primitive.mesh.vertex_array)
+    }
+
+    pub fn textures(&self) -> impl Iterator<Item = &Texture> {
+        self.textures.iter()
+    }
+
+    // Adds a copy of the scene to `graph`, left unattached like UploadedModel::instantiate. Every glTF node becomes
+    // a node with the same transform and children, and the named ones are the parts of the instance. A mesh with a
+    // single primitive is drawn by its node, otherwise each primitive gets a child node.
+    pub fn instantiate(&self, graph: &mut SceneGraph) -> ModelInstance {
+        let root = graph.add(SceneNode::new());
+        let mut parts = vec![];
+        let mut added = vec![false; self.nodes.len()];
+        for &node in &self.roots {
+            if let Some(child) = self.add_node(graph, node, &mut added, &mut parts) {
+                graph.add_child(root, child);
+            }
+        }
+        ModelInstance { root, parts }
+    }
+
+    fn add_node(&self, graph: &mut SceneGraph, index: usize, added: &mut [bool], parts: &mut Vec<(String, NodeHandle)>) -> Option<NodeHandle> {
+        // A valid scene is a forest, but a node listed twice would otherwise recurse forever
+        if std::mem::replace(&mut added[index], true) {
+            println!("WARNING::GLTF: node {} appears more than once in the scene, skipping it", index);
+            return None;
+        }
+        let gltf_node = &self.nodes[index];
+        let primitives = gltf_node.mesh.map_or(&[][..], |mesh| &self.meshes[mesh]);
+        let mut node = match primitives {
+            [primitive] => self.primitive_node(primitive),
+            _ => SceneNode::new(),
+        };
+        node.position = gltf_node.translation;
+        node.scale = gltf_node.scale;
+        // Unrotated nodes are left with Euler angles, so they can be turned by `rotation` like any other node
+        if gltf_node.rotation != glm::quat_identity() {
+            node.orientation = Some(gltf_node.rotation);
+        }
+        let handle = graph.add(node);
+        if primitives.len() > 1 {
+            for primitive in primitives {
+                let child = graph.add(self.primitive_node(primitive));
+                graph.add_child(handle, child);
+            }
+        }
+        if !gltf_node.name.is_empty() {
+            parts.push((gltf_node.name.clone(), handle));
+        }
+        for &child in &gltf_node.children {
+            if let Some(child) = self.add_node(graph, child, added, parts) {
+                graph.add_child(handle, child);
+            }
+        }
+        Some(handle)
+    }
+
+    fn primitive_node(&self, primitive: &UploadedPrimitive) -> SceneNode {
+        let mut node = primitive.mesh.node();
+        node.texture_id = primitive.base_color.map_or(0, |texture| self.textures[texture].id());
+        node.normal_map_id = primitive.normal_map.map_or(0, |texture| self.textures[texture].id());
+        node
+    }
+}
+
+// The diffuse texture named by the mesh's material, if it has one with texture coordinates to map it by.
+// A texture that fails to load is left out with a warning, so a missing file doesn't stop the model from loading.
+unsafe fn load_diffuse_texture(mesh: &mesh::Mesh) -> Option<Texture> {
+    let path = mesh.material.as_ref()?.diffuse_texture.as_ref()?;
+    if mesh.uvs.is_empty() {
+        return None;
+    }
+    load_texture(path)
+}
+
+// The normal map named by the mesh's material, if the mesh has the tangents to apply it with
+unsafe fn load_normal_map(mesh: &mesh::Mesh) -> Option<Texture> {
+    let path = mesh.material.as_ref()?.normal_texture.as_ref()?;
+    if mesh.tangents.is_empty() {
+        return None;
+    }
+    load_texture(path)
+}
+
+unsafe fn load_texture(path: &std::path::Path) -> Option<Texture> {
+    let options = TextureOptions::default().anisotropy(Texture::max_anisotropy());
+    match Texture::load(path, &options) {
+        Ok(texture) => Some(texture),
+        Err(e) => {
+            println!("WARNING::TEXTURE: {}", e);
+            None
+        },
+    }
+}
+
+pub fn update_node_transformations(scene: &mut SceneGraph, node: NodeHandle, transformation_so_far: &glm::Mat4) {
+    let root = &mut scene[node];
+
+    // Construct the correct transformation matrix
+    let transform: glm::Mat4 = transformation_so_far * root.local_transformation();
+
+    // Update the node's transformation matrix
+    root.current_transformation_matrix = transform;
+
+    // Recurse
+    for i in 0..scene.children(node).len() {
+        let child = scene.children(node)[i];
+        update_node_transformations(scene, child, &transform);
+    }
+}
+
+pub fn draw_scene(scene: &SceneGraph, node: NodeHandle, view_projection_matrix: &glm::Mat4, shader: &Shader) {
+    let root = &scene[node];
+
+    // Check if node is drawable, set uniforms, draw
+    if root.index_count != -1 {
+        let transform: glm::Mat4 = view_projection_matrix * root.current_transformation_matrix;
+        let (vao_id, index_count) = if root.levels_of_detail.is_empty() {
+            (root.vao_id, root.index_count)
+        } else {
+            root.mesh_for_screen_size(root.screen_size(view_projection_matrix))
+        };
+
+        unsafe {
+            allow_inactive(shader.set_mat4("transform", &transform));
+            allow_inactive(shader.set_mat4("model", &root.current_transformation_matrix));
+            // Tell the shader whether there is a texture, as sampling a missing one gives black rather than white
+            allow_inactive(shader.set_i32("textured", (root.texture_id != 0) as i32));
+            if root.texture_id != 0 {
+                allow_inactive(shader.set_sampler("albedo", 0));
+                gl::ActiveTexture(gl::TEXTURE0);
+                gl::BindTexture(gl::TEXTURE_2D, root.texture_id);
+            }
+            allow_inactive(shader.set_i32("normalMapped", (root.normal_map_id != 0) as i32));
+            if root.normal_map_id != 0 {
+                allow_inactive(shader.set_sampler("normalMap", 1));
+                gl::ActiveTexture(gl::TEXTURE1);
+                gl::BindTexture(gl::TEXTURE_2D, root.normal_map_id);
+            }
+            gl::BindVertexArray(vao_id);
+            gl::DrawElements(gl::TRIANGLES, index_count, gl::UNSIGNED_INT, ptr::null());
+        }
+    }
+    // Recurse
+    for &child in root.children() {
+        draw_scene(scene, child, view_projection_matrix, shader);
+    }
+}
+
+// Shaders are free to leave out uniforms they don't use, but a uniform of the wrong type is a bug
+fn allow_inactive(result: Result<(), UniformError>) {
+    match result {
+        Ok(()) | Err(UniformError::Inactive { .. }) => {},
+        Err(error) => panic!("{}", error),
+    }
+}
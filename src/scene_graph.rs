@@ -1,95 +1,371 @@
-extern crate nalgebra_glm as glm;
-
-use std::mem::ManuallyDrop;
-use std::pin::Pin;
-
-// Used to crete an unholy abomination upon which you should not cast your gaze.
-// This ended up being a necessity due to wanting to keep the code written by students as "straight forward" as possible
-// It is very very double plus ungood Rust, and intentionally leaks memory like a sieve. But it works, and you're more than welcome to pretend it doesn't exist!
-// In case you're curious about how it works; It allocates memory on the heap (Box), promises to prevent it from being moved or deallocated until dropped (Pin) 
-// and finally prevents the compiler from dropping it automatically at all (ManuallyDrop). If that sounds like a janky solution, it's because it is.
-// Prettier, Rustier and better solutions were tried numerous times, but were all found wanting of having what I arbitrarily decided to be the required level of
-// simplicity of use.
-pub type Node = ManuallyDrop<Pin<Box<SceneNode>>>;
-
-pub struct SceneNode {
-    pub position: glm::Vec3,
-    pub rotation: glm::Vec3,
-    pub scale: glm::Vec3,
-    pub reference_point: glm::Vec3,
-
-    pub current_transformation_matrix: glm::Mat4,
-
-    pub vao_id: u32,
-    pub index_count: i32,
-
-    pub children: Vec<*mut SceneNode>,
-}
-
-impl SceneNode {
-    pub fn new() -> Node {
-        ManuallyDrop::new(Pin::new(Box::new(SceneNode {
-            position: glm::zero(),
-            rotation: glm::zero(),
-            scale: glm::vec3(1.0, 1.0, 1.0),
-            reference_point: glm::zero(),
-            current_transformation_matrix: glm::identity(),
-            vao_id: 0,
-            index_count: -1,
-            children: vec![],
-        })))
-    }
-    pub fn from_vao(vao_id: u32, index_count: i32) -> Node {
-        ManuallyDrop::new(Pin::new(Box::new(SceneNode {
-            position: glm::zero(),
-            rotation: glm::zero(),
-            scale: glm::vec3(1.0, 1.0, 1.0),
-            reference_point: glm::zero(),
-            current_transformation_matrix: glm::identity(),
-            vao_id, index_count,
-            children: vec![],
-        })))
-    }
-    pub fn add_child(&mut self, child: &SceneNode) {
-        self.children.push(child as *const SceneNode as *mut SceneNode)
-    }
-    pub fn print(&self) {
-        let m = self.current_transformation_matrix;
-        let matrix_string = format!(
-"
-      {:.2}  {:.2}  {:.2}  {:.2}
-      {:.2}  {:.2}  {:.2}  {:.2}
-      {:.2}  {:.2}  {:.2}  {:.2}
-      {:.2}  {:.2}  {:.2}  {:.2}
-",
-            m[0],m[4],m[8],m[12],
-            m[1],m[5],m[9],m[13],
-            m[2],m[6],m[10],m[14],
-            m[3],m[7],m[11],m[15],
-        );
-        println!(
-"SceneNode {{
-    VAO:       {}
-    Indices:   {}
-    Children:  {}
-    Position:  [{:.2}, {:.2}, {:.2}]
-    Rotation:  [{:.2}, {:.2}, {:.2}]
-    Reference: [{:.2}, {:.2}, {:.2}]
-    Current Transformation Matrix: {}
-}}",
-            self.vao_id,
-            self.index_count,
-            self.children.len(),
-            self.position.x,
-            self.position.y,
-            self.position.z,
-            self.rotation.x,
-            self.rotation.y,
-            self.rotation.z,
-            self.reference_point.x,
-            self.reference_point.y,
-            self.reference_point.z,
-            matrix_string,
-        );
-    }
-}
+extern crate nalgebra_glm as glm;
+
+use std::ops::{Index, IndexMut};
+
+// The scene graph owns every node in a single arena (a Vec of slots), and nodes refer to each other
+// through small copyable handles instead of pointers. Removing a node frees its slot for reuse, and
+// every slot carries a generation counter so that a handle to a removed node can never silently
+// refer to whatever node ends up reusing its slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeHandle {
+    index: usize,
+    generation: u32,
+}
+
+// The order in which the Euler angles in `SceneNode::rotation` are applied to the node. XYZ rotates
+// around X first and Z last, which is the order nodes have always used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RotationOrder {
+    XYZ,
+    XZY,
+    YXZ,
+    YZX,
+    ZXY,
+    ZYX,
+}
+
+// A coarser version of a node's mesh, drawn instead of it while the node covers less of the screen than
+// `max_screen_size`, as a fraction of the viewport height
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LevelOfDetail {
+    pub vao_id: u32,
+    pub index_count: i32,
+    pub max_screen_size: f32,
+}
+
+pub struct SceneNode {
+    pub position: glm::Vec3,
+    pub rotation: glm::Vec3,
+    pub rotation_order: RotationOrder,
+    // When set, this quaternion is used instead of `rotation`, which avoids the gimbal lock Euler angles suffer from
+    pub orientation: Option<glm::Quat>,
+    pub scale: glm::Vec3,
+    pub reference_point: glm::Vec3,
+
+    pub current_transformation_matrix: glm::Mat4,
+
+    pub vao_id: u32,
+    pub index_count: i32,
+    // Sampled through the `albedo` uniform when drawn, 0 for none
+    pub texture_id: u32,
+    // Sampled through the `normalMap` uniform when drawn, 0 for none. Needs a vertex array with tangents.
+    pub normal_map_id: u32,
+    // From the finest to the coarsest, with ever smaller screen sizes, see mesh_for_screen_size
+    pub levels_of_detail: Vec<LevelOfDetail>,
+    // A sphere around the node's mesh in its own coordinates, as (center, radius), for judging its size on screen
+    pub bounding_sphere: (glm::Vec3, f32),
+
+    parent: Option<NodeHandle>,
+    children: Vec<NodeHandle>,
+}
+
+struct Slot {
+    generation: u32,
+    node: Option<SceneNode>,
+}
+
+#[derive(Default)]
+pub struct SceneGraph {
+    slots: Vec<Slot>,
+    free: Vec<usize>,
+}
+
+impl SceneNode {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> SceneNode {
+        SceneNode::from_vao(0, -1)
+    }
+    pub fn from_vao(vao_id: u32, index_count: i32) -> SceneNode {
+        SceneNode {
+            position: glm::zero(),
+            rotation: glm::zero(),
+            rotation_order: RotationOrder::XYZ,
+            orientation: None,
+            scale: glm::vec3(1.0, 1.0, 1.0),
+            reference_point: glm::zero(),
+            current_transformation_matrix: glm::identity(),
+            vao_id, index_count,
+            texture_id: 0,
+            normal_map_id: 0,
+            levels_of_detail: vec![],
+            bounding_sphere: (glm::zero(), 0.0),
+            parent: None,
+            children: vec![],
+        }
+    }
+    pub fn parent(&self) -> Option<NodeHandle> {
+        self.parent
+    }
+    // How much of the viewport height the bounding sphere covers, as of the last update of the node's
+    // transformation. Infinite when the camera is inside the sphere.
+    pub fn screen_size(&self, view_projection_matrix: &glm::Mat4) -> f32 {
+        let model = &self.current_transformation_matrix;
+        let (center, radius) = self.bounding_sphere;
+        let scale = (0..3).map(|i| model.column(i).xyz().norm()).fold(0.0, f32::max);
+        let radius = radius * scale;
+        let clip = view_projection_matrix * model * glm::vec4(center.x, center.y, center.z, 1.0);
+        if clip.w <= radius {
+            return f32::INFINITY;
+        }
+        // The second row of the view-projection matrix scales view space heights by the projection's focal length
+        let focal_length = view_projection_matrix.row(1).columns(0, 3).norm();
+        radius * focal_length / clip.w
+    }
+    // The vertex array and index count to draw at the given screen size
+    pub fn mesh_for_screen_size(&self, screen_size: f32) -> (u32, i32) {
+        self.levels_of_detail.iter()
+            .take_while(|level| screen_size < level.max_screen_size)
+            .last()
+            .map_or((self.vao_id, self.index_count), |level| (level.vao_id, level.index_count))
+    }
+    // The node's transformation relative to its parent. Rotation and scale are both applied around
+    // the reference point, so a non-uniform scale stretches the node about its pivot.
+    pub fn local_transformation(&self) -> glm::Mat4 {
+        let mut transform: glm::Mat4 = glm::identity();
+
+        transform *= glm::translation(&self.position);
+        transform *= glm::translation(&self.reference_point);
+        transform *= self.rotation_matrix();
+        transform *= glm::scaling(&self.scale);
+        transform *= glm::translation(&(self.reference_point * -1.0));
+
+        transform
+    }
+    pub fn rotation_matrix(&self) -> glm::Mat4 {
+        match self.orientation {
+            Some(orientation) => glm::quat_to_mat4(&orientation),
+            None => {
+                let rotate_x = glm::rotation(self.rotation.x, &glm::vec3(1.0, 0.0, 0.0));
+                let rotate_y = glm::rotation(self.rotation.y, &glm::vec3(0.0, 1.0, 0.0));
+                let rotate_z = glm::rotation(self.rotation.z, &glm::vec3(0.0, 0.0, 1.0));
+                // Matrices apply right to left, so the first rotation in the order goes last
+                match self.rotation_order {
+                    RotationOrder::XYZ => rotate_z * rotate_y * rotate_x,
+                    RotationOrder::XZY => rotate_y * rotate_z * rotate_x,
+                    RotationOrder::YXZ => rotate_z * rotate_x * rotate_y,
+                    RotationOrder::YZX => rotate_x * rotate_z * rotate_y,
+                    RotationOrder::ZXY => rotate_y * rotate_x * rotate_z,
+                    RotationOrder::ZYX => rotate_x * rotate_y * rotate_z,
+                }
+            },
+        }
+    }
+    // The node's current rotation as a unit quaternion, whether it is stored as one or as Euler angles
+    pub fn rotation_quat(&self) -> glm::Quat {
+        match self.orientation {
+            Some(orientation) => orientation,
+            None => glm::to_quat(&self.rotation_matrix()),
+        }
+    }
+    // Rotates the node by `angle` radians around `axis`, given in the parent's coordinate system.
+    // This switches the node over to quaternion orientation. A zero axis leaves the node as it is.
+    pub fn rotate_around_axis(&mut self, angle: f32, axis: &glm::Vec3) {
+        if glm::length(axis) < f32::EPSILON { return }
+        let rotation = glm::quat_angle_axis(angle, &glm::normalize(axis));
+        self.orientation = Some(glm::quat_normalize(&(rotation * self.rotation_quat())));
+    }
+    // Turns the node so that its local -Z axis points from its position towards `target`, with its
+    // local +Y axis as close to `up` as possible. Both are given in the parent's coordinate system.
+    // When `up` is parallel to the direction, any other up vector is used instead.
+    pub fn look_at(&mut self, target: &glm::Vec3, up: &glm::Vec3) {
+        let direction = target - self.position;
+        if glm::length(&direction) < f32::EPSILON { return }
+        let direction = glm::normalize(&direction);
+        let up = if glm::length(&glm::cross(&direction, up)) > 1e-6 {
+            *up
+        } else if direction.x.abs() < 0.9 {
+            glm::vec3(1.0, 0.0, 0.0)
+        } else {
+            glm::vec3(0.0, 0.0, 1.0)
+        };
+        // quat_look_at builds a view rotation, which maps the direction onto -Z; the node needs the opposite
+        self.orientation = Some(glm::quat_conjugate(&glm::quat_look_at(&direction, &up)));
+    }
+    // Moves the node's orientation a fraction `t` of the way towards `target` along the shortest arc
+    pub fn slerp_towards(&mut self, target: &glm::Quat, t: f32) {
+        self.orientation = Some(slerp(&self.rotation_quat(), target, t));
+    }
+    pub fn children(&self) -> &[NodeHandle] {
+        &self.children
+    }
+    pub fn print(&self) {
+        let m = self.current_transformation_matrix;
+        let matrix_string = format!(
+"
+      {:.2}  {:.2}  {:.2}  {:.2}
+      {:.2}  {:.2}  {:.2}  {:.2}
+      {:.2}  {:.2}  {:.2}  {:.2}
+      {:.2}  {:.2}  {:.2}  {:.2}
+",
+            m[0],m[4],m[8],m[12],
+            m[1],m[5],m[9],m[13],
+            m[2],m[6],m[10],m[14],
+            m[3],m[7],m[11],m[15],
+        );
+        println!(
+"SceneNode {{
+    VAO:       {}
+    Indices:   {}
+    Children:  {}
+    Position:  [{:.2}, {:.2}, {:.2}]
+    Rotation:  [{:.2}, {:.2}, {:.2}]
+    Scale:     [{:.2}, {:.2}, {:.2}]
+    Reference: [{:.2}, {:.2}, {:.2}]
+    Current Transformation Matrix: {}
+}}",
+            self.vao_id,
+            self.index_count,
+            self.children.len(),
+            self.position.x,
+            self.position.y,
+            self.position.z,
+            self.rotation.x,
+            self.rotation.y,
+            self.rotation.z,
+            self.scale.x,
+            self.scale.y,
+            self.scale.z,
+            self.reference_point.x,
+            self.reference_point.y,
+            self.reference_point.z,
+            matrix_string,
+        );
+    }
+}
+
+// Spherical linear interpolation between two orientations, always taking the shorter way around
+pub fn slerp(from: &glm::Quat, to: &glm::Quat, t: f32) -> glm::Quat {
+    let to = if glm::quat_dot(from, to) < 0.0 { -to } else { *to };
+    glm::quat_slerp(from, &to, t)
+}
+
+impl SceneGraph {
+    pub fn new() -> SceneGraph {
+        SceneGraph { slots: vec![], free: vec![] }
+    }
+
+    // Moves a node into the graph. The node starts out detached; use add_child to place it in the hierarchy
+    pub fn add(&mut self, mut node: SceneNode) -> NodeHandle {
+        node.parent = None;
+        node.children.clear();
+        if let Some(index) = self.free.pop() {
+            let slot = &mut self.slots[index];
+            slot.node = Some(node);
+            NodeHandle { index, generation: slot.generation }
+        } else {
+            self.slots.push(Slot { generation: 0, node: Some(node) });
+            NodeHandle { index: self.slots.len() - 1, generation: 0 }
+        }
+    }
+
+    pub fn contains(&self, handle: NodeHandle) -> bool {
+        self.get(handle).is_some()
+    }
+
+    pub fn get(&self, handle: NodeHandle) -> Option<&SceneNode> {
+        self.slots.get(handle.index)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.node.as_ref())
+    }
+
+    pub fn get_mut(&mut self, handle: NodeHandle) -> Option<&mut SceneNode> {
+        self.slots.get_mut(handle.index)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.node.as_mut())
+    }
+
+    // Number of live nodes in the graph
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn parent(&self, handle: NodeHandle) -> Option<NodeHandle> {
+        self[handle].parent
+    }
+
+    pub fn children(&self, handle: NodeHandle) -> &[NodeHandle] {
+        &self[handle].children
+    }
+
+    // Attaches `child` to `parent`. If the child already has a parent it is moved, along with its
+    // whole subtree, so this doubles as reparenting. Panics if it would make a node its own ancestor.
+    pub fn add_child(&mut self, parent: NodeHandle, child: NodeHandle) {
+        assert!(self.contains(child), "Attempted to attach a node which is not in the scene graph");
+        if self.is_ancestor(child, parent) {
+            panic!("Attempted to attach a scene node to itself or one of its descendants");
+        }
+        self.detach(child);
+        self[parent].children.push(child);
+        self[child].parent = Some(parent);
+    }
+
+    // Alias of add_child, for call sites where moving an attached node is the intent
+    pub fn reparent(&mut self, child: NodeHandle, new_parent: NodeHandle) {
+        self.add_child(new_parent, child);
+    }
+
+    // Removes the node from its parent's children, leaving it (and its subtree) in the graph as a new root
+    pub fn detach(&mut self, handle: NodeHandle) {
+        if let Some(parent) = self[handle].parent.take() {
+            self[parent].children.retain(|&c| c != handle);
+        }
+    }
+
+    // Removes the node and all of its descendants from the graph, returning the removed node itself.
+    // Handles to any of the removed nodes are invalidated.
+    pub fn remove(&mut self, handle: NodeHandle) -> SceneNode {
+        self.detach(handle);
+        let mut node = self.take(handle);
+        let mut pending = std::mem::take(&mut node.children);
+        while let Some(descendant) = pending.pop() {
+            pending.extend(self.take(descendant).children);
+        }
+        node
+    }
+
+    // Returns whether `ancestor` is `node` or lies on the path from `node` up to its root
+    pub fn is_ancestor(&self, ancestor: NodeHandle, node: NodeHandle) -> bool {
+        let mut current = Some(node);
+        while let Some(handle) = current {
+            if handle == ancestor { return true }
+            current = self[handle].parent;
+        }
+        false
+    }
+
+    // Depth-first, pre-order list of the nodes in the subtree rooted at `root`
+    pub fn descendants(&self, root: NodeHandle) -> Vec<NodeHandle> {
+        let mut order = vec![];
+        let mut stack = vec![root];
+        while let Some(handle) = stack.pop() {
+            order.push(handle);
+            stack.extend(self[handle].children.iter().rev());
+        }
+        order
+    }
+
+    fn take(&mut self, handle: NodeHandle) -> SceneNode {
+        let slot = &mut self.slots[handle.index];
+        let node = slot.node.take().expect("Attempted to remove a scene node twice");
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(handle.index);
+        node
+    }
+}
+
+impl Index<NodeHandle> for SceneGraph {
+    type Output = SceneNode;
+    fn index(&self, handle: NodeHandle) -> &SceneNode {
+        self.get(handle).expect("Invalid scene node handle, was the node removed?")
+    }
+}
+
+impl IndexMut<NodeHandle> for SceneGraph {
+    fn index_mut(&mut self, handle: NodeHandle) -> &mut SceneNode {
+        self.get_mut(handle).expect("Invalid scene node handle, was the node removed?")
+    }
+}
@@ -1,153 +1,535 @@
-use gl;
+extern crate nalgebra_glm as glm;
+
 use std::{
     ptr,
+    collections::HashMap,
     str,
+    fmt,
     ffi::CString,
-    path::Path,
+    path::{Path, PathBuf},
+    time::SystemTime,
 };
 
+use crate::gl_objects::Program;
+use crate::preprocessor::{self, PreprocessedSource};
+use crate::program_cache::{self, ProgramCache};
+use crate::reflection::{self, ProgramReflection, ShaderVariable, UniformError};
+use crate::texture::Texture;
+
 pub struct Shader {
-    pub program_id: u32,
+    program: Program,
+    reflection: ProgramReflection,
+    // The stages attached to the program, each with the defines it was preprocessed with
+    stages: Vec<(StageSource, Vec<(String, String)>)>,
+    // Every file read while building the program, includes too, with the modification time it had at that point
+    watched: Vec<(PathBuf, Option<SystemTime>)>,
+    // Reloads go through the same cache the program was built with
+    cache: Option<ProgramCache>,
+    loaded_from_cache: bool,
 }
 
+// Attached sources are only preprocessed, compiling them is left to `link` so it can be skipped when the
+// linked program is found in the cache
 pub struct ShaderBuilder {
-    program_id: u32,
+    // Taken by the Shader on a successful link
+    program: Option<Program>,
     shaders: Vec::<u32>,
+    sources: Vec<PendingStage>,
+    defines: Vec<(String, String)>,
+    stages: Vec<(StageSource, Vec<(String, String)>)>,
+    watched: Vec<(PathBuf, Option<SystemTime>)>,
+    cache: Option<ProgramCache>,
+}
+
+// Where an attached stage came from, so reloads can build it again
+#[derive(Clone)]
+enum StageSource {
+    File(PathBuf),
+    // Compiled again from the same string on every reload
+    String { source: String, shader_type: ShaderType },
+}
+
+struct PendingStage {
+    preprocessed: PreprocessedSource,
+    shader_type: ShaderType,
+    file: Option<PathBuf>,
 }
 
 #[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ShaderType {
     Vertex,
     Fragment,
     TessellationControl,
     TessellationEvaluation,
     Geometry,
+    Compute,
+}
+
+#[derive(Debug)]
+pub enum ShaderError {
+    // The shader source could not be read
+    Io { path: PathBuf, error: std::io::Error },
+    // The file extension does not name a shader stage, see ShaderType::from_ext
+    UnknownExtension { path: PathBuf },
+    // A malformed or circular #include, `line` is the line of the directive in `file`
+    Include { file: PathBuf, line: usize, message: String },
+    // `file` is None for shaders compiled straight from a string
+    Compile { stage: ShaderType, file: Option<PathBuf>, log: String },
+    Link { log: String },
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShaderError::Io { path, error } =>
+                write!(f, "Failed to read shader source {}: {}", path.display(), error),
+            ShaderError::UnknownExtension { path } =>
+                write!(f, "Failed to parse the extension of shader file {}, expected one of .vert, .frag, .tcs, .tes, .geom or .comp", path.display()),
+            ShaderError::Include { file, line, message } =>
+                write!(f, "{}:{}: {}", file.display(), line, message),
+            ShaderError::Compile { stage, file: Some(file), log } =>
+                write!(f, "{:?} shader {} failed to compile:\n{}", stage, file.display(), log),
+            ShaderError::Compile { stage, file: None, log } =>
+                write!(f, "{:?} shader failed to compile:\n{}", stage, log),
+            ShaderError::Link { log } =>
+                write!(f, "Shader program failed to link:\n{}", log),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ShaderError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
 }
 
 impl Shader {
+    pub fn program_id(&self) -> u32 {
+        self.program.id()
+    }
+
     // Make sure the shader is active before calling this
     pub unsafe fn get_uniform_location(&self, name: &str) -> i32 {
-        gl::GetUniformLocation(self.program_id, CString::new(name).expect("CString::new failed").as_ptr())
+        gl::GetUniformLocation(self.program.id(), CString::new(name).expect("CString::new failed").as_ptr())
     }
 
     pub unsafe fn activate(&self) {
-        gl::UseProgram(self.program_id);
+        gl::UseProgram(self.program.id());
+    }
+
+    // The active uniforms of the program by name, arrays without their "[0]" suffix
+    pub fn uniforms(&self) -> &HashMap<String, ShaderVariable> {
+        &self.reflection.uniforms
+    }
+
+    // The active vertex attributes of the program, ordered by location
+    pub fn attributes(&self) -> &[ShaderVariable] {
+        &self.reflection.attributes
+    }
+
+    pub fn attribute_location(&self, name: &str) -> Option<i32> {
+        self.reflection.attributes.iter().find(|a| a.name == name).map(|a| a.location)
+    }
+
+    // Typed uniform setters. Each checks the uniform exists and has the matching GLSL type before setting it.
+    // They write straight to the program, so it does not need to be active.
+    pub unsafe fn set_mat4(&self, name: &str, value: &glm::Mat4) -> Result<(), UniformError> {
+        let location = self.reflection.uniform_location(self.program.id(), name, &[gl::FLOAT_MAT4])?;
+        gl::ProgramUniformMatrix4fv(self.program.id(), location, 1, gl::FALSE, value.as_ptr());
+        Ok(())
+    }
+
+    pub unsafe fn set_mat3(&self, name: &str, value: &glm::Mat3) -> Result<(), UniformError> {
+        let location = self.reflection.uniform_location(self.program.id(), name, &[gl::FLOAT_MAT3])?;
+        gl::ProgramUniformMatrix3fv(self.program.id(), location, 1, gl::FALSE, value.as_ptr());
+        Ok(())
+    }
+
+    pub unsafe fn set_vec4(&self, name: &str, value: &glm::Vec4) -> Result<(), UniformError> {
+        let location = self.reflection.uniform_location(self.program.id(), name, &[gl::FLOAT_VEC4])?;
+        gl::ProgramUniform4fv(self.program.id(), location, 1, value.as_ptr());
+        Ok(())
+    }
+
+    pub unsafe fn set_vec3(&self, name: &str, value: &glm::Vec3) -> Result<(), UniformError> {
+        let location = self.reflection.uniform_location(self.program.id(), name, &[gl::FLOAT_VEC3])?;
+        gl::ProgramUniform3fv(self.program.id(), location, 1, value.as_ptr());
+        Ok(())
+    }
+
+    pub unsafe fn set_vec2(&self, name: &str, value: &glm::Vec2) -> Result<(), UniformError> {
+        let location = self.reflection.uniform_location(self.program.id(), name, &[gl::FLOAT_VEC2])?;
+        gl::ProgramUniform2fv(self.program.id(), location, 1, value.as_ptr());
+        Ok(())
+    }
+
+    pub unsafe fn set_f32(&self, name: &str, value: f32) -> Result<(), UniformError> {
+        let location = self.reflection.uniform_location(self.program.id(), name, &[gl::FLOAT])?;
+        gl::ProgramUniform1f(self.program.id(), location, value);
+        Ok(())
+    }
+
+    // Also accepts bool uniforms, which are set through integers
+    pub unsafe fn set_i32(&self, name: &str, value: i32) -> Result<(), UniformError> {
+        let location = self.reflection.uniform_location(self.program.id(), name, &[gl::INT, gl::BOOL])?;
+        gl::ProgramUniform1i(self.program.id(), location, value);
+        Ok(())
+    }
+
+    pub unsafe fn set_u32(&self, name: &str, value: u32) -> Result<(), UniformError> {
+        let location = self.reflection.uniform_location(self.program.id(), name, &[gl::UNSIGNED_INT])?;
+        gl::ProgramUniform1ui(self.program.id(), location, value);
+        Ok(())
+    }
+
+    // Points a sampler uniform at a texture unit, with 0 meaning gl::TEXTURE0
+    pub unsafe fn set_sampler(&self, name: &str, texture_unit: u32) -> Result<(), UniformError> {
+        let location = self.reflection.uniform_location(self.program.id(), name, reflection::SAMPLER_TYPES)?;
+        gl::ProgramUniform1i(self.program.id(), location, texture_unit as i32);
+        Ok(())
+    }
+
+    // Binds `texture` to a texture unit and points the sampler uniform `name` at it
+    pub unsafe fn bind_texture(&self, name: &str, texture: &Texture, texture_unit: u32) -> Result<(), UniformError> {
+        self.set_sampler(name, texture_unit)?;
+        texture.bind(texture_unit);
+        Ok(())
+    }
+
+    // The local work group size declared by a compute shader, None if the program has no compute stage
+    pub fn work_group_size(&self) -> Option<[u32; 3]> {
+        self.reflection.work_group_size
+    }
+
+    // Activates the program and runs `groups` work groups of a compute shader.
+    // Follow it with gl::MemoryBarrier before reading what the shader wrote.
+    pub unsafe fn dispatch(&self, groups: [u32; 3]) {
+        assert!(self.reflection.work_group_size.is_some(), "Can only dispatch a program with a compute stage");
+        self.activate();
+        gl::DispatchCompute(groups[0], groups[1], groups[2]);
+    }
+
+    // Dispatches enough work groups to cover `invocations` in every dimension, rounding up.
+    // The shader should skip invocations beyond the end of its data when the sizes don't divide evenly.
+    pub unsafe fn dispatch_invocations(&self, invocations: [u32; 3]) {
+        let size = self.reflection.work_group_size.expect("Can only dispatch a program with a compute stage");
+        self.dispatch([
+            invocations[0].div_ceil(size[0]),
+            invocations[1].div_ceil(size[1]),
+            invocations[2].div_ceil(size[2]),
+        ]);
+    }
+
+    // Binds `buffer_id` to the binding point of the shader storage block `name`
+    pub unsafe fn bind_storage_buffer(&self, name: &str, buffer_id: u32) -> Result<(), UniformError> {
+        let binding = *self.reflection.storage_blocks.get(name)
+            .ok_or_else(|| UniformError::Inactive { name: name.to_string() })?;
+        gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, binding, buffer_id);
+        Ok(())
+    }
+
+    // Binds a level of `texture_id` to image unit `unit` and points the image uniform `name` at it.
+    // `format` must match the format qualifier of the uniform, e.g. gl::RGBA32F for layout(rgba32f).
+    pub unsafe fn bind_image(&self, name: &str, unit: u32, texture_id: u32, level: i32, access: gl::types::GLenum, format: gl::types::GLenum) -> Result<(), UniformError> {
+        let location = self.reflection.uniform_location(self.program.id(), name, reflection::IMAGE_TYPES)?;
+        gl::ProgramUniform1i(self.program.id(), location, unit as i32);
+        gl::BindImageTexture(unit, texture_id, level, gl::TRUE, 0, access, format);
+        Ok(())
+    }
+
+    // Whether the program was loaded from its ProgramCache rather than compiled, by the last build or reload
+    pub fn loaded_from_cache(&self) -> bool {
+        self.loaded_from_cache
+    }
+
+    // Every file the program was built from, including the ones pulled in through #include
+    pub fn source_paths(&self) -> impl Iterator<Item = &Path> {
+        self.watched.iter().map(|(path, _)| path.as_path())
+    }
+
+    // Checks whether any of the source files changed since the program was built, and if so rebuilds it.
+    // Cheap enough to call every frame. When the new sources fail to build the error is printed and the
+    // old program is kept, until the files change again. Returns whether the program was replaced.
+    pub unsafe fn reload_if_changed(&mut self) -> bool {
+        let changed = self.watched.iter().any(|(path, modified)| modification_time(path) != *modified);
+        if !changed {
+            return false;
+        }
+        match self.reload() {
+            Ok(()) => {
+                println!("Reloaded shader program built from {}", self.describe_sources());
+                true
+            },
+            Err(e) => {
+                println!("ERROR::SHADER::RELOAD_FAILED, keeping the previous program\n{}", e);
+                // Don't try again until the files are touched again
+                for (path, modified) in &mut self.watched {
+                    *modified = modification_time(path);
+                }
+                false
+            },
+        }
+    }
+
+    // Rebuilds the program from its source files, with the same defines, replacing it only if the new one
+    // compiles and links. If the old program was in use the new one takes its place. Stages compiled from strings
+    // are compiled again from the same string, and programs with no source files are left alone.
+    pub unsafe fn reload(&mut self) -> Result<(), ShaderError> {
+        if !self.stages.iter().any(|(source, _)| matches!(source, StageSource::File(_))) {
+            return Ok(());
+        }
+        let mut builder = ShaderBuilder::new();
+        builder.cache = self.cache.clone();
+        for (source, defines) in &self.stages {
+            builder.defines = defines.clone();
+            builder = match source {
+                StageSource::File(path) => builder.attach_path(path)?,
+                StageSource::String { source, shader_type } => builder.compile_shader(source, *shader_type)?,
+            };
+        }
+        let new_shader = builder.link()?;
+
+        let mut current_program = 0;
+        gl::GetIntegerv(gl::CURRENT_PROGRAM, &mut current_program);
+        // The old program is deleted when it goes out of scope
+        let old_program = std::mem::replace(&mut self.program, new_shader.program);
+        self.reflection = new_shader.reflection;
+        self.stages = new_shader.stages;
+        self.watched = new_shader.watched;
+        self.loaded_from_cache = new_shader.loaded_from_cache;
+        if current_program as u32 == old_program.id() {
+            gl::UseProgram(self.program.id());
+        }
+        Ok(())
+    }
+
+    fn describe_sources(&self) -> String {
+        self.source_paths().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", ")
     }
 }
 
-impl Into<gl::types::GLenum> for ShaderType {
-    fn into(self) -> gl::types::GLenum {
-        match self {
+fn modification_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+impl From<ShaderType> for gl::types::GLenum {
+    fn from(shader_type: ShaderType) -> gl::types::GLenum {
+        match shader_type {
             ShaderType::Vertex                  => { gl::VERTEX_SHADER          },
             ShaderType::Fragment                => { gl::FRAGMENT_SHADER        },
             ShaderType::TessellationControl     => { gl::TESS_CONTROL_SHADER    },
             ShaderType::TessellationEvaluation  => { gl::TESS_EVALUATION_SHADER } ,
             ShaderType::Geometry                => { gl::GEOMETRY_SHADER        },
+            ShaderType::Compute                 => { gl::COMPUTE_SHADER         },
         }
     }
 }
 
 impl ShaderType {
-    fn from_ext(ext: &std::ffi::OsStr) -> Result<ShaderType, String> {
-        match ext.to_str().expect("Failed to read extension") {
-            "vert" => { Ok(ShaderType::Vertex) },
-            "frag" => { Ok(ShaderType::Fragment) },
-            "tcs"  => { Ok(ShaderType::TessellationControl) },
-            "tes"  => { Ok(ShaderType::TessellationEvaluation) },
-            "geom" => { Ok(ShaderType::Geometry) },
-            e => { Err(e.to_string()) },
+    pub fn from_ext(ext: &std::ffi::OsStr) -> Result<ShaderType, String> {
+        match ext.to_str() {
+            Some("vert") => { Ok(ShaderType::Vertex) },
+            Some("frag") => { Ok(ShaderType::Fragment) },
+            Some("tcs")  => { Ok(ShaderType::TessellationControl) },
+            Some("tes")  => { Ok(ShaderType::TessellationEvaluation) },
+            Some("geom") => { Ok(ShaderType::Geometry) },
+            Some("comp") => { Ok(ShaderType::Compute) },
+            e => { Err(e.unwrap_or_default().to_string()) },
         }
     }
 }
 
 impl ShaderBuilder {
+    fn program_id(&self) -> u32 {
+        self.program.as_ref().map_or(0, Program::id)
+    }
+
     pub unsafe fn new() -> ShaderBuilder {
         ShaderBuilder {
-            program_id: gl::CreateProgram(),
+            program: Some(Program::new()),
             shaders: vec![],
+            sources: vec![],
+            defines: vec![],
+            stages: vec![],
+            watched: vec![],
+            cache: None,
         }
     }
 
-    pub unsafe fn attach_file(self, shader_path: &str) -> ShaderBuilder {
-        let path = Path::new(shader_path);
-        if let Some(extension) = path.extension() {
-            let shader_type = ShaderType::from_ext(extension)
-                .expect("Failed to parse file extension.");
-            let shader_src = std::fs::read_to_string(path)
-                .expect(&format!("Failed to read shader source. {}", shader_path));
-            self.compile_shader(&shader_src, shader_type)
-        } else {
-            panic!("Failed to read extension of file with path: {}", shader_path);
+    // Looks the linked program up in `cache` before compiling anything, and stores it there after linking
+    pub fn cache(mut self, cache: ProgramCache) -> ShaderBuilder {
+        self.cache = Some(cache);
+        self
+    }
+
+    // Adds `#define name value` to every shader attached after this call, replacing an earlier define of the same name.
+    // This way a single source file can be compiled into several variants.
+    pub fn define(mut self, name: &str, value: &str) -> ShaderBuilder {
+        self.defines.retain(|(existing, _)| existing != name);
+        self.defines.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub unsafe fn attach_file(self, shader_path: &str) -> Result<ShaderBuilder, ShaderError> {
+        self.attach_path(Path::new(shader_path))
+    }
+
+    pub unsafe fn attach_path(mut self, path: &Path) -> Result<ShaderBuilder, ShaderError> {
+        let shader_type = path.extension()
+            .and_then(|extension| ShaderType::from_ext(extension).ok())
+            .ok_or_else(|| ShaderError::UnknownExtension { path: path.to_path_buf() })?;
+        let preprocessed = preprocessor::preprocess_file(path, &self.defines)?;
+        for file in &preprocessed.files {
+            if !self.watched.iter().any(|(watched, _)| watched == file) {
+                self.watched.push((file.clone(), modification_time(file)));
+            }
         }
+        self.stages.push((StageSource::File(path.to_path_buf()), self.defines.clone()));
+        self.sources.push(PendingStage { preprocessed, shader_type, file: Some(path.to_path_buf()) });
+        Ok(self)
+    }
+
+    // Compiles GLSL source held in memory. Includes are resolved relative to the working directory.
+    pub unsafe fn compile_shader(mut self, shader_src: &str, shader_type: ShaderType) -> Result<ShaderBuilder, ShaderError> {
+        let preprocessed = preprocessor::preprocess_source(shader_src, None, &self.defines)?;
+        self.stages.push((StageSource::String { source: shader_src.to_string(), shader_type }, self.defines.clone()));
+        self.sources.push(PendingStage { preprocessed, shader_type, file: None });
+        Ok(self)
     }
 
-    pub unsafe fn compile_shader(mut self, shader_src: &str, shader_type: ShaderType) -> ShaderBuilder {
+    unsafe fn compile(&mut self, preprocessed: &PreprocessedSource, shader_type: ShaderType, file: Option<&Path>) -> Result<(), ShaderError> {
         let shader = gl::CreateShader(shader_type.into());
-        let c_str_shader = CString::new(shader_src.as_bytes()).unwrap();
+        let c_str_shader = CString::new(preprocessed.source.as_bytes()).unwrap();
         gl::ShaderSource(shader, 1, &c_str_shader.as_ptr(), ptr::null());
         gl::CompileShader(shader);
 
-        if !self.check_shader_errors(shader) {
-            panic!("Shader failed to compile.");
+        if let Err(mut log) = self.check_shader_errors(shader) {
+            gl::DeleteShader(shader);
+            if preprocessed.files.len() > 1 {
+                log = format!("{}\nSource string numbers:\n{}", log, preprocessed.source_string_legend());
+            }
+            return Err(ShaderError::Compile { stage: shader_type, file: file.map(Path::to_path_buf), log });
         }
 
         self.shaders.push(shader);
 
-        self
+        Ok(())
     }
 
-    unsafe fn check_shader_errors(&self, shader_id: u32) -> bool {
+    unsafe fn check_shader_errors(&self, shader_id: u32) -> Result<(), String> {
         let mut success = i32::from(gl::FALSE);
-        let mut info_log = Vec::with_capacity(512);
-        info_log.set_len(512 - 1);
         gl::GetShaderiv(shader_id, gl::COMPILE_STATUS, &mut success);
         if success != i32::from(gl::TRUE) {
+            let mut log_length = 0;
+            gl::GetShaderiv(shader_id, gl::INFO_LOG_LENGTH, &mut log_length);
+            let mut info_log = vec![0u8; log_length.max(1) as usize];
             gl::GetShaderInfoLog(
                 shader_id,
-                512,
+                info_log.len() as i32,
                 ptr::null_mut(),
                 info_log.as_mut_ptr() as *mut gl::types::GLchar,
             );
-            println!("ERROR::Shader Compilation Failed!\n{}", String::from_utf8_lossy(&info_log));
-            return false;
+            return Err(info_log_to_string(info_log));
         }
-        true
+        Ok(())
     }
 
-    unsafe fn check_linker_errors(&self) -> bool {
+    unsafe fn check_linker_errors(&self) -> Result<(), String> {
         let mut success = i32::from(gl::FALSE);
-        let mut info_log = Vec::with_capacity(512);
-        info_log.set_len(512 - 1);
-        gl::GetProgramiv(self.program_id, gl::LINK_STATUS, &mut success);
+        gl::GetProgramiv(self.program_id(), gl::LINK_STATUS, &mut success);
         if success != i32::from(gl::TRUE) {
+            let mut log_length = 0;
+            gl::GetProgramiv(self.program_id(), gl::INFO_LOG_LENGTH, &mut log_length);
+            let mut info_log = vec![0u8; log_length.max(1) as usize];
             gl::GetProgramInfoLog(
-                self.program_id,
-                512,
+                self.program_id(),
+                info_log.len() as i32,
                 ptr::null_mut(),
                 info_log.as_mut_ptr() as *mut gl::types::GLchar,
             );
-            println!("ERROR::SHADER::PROGRAM::COMPILATION_FAILED\n{}", String::from_utf8_lossy(&info_log));
-            return false;
+            return Err(info_log_to_string(info_log));
         }
-        true
+        Ok(())
     }
 
     #[must_use = "The shader program is useless if not stored in a variable."]
-    pub unsafe fn link(self) -> Shader {
-        for &shader in &self.shaders {
-            gl::AttachShader(self.program_id, shader);
-        }
-        gl::LinkProgram(self.program_id);
+    pub unsafe fn link(mut self) -> Result<Shader, ShaderError> {
+        let cache = self.cache.clone().filter(|_| ProgramCache::is_supported());
+        let key = cache.as_ref().map(|_| {
+            let sources: Vec<_> = self.sources.iter()
+                .map(|stage| (stage.shader_type.into(), stage.preprocessed.source.as_str()))
+                .collect();
+            ProgramCache::key(&sources)
+        });
 
-        // todo:: use this to make safer abstraction
-        self.check_linker_errors();
+        let cached = match (&cache, key) {
+            (Some(cache), Some(key)) => cache.load(key)
+                .map(|binary| program_cache::load_program_binary(self.program_id(), &binary))
+                .unwrap_or(false),
+            _ => false,
+        };
 
-        for &shader in &self.shaders {
-            gl::DeleteShader(shader);
+        if !cached {
+            if cache.is_some() {
+                // A rejected binary may leave the program in an odd state, so start over with a fresh one
+                self.program = Some(Program::new());
+                gl::ProgramParameteri(self.program_id(), gl::PROGRAM_BINARY_RETRIEVABLE_HINT, i32::from(gl::TRUE));
+            }
+            let sources = std::mem::take(&mut self.sources);
+            for stage in &sources {
+                self.compile(&stage.preprocessed, stage.shader_type, stage.file.as_deref())?;
+            }
+            self.sources = sources;
+
+            for &shader in &self.shaders {
+                gl::AttachShader(self.program_id(), shader);
+            }
+            gl::LinkProgram(self.program_id());
+
+            self.check_linker_errors().map_err(|log| ShaderError::Link { log })?;
+
+            if let (Some(cache), Some(key)) = (&cache, key) {
+                // Failing to cache the program only costs time on the next start, so it is not an error
+                let stored = program_cache::get_program_binary(self.program_id())
+                    .map(|binary| cache.store(key, &binary));
+                if let Some(Err(e)) = stored {
+                    println!("WARNING::SHADER::CACHE: failed to write {}: {}", cache.path(key).display(), e);
+                }
+            }
         }
 
-        Shader {
-            program_id: self.program_id
+        // The program is handed over to the Shader, so it is not deleted along with the builder
+        let program = self.program.take().unwrap();
+        let is_compute = self.sources.iter().any(|stage| stage.shader_type == ShaderType::Compute);
+        Ok(Shader {
+            reflection: ProgramReflection::new(program.id(), is_compute),
+            program,
+            stages: std::mem::take(&mut self.stages),
+            watched: std::mem::take(&mut self.watched),
+            cache: self.cache.take(),
+            loaded_from_cache: cached,
+        })
+    }
+}
+
+// Frees the shader objects. The program frees itself unless it was successfully linked into a Shader.
+impl Drop for ShaderBuilder {
+    fn drop(&mut self) {
+        unsafe {
+            for &shader in &self.shaders {
+                gl::DeleteShader(shader);
+            }
         }
     }
 }
+
+fn info_log_to_string(mut info_log: Vec<u8>) -> String {
+    if let Some(end) = info_log.iter().position(|&c| c == 0) {
+        info_log.truncate(end);
+    }
+    String::from_utf8_lossy(&info_log).trim_end().to_string()
+}
@@ -0,0 +1,374 @@
+extern crate nalgebra_glm as glm;
+
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+// Triangle mesh simplification by edge collapse, ordered by the quadric error metric of Garland and Heckbert.
+//
+// Vertices are only ever collapsed onto a neighbouring vertex, so the result is a new index buffer into the
+// original vertices and no attribute needs interpolating. Vertices on attribute seams, where several vertices
+// share a position, are kept where they are, so textures don't tear. Vertices on open borders may only slide
+// along the border, and constraint planes through the border edges make that costly anywhere it isn't straight.
+
+// How much more a border constraint weighs than the triangle it belongs to
+const BORDER_WEIGHT: f64 = 10.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SimplifyOptions {
+    // Stop before any collapse that would put the surface further than this from where it was, in model units,
+    // even if the target triangle count isn't reached
+    pub max_error: f32,
+    // Keep every border vertex, rather than letting them slide along straight borders
+    pub lock_borders: bool,
+}
+
+impl Default for SimplifyOptions {
+    fn default() -> SimplifyOptions {
+        SimplifyOptions { max_error: f32::INFINITY, lock_borders: false }
+    }
+}
+
+pub struct Simplified {
+    pub indices: Vec<u32>,
+    // An estimate of how far the simplified surface is from the original at worst, in model units
+    pub error: f32,
+}
+
+// Collapses edges until at most `target_triangles` triangles are left, or no collapse is allowed or cheap enough
+pub fn simplify(positions: &[f32], indices: &[u32], target_triangles: usize, options: &SimplifyOptions) -> Simplified {
+    let mut simplifier = Simplifier::new(positions, indices, options);
+    let error = simplifier.run(target_triangles, options.max_error as f64);
+    Simplified { indices: simplifier.indices(), error }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Interior,
+    Border,
+    Locked,
+}
+
+// The squared distance to a set of weighted planes, as a symmetric 4x4 matrix (xx, xy, xz, xw, yy, yz, yw, zz,
+// zw, ww) along with the total weight, so the error can be given as an average
+#[derive(Clone, Copy, Default)]
+struct Quadric {
+    matrix: [f64; 10],
+    weight: f64,
+}
+
+impl Quadric {
+    fn plane(normal: glm::DVec3, point: glm::DVec3, weight: f64) -> Quadric {
+        let [a, b, c] = [normal.x, normal.y, normal.z];
+        let d = -normal.dot(&point);
+        let matrix = [a * a, a * b, a * c, a * d, b * b, b * c, b * d, c * c, c * d, d * d];
+        Quadric { matrix: matrix.map(|m| m * weight), weight }
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (m, o) in self.matrix.iter_mut().zip(&other.matrix) {
+            *m += o;
+        }
+        self.weight += other.weight;
+    }
+
+    // The average squared distance of `p` to the planes
+    fn error(&self, p: glm::DVec3) -> f64 {
+        let [xx, xy, xz, xw, yy, yz, yw, zz, zw, ww] = self.matrix;
+        let (x, y, z) = (p.x, p.y, p.z);
+        let error = xx * x * x + 2.0 * xy * x * y + 2.0 * xz * x * z + 2.0 * xw * x
+            + yy * y * y + 2.0 * yz * y * z + 2.0 * yw * y
+            + zz * z * z + 2.0 * zw * z
+            + ww;
+        if self.weight > 0.0 { error.max(0.0) / self.weight } else { 0.0 }
+    }
+}
+
+// A possible collapse of `from` onto `to`, valid as long as neither position has changed since
+struct Candidate {
+    cost: f64,
+    from: u32,
+    to: u32,
+    versions: (u32, u32),
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Candidate) -> bool {
+        self.cost == other.cost
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Candidate) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    // Reversed, so the binary heap pops the cheapest collapse first
+    fn cmp(&self, other: &Candidate) -> Ordering {
+        other.cost.partial_cmp(&self.cost).unwrap_or(Ordering::Equal)
+    }
+}
+
+struct Simplifier {
+    positions: Vec<glm::DVec3>,
+    // Vertices at the same position share a position id, and quadrics, kinds and versions are per position
+    position_ids: Vec<usize>,
+    position_vertices: Vec<Vec<u32>>,
+    kinds: Vec<Kind>,
+    quadrics: Vec<Quadric>,
+    versions: Vec<u32>,
+    triangles: Vec<[u32; 3]>,
+    alive: Vec<bool>,
+    alive_count: usize,
+    vertex_triangles: Vec<Vec<usize>>,
+    candidates: BinaryHeap<Candidate>,
+}
+
+impl Simplifier {
+    fn new(positions: &[f32], indices: &[u32], options: &SimplifyOptions) -> Simplifier {
+        let vertex_count = positions.len() / 3;
+        let triangles: Vec<[u32; 3]> = indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect();
+
+        // Adding 0 turns -0 into 0, so the two compare equal
+        let mut welded = HashMap::<[u32; 3], usize>::new();
+        let mut position_ids = Vec::with_capacity(vertex_count);
+        let mut position_vertices = Vec::<Vec<u32>>::new();
+        let mut unique_positions = vec![];
+        for vertex in 0..vertex_count {
+            let p = &positions[vertex * 3..vertex * 3 + 3];
+            let id = *welded.entry([p[0], p[1], p[2]].map(|c| (c + 0.0).to_bits())).or_insert_with(|| {
+                position_vertices.push(vec![]);
+                unique_positions.push(glm::vec3(p[0] as f64, p[1] as f64, p[2] as f64));
+                position_vertices.len() - 1
+            });
+            position_ids.push(id);
+            position_vertices[id].push(vertex as u32);
+        }
+
+        let mut simplifier = Simplifier {
+            positions: unique_positions,
+            kinds: vec![Kind::Interior; position_vertices.len()],
+            quadrics: vec![Quadric::default(); position_vertices.len()],
+            versions: vec![0; position_vertices.len()],
+            position_ids,
+            position_vertices,
+            alive: vec![true; triangles.len()],
+            alive_count: triangles.len(),
+            vertex_triangles: vec![vec![]; vertex_count],
+            triangles,
+            candidates: BinaryHeap::new(),
+        };
+        simplifier.classify(options.lock_borders);
+        for vertex in 0..vertex_count as u32 {
+            simplifier.push_candidates(vertex);
+        }
+        simplifier
+    }
+
+    fn position(&self, vertex: u32) -> glm::DVec3 {
+        self.positions[self.position_ids[vertex as usize]]
+    }
+
+    fn face_normal(&self, triangle: [u32; 3]) -> glm::DVec3 {
+        let [a, b, c] = triangle.map(|v| self.position(v));
+        (b - a).cross(&(c - a))
+    }
+
+    // Works out which positions may move, and sets up the quadrics of the original surface
+    fn classify(&mut self, lock_borders: bool) {
+        let mut edges = HashMap::<(usize, usize), usize>::new();
+        for (t, triangle) in self.triangles.iter().enumerate() {
+            for k in 0..3 {
+                self.vertex_triangles[triangle[k] as usize].push(t);
+                let (a, b) = (self.position_ids[triangle[k] as usize], self.position_ids[triangle[(k + 1) % 3] as usize]);
+                *edges.entry((a.min(b), a.max(b))).or_default() += 1;
+            }
+        }
+
+        for &triangle in &self.triangles {
+            let normal = self.face_normal(triangle);
+            let area = normal.norm() / 2.0;
+            if area == 0.0 {
+                continue;
+            }
+            let plane = Quadric::plane(normal.normalize(), self.position(triangle[0]), area);
+            for &vertex in &triangle {
+                self.quadrics[self.position_ids[vertex as usize]].add(&plane);
+            }
+            // Border edges get a plane through them at right angles to the triangle, which keeps borders in place
+            for k in 0..3 {
+                let (a, b) = (self.position_ids[triangle[k] as usize], self.position_ids[triangle[(k + 1) % 3] as usize]);
+                if edges[&(a.min(b), a.max(b))] != 1 {
+                    continue;
+                }
+                let edge = self.positions[b] - self.positions[a];
+                let constraint_normal = edge.cross(&normal);
+                if constraint_normal.norm() == 0.0 {
+                    continue;
+                }
+                let constraint = Quadric::plane(constraint_normal.normalize(), self.positions[a], edge.norm_squared() * BORDER_WEIGHT);
+                self.quadrics[a].add(&constraint);
+                self.quadrics[b].add(&constraint);
+            }
+        }
+
+        let mut border_edges = vec![0; self.positions.len()];
+        for (&(a, b), &triangles) in &edges {
+            match triangles {
+                1 => {
+                    border_edges[a] += 1;
+                    border_edges[b] += 1;
+                },
+                2 => {},
+                // Edges shared by more than two triangles are too tangled to touch
+                _ => {
+                    self.kinds[a] = Kind::Locked;
+                    self.kinds[b] = Kind::Locked;
+                },
+            }
+        }
+        for (id, kind) in self.kinds.iter_mut().enumerate() {
+            if *kind == Kind::Locked {
+                continue;
+            }
+            *kind = match border_edges[id] {
+                _ if self.position_vertices[id].len() > 1 => Kind::Locked,
+                0 => Kind::Interior,
+                2 if !lock_borders => Kind::Border,
+                _ => Kind::Locked,
+            };
+        }
+    }
+
+    // Queues every collapse of the edges around `vertex`, in both directions
+    fn push_candidates(&mut self, vertex: u32) {
+        let mut pairs = vec![];
+        for &t in &self.vertex_triangles[vertex as usize] {
+            if !self.alive[t] {
+                continue;
+            }
+            for &other in &self.triangles[t] {
+                if other != vertex {
+                    pairs.push((vertex, other));
+                    pairs.push((other, vertex));
+                }
+            }
+        }
+        for (from, to) in pairs {
+            let (from_id, to_id) = (self.position_ids[from as usize], self.position_ids[to as usize]);
+            if self.kinds[from_id] == Kind::Locked || from_id == to_id {
+                continue;
+            }
+            let mut quadric = self.quadrics[from_id];
+            quadric.add(&self.quadrics[to_id]);
+            let cost = quadric.error(self.positions[to_id]);
+            self.candidates.push(Candidate { cost, from, to, versions: (self.versions[from_id], self.versions[to_id]) });
+        }
+    }
+
+    fn run(&mut self, target_triangles: usize, max_error: f64) -> f32 {
+        let max_cost = max_error * max_error;
+        let mut error = 0.0f64;
+        while self.alive_count > target_triangles {
+            let candidate = match self.candidates.pop() {
+                Some(candidate) => candidate,
+                None => break,
+            };
+            let (from_id, to_id) = (self.position_ids[candidate.from as usize], self.position_ids[candidate.to as usize]);
+            if (self.versions[from_id], self.versions[to_id]) != candidate.versions {
+                continue;
+            }
+            if candidate.cost > max_cost {
+                break;
+            }
+            if !self.can_collapse(candidate.from, candidate.to) {
+                continue;
+            }
+            self.collapse(candidate.from, candidate.to);
+            error = error.max(candidate.cost);
+        }
+        error.sqrt() as f32
+    }
+
+    fn can_collapse(&self, from: u32, to: u32) -> bool {
+        let (from_id, to_id) = (self.position_ids[from as usize], self.position_ids[to as usize]);
+        let shared = self.alive_triangles(from).filter(|&t| self.triangle_has_position(t, to_id)).count();
+        // Border vertices may only move along the border
+        match self.kinds[from_id] {
+            Kind::Locked => return false,
+            Kind::Border if shared != 1 => return false,
+            _ if shared == 0 => return false,
+            _ => {},
+        }
+
+        // The link condition: the two ends may have no neighbours in common other than across the triangles that
+        // disappear, or the collapse would pinch the surface
+        let common = self.neighbours(from_id).intersection(&self.neighbours(to_id)).count();
+        if common != shared {
+            return false;
+        }
+
+        // Triangles that stay may not flip over or become slivers
+        let destination = self.positions[to_id];
+        self.alive_triangles(from).filter(|&t| !self.triangle_has_position(t, to_id)).all(|t| {
+            let before = self.face_normal(self.triangles[t]);
+            let moved = self.triangles[t].map(|v| if v == from { destination } else { self.position(v) });
+            let after = (moved[1] - moved[0]).cross(&(moved[2] - moved[0]));
+            after.norm() > 0.0 && before.dot(&after) > 0.0
+        })
+    }
+
+    fn collapse(&mut self, from: u32, to: u32) {
+        let (from_id, to_id) = (self.position_ids[from as usize], self.position_ids[to as usize]);
+        let triangles = std::mem::take(&mut self.vertex_triangles[from as usize]);
+        for t in triangles {
+            if !self.alive[t] {
+                continue;
+            }
+            if self.triangle_has_position(t, to_id) {
+                self.alive[t] = false;
+                self.alive_count -= 1;
+            } else {
+                for vertex in self.triangles[t].iter_mut() {
+                    if *vertex == from {
+                        *vertex = to;
+                    }
+                }
+                self.vertex_triangles[to as usize].push(t);
+            }
+        }
+        let from_quadric = self.quadrics[from_id];
+        self.quadrics[to_id].add(&from_quadric);
+        self.versions[from_id] += 1;
+        self.versions[to_id] += 1;
+        for vertex in self.position_vertices[to_id].clone() {
+            self.push_candidates(vertex);
+        }
+    }
+
+    fn alive_triangles(&self, vertex: u32) -> impl Iterator<Item = usize> + '_ {
+        self.vertex_triangles[vertex as usize].iter().copied().filter(move |&t| self.alive[t])
+    }
+
+    fn triangle_has_position(&self, triangle: usize, id: usize) -> bool {
+        self.triangles[triangle].iter().any(|&v| self.position_ids[v as usize] == id)
+    }
+
+    // The positions sharing a triangle with position `id`
+    fn neighbours(&self, id: usize) -> HashSet<usize> {
+        self.position_vertices[id].iter()
+            .flat_map(|&vertex| self.alive_triangles(vertex))
+            .flat_map(|t| self.triangles[t].iter().map(|&v| self.position_ids[v as usize]))
+            .filter(|&other| other != id)
+            .collect()
+    }
+
+    fn indices(&self) -> Vec<u32> {
+        self.triangles.iter().zip(&self.alive).filter(|(_, &alive)| alive).flat_map(|(t, _)| t.iter().copied()).collect()
+    }
+}
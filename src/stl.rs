@@ -0,0 +1,162 @@
+extern crate nalgebra_glm as glm;
+
+use std::{fs, path::Path};
+
+use crate::mesh::{Encoding, Mesh};
+use crate::normals;
+use crate::optimize;
+
+// STL meshes, as CAD tools export parts, in ASCII or binary.
+//
+// STL files are lists of triangles with a normal each and no shared vertices. Corners at the same position with
+// the same normal are merged when loading, so every facet keeps its own normal and the part is shaded flat, as
+// CAD parts are meant to look. Facets without a normal get one from their winding.
+
+// Binary files start with an 80 byte header and the number of triangles, then 50 bytes for each
+const BINARY_HEADER_SIZE: usize = 84;
+const BINARY_TRIANGLE_SIZE: usize = 50;
+
+pub fn load<P: AsRef<Path>>(path: P, color: [f32; 4]) -> Result<Mesh, String> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).map_err(|e| format!("Failed to load STL file {}: {}", path.display(), e))?;
+    parse(&bytes, color).map_err(|e| format!("Failed to load STL file {}: {}", path.display(), e))
+}
+
+// STL has no colours, so the whole mesh is given `color`
+pub fn parse(bytes: &[u8], color: [f32; 4]) -> Result<Mesh, String> {
+    // Binary files may start with "solid" too, so their size, which is known from the header, decides
+    let binary_size = bytes.get(80..84)
+        .map(|count| BINARY_HEADER_SIZE + u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize * BINARY_TRIANGLE_SIZE);
+    let facets = if binary_size == Some(bytes.len()) || !bytes.trim_ascii_start().starts_with(b"solid") {
+        parse_binary(bytes)?
+    } else {
+        parse_ascii(std::str::from_utf8(bytes).map_err(|_| "the file is neither binary nor text")?)?
+    };
+
+    let mut positions = Vec::with_capacity(facets.len() * 9);
+    let mut corner_normals = Vec::with_capacity(facets.len() * 9);
+    for (normal, corners) in &facets {
+        let [a, b, c] = corners.map(|corner| glm::make_vec3(&corner));
+        let given = glm::make_vec3(normal);
+        let normal = if given.norm() > 0.0 { given.normalize() } else { (b - a).cross(&(c - a)) };
+        let normal = if normal.norm() > 0.0 { normal.normalize() } else { normal };
+        for corner in corners {
+            positions.extend_from_slice(corner);
+            corner_normals.extend_from_slice(normal.as_slice());
+        }
+    }
+    let corners: Vec<u32> = (0..facets.len() as u32 * 3).collect();
+    let (remap, indices) = optimize::deduplicate(&[(&positions, 3), (&corner_normals, 3)], &corners);
+    Ok(Mesh {
+        vertices: normals::remap(&positions, 3, &remap),
+        normals: normals::remap(&corner_normals, 3, &remap),
+        uvs: vec![],
+        tangents: vec![],
+        colors: color.repeat(remap.len()),
+        index_count: indices.len() as i32,
+        indices,
+        material: None,
+        levels_of_detail: vec![],
+    })
+}
+
+type Facet = ([f32; 3], [[f32; 3]; 3]);
+
+fn parse_binary(bytes: &[u8]) -> Result<Vec<Facet>, String> {
+    if bytes.len() < BINARY_HEADER_SIZE {
+        return Err("the file is truncated".to_string());
+    }
+    let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+    let body = &bytes[BINARY_HEADER_SIZE..];
+    if body.len() / BINARY_TRIANGLE_SIZE < count {
+        return Err(format!("the file is truncated, it has room for {} of its {} triangles", body.len() / BINARY_TRIANGLE_SIZE, count));
+    }
+    let vector = |bytes: &[u8]| {
+        let float = |i: usize| f32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        [float(0), float(4), float(8)]
+    };
+    // The last two bytes of each triangle are an attribute most tools leave at 0
+    Ok(body.chunks_exact(BINARY_TRIANGLE_SIZE).take(count)
+        .map(|triangle| (vector(&triangle[0..12]), [vector(&triangle[12..24]), vector(&triangle[24..36]), vector(&triangle[36..48])]))
+        .collect())
+}
+
+fn parse_ascii(text: &str) -> Result<Vec<Facet>, String> {
+    let mut facets = vec![];
+    let mut words = text.split_ascii_whitespace();
+    let vector = |words: &mut std::str::SplitAsciiWhitespace| -> Result<[f32; 3], String> {
+        let mut number = || {
+            let word = words.next().ok_or("the file is truncated")?;
+            word.parse::<f32>().map_err(|_| format!("{} is not a number", word))
+        };
+        Ok([number()?, number()?, number()?])
+    };
+    while let Some(word) = words.next() {
+        // The name after solid and endsolid, and the loop keywords, need nothing done
+        if word != "facet" {
+            continue;
+        }
+        if words.next() != Some("normal") {
+            return Err("a facet has no normal".to_string());
+        }
+        let normal = vector(&mut words)?;
+        let mut corners = vec![];
+        // Until endfacet, reading every vertex of the outer loop
+        loop {
+            match words.next().ok_or("the file is truncated")? {
+                "vertex" => corners.push(vector(&mut words)?),
+                "endfacet" => break,
+                _ => {}
+            }
+        }
+        match corners.as_slice() {
+            &[a, b, c] => facets.push((normal, [a, b, c])),
+            _ => return Err(format!("a facet has {} vertices rather than 3", corners.len())),
+        }
+    }
+    Ok(facets)
+}
+
+pub fn save<P: AsRef<Path>>(mesh: &Mesh, path: P, encoding: Encoding) -> Result<(), String> {
+    let path = path.as_ref();
+    fs::write(path, encode(mesh, encoding)).map_err(|e| format!("Failed to write STL file {}: {}", path.display(), e))
+}
+
+// Writes every triangle with the normal of its face, as STL has no other attributes
+pub fn encode(mesh: &Mesh, encoding: Encoding) -> Vec<u8> {
+    let corner = |vertex: u32| glm::make_vec3(&mesh.vertices[vertex as usize * 3..vertex as usize * 3 + 3]);
+    let facets = mesh.indices.chunks_exact(3).map(|triangle| {
+        let [a, b, c] = [corner(triangle[0]), corner(triangle[1]), corner(triangle[2])];
+        let normal = (b - a).cross(&(c - a));
+        let normal = if normal.norm() > 0.0 { normal.normalize() } else { normal };
+        (normal, [a, b, c])
+    });
+
+    match encoding {
+        Encoding::Ascii => {
+            let mut text = "solid gloom-rs\n".to_string();
+            for (normal, corners) in facets {
+                text += &format!("  facet normal {} {} {}\n    outer loop\n", normal.x, normal.y, normal.z);
+                for corner in &corners {
+                    text += &format!("      vertex {} {} {}\n", corner.x, corner.y, corner.z);
+                }
+                text += "    endloop\n  endfacet\n";
+            }
+            text += "endsolid gloom-rs\n";
+            text.into_bytes()
+        }
+        Encoding::Binary => {
+            // Binary headers must not start with "solid", or readers may take the file for ASCII
+            let mut bytes = b"binary STL written by gloom-rs".to_vec();
+            bytes.resize(80, 0);
+            bytes.extend_from_slice(&(mesh.indices.len() as u32 / 3).to_le_bytes());
+            for (normal, corners) in facets {
+                for vector in std::iter::once(&normal).chain(&corners) {
+                    bytes.extend(vector.iter().flat_map(|x| x.to_le_bytes()));
+                }
+                bytes.extend_from_slice(&[0, 0]);
+            }
+            bytes
+        }
+    }
+}
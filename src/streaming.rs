@@ -0,0 +1,190 @@
+use std::{
+    marker::PhantomData,
+    ptr,
+};
+
+use crate::gl_objects::{Buffer, VertexArray};
+use crate::vertex_layout::{VertexLayout, VertexSource};
+
+// Vertex data rewritten every frame, streamed through a persistently mapped ring buffer.
+//
+// The buffer is split into sections, and each frame writes the next one while the GPU may still be drawing from
+// the others. A fence after each section's draws keeps the CPU from overwriting a section before the GPU is done
+// with it. With three sections that only happens if the GPU falls more than two frames behind.
+
+// How long to wait for the GPU to release a section before giving up and writing anyway
+const FENCE_TIMEOUT_NS: u64 = 1_000_000_000;
+
+pub struct RingBuffer {
+    buffer: Buffer,
+    mapping: *mut u8,
+    section_size: usize,
+    // One per section, null until the section has been used
+    fences: Vec<gl::types::GLsync>,
+    current: usize,
+    // Keeps the ring on the context thread, like the types in gl_objects
+    _thread: PhantomData<*const ()>,
+}
+
+impl RingBuffer {
+    // Needs glBufferStorage, which is core in OpenGL 4.4 and otherwise comes with ARB_buffer_storage
+    pub unsafe fn is_supported() -> bool {
+        gl::BufferStorage::is_loaded() && gl::MapBufferRange::is_loaded()
+    }
+
+    pub unsafe fn new(target: gl::types::GLenum, section_size: usize, sections: usize) -> Result<RingBuffer, String> {
+        if !RingBuffer::is_supported() {
+            return Err("Persistently mapped buffers need OpenGL 4.4 or ARB_buffer_storage".to_string());
+        }
+        assert!(sections > 0 && section_size > 0, "A ring buffer needs at least one non-empty section");
+        // Sections start at multiples of 256 bytes, which satisfies every offset alignment OpenGL asks for
+        let section_size = section_size.next_multiple_of(256);
+        let size = section_size * sections;
+
+        // Set up through gl::COPY_WRITE_BUFFER, as binding to `target` could change the state of a vertex array
+        let buffer = Buffer::new(target);
+        gl::BindBuffer(gl::COPY_WRITE_BUFFER, buffer.id());
+        let flags = gl::MAP_WRITE_BIT | gl::MAP_PERSISTENT_BIT | gl::MAP_COHERENT_BIT;
+        gl::BufferStorage(gl::COPY_WRITE_BUFFER, size as isize, ptr::null(), flags);
+        let mapping = gl::MapBufferRange(gl::COPY_WRITE_BUFFER, 0, size as isize, flags) as *mut u8;
+        if mapping.is_null() {
+            return Err(format!("Failed to map a ring buffer of {} bytes", size));
+        }
+
+        Ok(RingBuffer {
+            buffer,
+            mapping,
+            section_size,
+            fences: vec![ptr::null(); sections],
+            current: sections - 1,
+            _thread: PhantomData,
+        })
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    pub fn section_size(&self) -> usize {
+        self.section_size
+    }
+
+    // Copies `data` into the next section and returns the byte offset of that section in the buffer.
+    // Every draw reading the previous section must have been issued before calling this, as the previous
+    // section is fenced off here.
+    pub unsafe fn write<T: Copy>(&mut self, data: &[T]) -> usize {
+        let size = std::mem::size_of_val(data);
+        assert!(size <= self.section_size, "Writing {} bytes to a ring buffer section of {} bytes", size, self.section_size);
+
+        self.fences[self.current] = gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+        self.current = (self.current + 1) % self.fences.len();
+        self.wait_for(self.current);
+
+        let offset = self.current * self.section_size;
+        ptr::copy_nonoverlapping(data.as_ptr() as *const u8, self.mapping.add(offset), size);
+        offset
+    }
+
+    unsafe fn wait_for(&mut self, section: usize) {
+        let fence = std::mem::replace(&mut self.fences[section], ptr::null());
+        if fence.is_null() {
+            return;
+        }
+        let status = gl::ClientWaitSync(fence, gl::SYNC_FLUSH_COMMANDS_BIT, FENCE_TIMEOUT_NS);
+        if status == gl::TIMEOUT_EXPIRED || status == gl::WAIT_FAILED {
+            println!("WARNING::RING_BUFFER: the GPU did not finish with section {} in time", section);
+        }
+        gl::DeleteSync(fence);
+    }
+}
+
+impl Drop for RingBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            for &fence in &self.fences {
+                if !fence.is_null() {
+                    gl::DeleteSync(fence);
+                }
+            }
+            gl::BindBuffer(gl::COPY_WRITE_BUFFER, self.buffer.id());
+            gl::UnmapBuffer(gl::COPY_WRITE_BUFFER);
+        }
+    }
+}
+
+// A mesh whose vertices are replaced every frame, for deforming geometry such as animated terrain.
+// The vertices are streamed through a RingBuffer in an interleaved layout, while the indices stay fixed.
+pub struct StreamingMesh {
+    vao: VertexArray,
+    ring: RingBuffer,
+    layout: VertexLayout,
+    max_vertices: usize,
+    pub index_count: i32,
+}
+
+impl StreamingMesh {
+    // Room for `max_vertices` vertices a frame in `layout`, which is used interleaved whatever it says
+    pub unsafe fn new(layout: &VertexLayout, max_vertices: usize, indices: &[u32]) -> Result<StreamingMesh, String> {
+        let mut layout = layout.clone();
+        layout.interleaved = true;
+        let stride: usize = layout.attributes.iter().map(|attribute| attribute.size()).sum();
+        let ring = RingBuffer::new(gl::ARRAY_BUFFER, stride * max_vertices.max(1), 3)?;
+
+        let mut vao = VertexArray::new();
+        vao.bind();
+        // Each attribute gets a vertex buffer binding of its own, so a new section can be pointed at with
+        // glBindVertexBuffer without touching the formats
+        for attribute in &layout.attributes {
+            let component_type = attribute.component_type.into();
+            let components = attribute.components as i32;
+            if attribute.integer {
+                gl::VertexAttribIFormat(attribute.location, components, component_type, 0);
+            } else {
+                let normalized = if attribute.normalized { gl::TRUE } else { gl::FALSE };
+                gl::VertexAttribFormat(attribute.location, components, component_type, normalized, 0);
+            }
+            gl::VertexAttribBinding(attribute.location, attribute.location);
+            gl::EnableVertexAttribArray(attribute.location);
+        }
+        vao.attach_buffer(Buffer::from_slice(gl::ELEMENT_ARRAY_BUFFER, indices, gl::STATIC_DRAW));
+        gl::BindVertexArray(0);
+
+        Ok(StreamingMesh { vao, ring, layout, max_vertices, index_count: indices.len() as i32 })
+    }
+
+    pub fn vao(&self) -> &VertexArray {
+        &self.vao
+    }
+
+    pub fn ring(&self) -> &RingBuffer {
+        &self.ring
+    }
+
+    // Uploads this frame's vertices, which must happen before the mesh is first drawn.
+    // Draw the previous frame's vertices before calling this.
+    // Optional attributes the source has no data for are disabled, so the shader reads their default value.
+    pub unsafe fn update(&mut self, source: &dyn VertexSource) -> Result<(), String> {
+        let vertex_count = source.vertex_count();
+        if vertex_count > self.max_vertices {
+            return Err(format!("{} vertices don't fit in a streaming mesh of {}", vertex_count, self.max_vertices));
+        }
+        let packed = match self.layout.pack(source)?.pop() {
+            Some(packed) => packed,
+            None => return Ok(()),
+        };
+
+        let section = self.ring.write(&packed.data);
+        self.vao.bind();
+        for attribute in &self.layout.attributes {
+            match packed.attributes.iter().find(|(packed, _)| packed.location == attribute.location) {
+                Some((_, offset)) => {
+                    gl::BindVertexBuffer(attribute.location, self.ring.buffer().id(), (section + offset) as isize, packed.stride as i32);
+                    gl::EnableVertexAttribArray(attribute.location);
+                },
+                None => gl::DisableVertexAttribArray(attribute.location),
+            }
+        }
+        gl::BindVertexArray(0);
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+// Per-vertex tangents for normal mapping, generated with MikkTSpace so normal maps baked by other tools come out
+// the same here.
+//
+// MikkTSpace works on triangle corners, and corners sharing a vertex can be given different tangents, at a mirrored
+// UV seam say. Such vertices are split like GeneratedNormals splits them.
+
+pub struct GeneratedTangents {
+    // Four per vertex: the tangent, followed by 1 or -1 for the handedness of the tangent space. The bitangent is
+    // cross(normal, tangent.xyz) * tangent.w.
+    pub tangents: Vec<f32>,
+    // The vertex of the input each vertex was made from, see normals::remap
+    pub remap: Vec<u32>,
+    pub indices: Vec<u32>,
+}
+
+// Positions and normals have three components per vertex, and uvs two
+pub fn generate(positions: &[f32], normals: &[f32], uvs: &[f32], indices: &[u32]) -> Result<GeneratedTangents, String> {
+    let vertex_count = positions.len() / 3;
+    if normals.len() != vertex_count * 3 || uvs.len() != vertex_count * 2 {
+        return Err("Tangents need a normal and texture coordinates for every vertex".to_string());
+    }
+
+    let mut geometry = Corners { positions, normals, uvs, indices, tangents: vec![[0.0; 4]; indices.len()] };
+    if !bevy_mikktspace::generate_tangents(&mut geometry) {
+        return Err("MikkTSpace could not generate tangents for the mesh".to_string());
+    }
+
+    let mut tangents = vec![];
+    let mut remap = vec![];
+    let mut output_indices = Vec::with_capacity(indices.len());
+    let mut split_vertices = HashMap::<(u32, [u32; 4]), u32>::new();
+    for (&vertex, tangent) in indices.iter().zip(&geometry.tangents) {
+        let key = (vertex, tangent.map(f32::to_bits));
+        let index = *split_vertices.entry(key).or_insert_with(|| {
+            tangents.extend_from_slice(tangent);
+            remap.push(vertex);
+            remap.len() as u32 - 1
+        });
+        output_indices.push(index);
+    }
+    Ok(GeneratedTangents { tangents, remap, indices: output_indices })
+}
+
+// An indexed triangle mesh as MikkTSpace sees it, collecting a tangent for every corner
+struct Corners<'a> {
+    positions: &'a [f32],
+    normals: &'a [f32],
+    uvs: &'a [f32],
+    indices: &'a [u32],
+    tangents: Vec<[f32; 4]>,
+}
+
+impl Corners<'_> {
+    fn vertex(&self, face: usize, vert: usize) -> usize {
+        self.indices[face * 3 + vert] as usize
+    }
+}
+
+impl bevy_mikktspace::Geometry for Corners<'_> {
+    fn num_faces(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        let i = self.vertex(face, vert) * 3;
+        [self.positions[i], self.positions[i + 1], self.positions[i + 2]]
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        let i = self.vertex(face, vert) * 3;
+        [self.normals[i], self.normals[i + 1], self.normals[i + 2]]
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        let i = self.vertex(face, vert) * 2;
+        [self.uvs[i], self.uvs[i + 1]]
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.tangents[face * 3 + vert] = tangent;
+    }
+}
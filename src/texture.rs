@@ -0,0 +1,207 @@
+use std::{
+    ffi::CStr,
+    marker::PhantomData,
+    path::Path,
+};
+
+// 2D textures loaded from image files, uploaded with a full mipmap chain unless asked otherwise.
+//
+// Images are flipped on upload, so texture coordinate (0, 0) is the bottom left corner of the image as OpenGL and
+// most model formats expect.
+
+// From ARB_texture_filter_anisotropic, core in OpenGL 4.6, which the generated bindings predate
+const TEXTURE_MAX_ANISOTROPY: gl::types::GLenum = 0x84FE;
+const MAX_TEXTURE_MAX_ANISOTROPY: gl::types::GLenum = 0x84FF;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Wrap {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+}
+
+impl From<Wrap> for gl::types::GLenum {
+    fn from(wrap: Wrap) -> gl::types::GLenum {
+        match wrap {
+            Wrap::Repeat => gl::REPEAT,
+            Wrap::MirroredRepeat => gl::MIRRORED_REPEAT,
+            Wrap::ClampToEdge => gl::CLAMP_TO_EDGE,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    Linear,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureOptions {
+    pub wrap: Wrap,
+    pub filter: Filter,
+    pub mipmaps: bool,
+    // The most samples anisotropic filtering may take, 1 turning it off. Clamped to what the driver allows, and
+    // ignored when it has no anisotropic filtering at all.
+    pub anisotropy: f32,
+    // Whether the image holds sRGB encoded colours, which are turned linear when sampled. Colour maps usually are,
+    // while normal maps and other data are linear already. Note that the renderer doesn't encode its output as
+    // sRGB, so sRGB textures only look right once gl::FRAMEBUFFER_SRGB is enabled.
+    pub srgb: bool,
+}
+
+impl Default for TextureOptions {
+    fn default() -> TextureOptions {
+        TextureOptions { wrap: Wrap::Repeat, filter: Filter::Linear, mipmaps: true, anisotropy: 1.0, srgb: false }
+    }
+}
+
+impl TextureOptions {
+    pub fn wrap(mut self, wrap: Wrap) -> TextureOptions {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn filter(mut self, filter: Filter) -> TextureOptions {
+        self.filter = filter;
+        self
+    }
+
+    pub fn mipmaps(mut self, mipmaps: bool) -> TextureOptions {
+        self.mipmaps = mipmaps;
+        self
+    }
+
+    pub fn anisotropy(mut self, anisotropy: f32) -> TextureOptions {
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    pub fn srgb(mut self, srgb: bool) -> TextureOptions {
+        self.srgb = srgb;
+        self
+    }
+}
+
+// Owns the texture object, like the types in gl_objects, and deletes it when dropped
+pub struct Texture {
+    id: u32,
+    width: u32,
+    height: u32,
+    levels: u32,
+    _thread: PhantomData<*const ()>,
+}
+
+impl Texture {
+    // Loads a PNG or JPEG file, or anything else the image crate can decode
+    pub unsafe fn load<P: AsRef<Path>>(path: P, options: &TextureOptions) -> Result<Texture, String> {
+        let path = path.as_ref();
+        let image = image::open(path).map_err(|e| format!("Failed to load texture {}: {}", path.display(), e))?;
+        Ok(Texture::from_image(&image.into_rgba(), options))
+    }
+
+    // Uploads an image whose first row is its top
+    pub unsafe fn from_image(image: &image::RgbaImage, options: &TextureOptions) -> Texture {
+        let (width, height) = image.dimensions();
+        assert!(width > 0 && height > 0, "Textures can't be empty");
+        let flipped = image::imageops::flip_vertical(image);
+        let levels = if options.mipmaps { 32 - width.max(height).leading_zeros() } else { 1 };
+        let internal_format = if options.srgb { gl::SRGB8_ALPHA8 } else { gl::RGBA8 };
+
+        let mut id = 0;
+        gl::GenTextures(1, &mut id);
+        gl::BindTexture(gl::TEXTURE_2D, id);
+        gl::TexStorage2D(gl::TEXTURE_2D, levels as i32, internal_format, width as i32, height as i32);
+        // Rows of RGBA8 pixels are always a multiple of 4 bytes long, so the default unpack alignment is fine
+        gl::TexSubImage2D(gl::TEXTURE_2D, 0, 0, 0, width as i32, height as i32, gl::RGBA, gl::UNSIGNED_BYTE,
+            flipped.as_ptr() as *const std::ffi::c_void);
+        if levels > 1 {
+            gl::GenerateMipmap(gl::TEXTURE_2D);
+        }
+
+        let texture = Texture { id, width, height, levels, _thread: PhantomData };
+        texture.set_options(options);
+        texture
+    }
+
+    // Changes how the texture is sampled, leaving it bound to the active texture unit. Turning mipmaps on only has an
+    // effect if the texture was created with them.
+    pub unsafe fn set_options(&self, options: &TextureOptions) {
+        let wrap = gl::types::GLenum::from(options.wrap) as i32;
+        let mipmapped = options.mipmaps && self.levels > 1;
+        let min_filter = match (options.filter, mipmapped) {
+            (Filter::Nearest, false) => gl::NEAREST,
+            (Filter::Linear, false) => gl::LINEAR,
+            (Filter::Nearest, true) => gl::NEAREST_MIPMAP_NEAREST,
+            (Filter::Linear, true) => gl::LINEAR_MIPMAP_LINEAR,
+        };
+        let mag_filter = match options.filter {
+            Filter::Nearest => gl::NEAREST,
+            Filter::Linear => gl::LINEAR,
+        };
+        gl::BindTexture(gl::TEXTURE_2D, self.id);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, wrap);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, wrap);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, min_filter as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, mag_filter as i32);
+        let max_anisotropy = Texture::max_anisotropy();
+        if max_anisotropy > 0.0 {
+            gl::TexParameterf(gl::TEXTURE_2D, TEXTURE_MAX_ANISOTROPY, options.anisotropy.clamp(1.0, max_anisotropy));
+        }
+    }
+
+    // The largest anisotropy the driver allows, or 0 if it can't filter anisotropically
+    pub unsafe fn max_anisotropy() -> f32 {
+        if !has_anisotropic_filtering() {
+            return 0.0;
+        }
+        let mut max = 0.0;
+        gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut max);
+        max
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    // The number of mipmap levels, 1 being just the image itself
+    pub fn levels(&self) -> u32 {
+        self.levels
+    }
+
+    // Binds the texture to a texture unit, with 0 meaning gl::TEXTURE0. See Shader::bind_texture for also
+    // pointing a sampler at it.
+    pub unsafe fn bind(&self, unit: u32) {
+        gl::ActiveTexture(gl::TEXTURE0 + unit);
+        gl::BindTexture(gl::TEXTURE_2D, self.id);
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteTextures(1, &self.id) };
+    }
+}
+
+unsafe fn has_anisotropic_filtering() -> bool {
+    let (mut major, mut minor) = (0, 0);
+    gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+    gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+    if (major, minor) >= (4, 6) {
+        return true;
+    }
+    let mut count = 0;
+    gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+    (0..count.max(0) as u32).any(|i| {
+        let name = CStr::from_ptr(gl::GetStringi(gl::EXTENSIONS, i) as *const _).to_bytes();
+        name == b"GL_ARB_texture_filter_anisotropic" || name == b"GL_EXT_texture_filter_anisotropic"
+    })
+}
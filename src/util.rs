@@ -1,10 +1,36 @@
-use std::ffi::CString;
+use std::{ mem, os::raw::c_void, ffi::CStr };
+
+// == // Helper functions to make interacting with OpenGL a little bit prettier. You *WILL* need these! // == //
+// The names should be pretty self explanatory
+pub fn byte_size_of_array<T>(val: &[T]) -> isize {
+    std::mem::size_of_val(val) as isize
+}
+
+// Get the OpenGL-compatible pointer to an arbitrary array of numbers
+pub fn pointer_to_array<T>(val: &[T]) -> *const c_void {
+    &val[0] as *const T as *const c_void
+}
+
+// Get the size of the given type in bytes
+pub fn size_of<T>() -> i32 {
+    mem::size_of::<T>() as i32
+}
+
+// Get an offset in bytes for n units of type T
+pub fn offset<T>(n: u32) -> *const c_void {
+    (n * mem::size_of::<T>() as u32) as *const T as *const c_void
+}
+
+// Get a null pointer (equivalent to an offset of 0)
+// ptr::null()
 
 pub unsafe fn get_gl_string(name: gl::types::GLenum) -> String {
-    std::ffi::CStr::from_ptr(gl::GetString(name) as *mut i8).to_string_lossy().to_string()
+    CStr::from_ptr(gl::GetString(name) as *mut i8).to_string_lossy().to_string()
 }
 
 // Debug callback to panic upon enountering any OpenGL error
+// The signature is dictated by glDebugMessageCallback, which guarantees `msg` is a valid C string
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "system" fn debug_callback(
     source: u32, e_type: u32, id: u32,
     severity: u32, _length: i32,
@@ -22,8 +48,7 @@ pub extern "system" fn debug_callback(
             _ => "unknown",
         };
         unsafe {
-            let string = CString::from_raw(msg as *mut i8);
-            let error_message = String::from_utf8_lossy(string.as_bytes()).to_string();
+            let error_message = CStr::from_ptr(msg).to_string_lossy().to_string();
             panic!("{}: Error of severity {} raised from {}: {}\n",
                 id, severity_string, source, error_message);
         }
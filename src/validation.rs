@@ -0,0 +1,451 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use naga::front::glsl;
+
+use crate::preprocessor;
+use crate::shader::{ShaderError, ShaderType};
+
+// Checks GLSL shaders without an OpenGL context, using naga as the compiler front-end.
+//
+// naga follows the Vulkan flavour of GLSL, so each source is first rewritten into an equivalent it accepts:
+// the version becomes 450, loose uniforms are wrapped in uniform blocks, combined samplers are split into a
+// texture and a sampler, booleans are stored as integers, and every opaque uniform and block gets a binding.
+// Each line of the rewrite comes from one line of the original files, so errors still point at the right place.
+
+// A problem found in a shader. `line` is 0 when the problem can't be pinned to a line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.file.display(), self.message)
+        } else {
+            write!(f, "{}:{}: {}", self.file.display(), self.line, self.message)
+        }
+    }
+}
+
+// A user-defined input or output of a shader stage with an explicit location
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InterfaceVariable {
+    pub name: String,
+    pub location: u32,
+    pub type_name: String,
+    // Where the variable is declared, if it could be found
+    pub file: PathBuf,
+    pub line: usize,
+}
+
+// The locations a validated stage reads from and writes to
+#[derive(Clone, Debug)]
+pub struct StageInterface {
+    pub stage: ShaderType,
+    pub file: PathBuf,
+    pub inputs: Vec<InterfaceVariable>,
+    pub outputs: Vec<InterfaceVariable>,
+}
+
+// The outcome of validating a directory of shaders
+#[derive(Debug, Default)]
+pub struct Report {
+    pub validated: Vec<PathBuf>,
+    // Shaders of stages naga can't parse (tessellation and geometry)
+    pub skipped: Vec<PathBuf>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+}
+
+// Validates every shader in `dir`, recognized by its extension as in ShaderBuilder::attach_file.
+// Shaders sharing a file stem are treated as one program, and the outputs of its vertex shader are checked
+// against the inputs of its fragment shader.
+pub fn validate_directory(dir: &Path) -> Report {
+    let mut report = Report::default();
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            report.diagnostics.push(Diagnostic { file: dir.to_path_buf(), line: 0, message: e.to_string() });
+            return report;
+        },
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().and_then(|e| ShaderType::from_ext(e).ok()).is_some())
+        .collect();
+    paths.sort();
+
+    let mut interfaces = vec![];
+    for path in paths {
+        let stage = path.extension().and_then(|e| ShaderType::from_ext(e).ok()).unwrap();
+        if !is_supported(stage) {
+            report.skipped.push(path);
+            continue;
+        }
+        match validate_file(&path, stage, &[]) {
+            Ok(interface) => interfaces.push(interface),
+            Err(mut diagnostics) => report.diagnostics.append(&mut diagnostics),
+        }
+        report.validated.push(path);
+    }
+
+    for fragment in interfaces.iter().filter(|i| i.stage == ShaderType::Fragment) {
+        let vertex = interfaces.iter()
+            .find(|i| i.stage == ShaderType::Vertex && i.file.file_stem() == fragment.file.file_stem());
+        if let Some(vertex) = vertex {
+            report.diagnostics.append(&mut check_interface(vertex, fragment));
+        }
+    }
+    report
+}
+
+// Whether naga can validate shaders of this stage
+pub fn is_supported(stage: ShaderType) -> bool {
+    matches!(stage, ShaderType::Vertex | ShaderType::Fragment | ShaderType::Compute)
+}
+
+// Validates a single shader file, with includes expanded and `defines` injected as ShaderBuilder would
+pub fn validate_file(path: &Path, stage: ShaderType, defines: &[(String, String)]) -> Result<StageInterface, Vec<Diagnostic>> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| vec![Diagnostic { file: path.to_path_buf(), line: 0, message: e.to_string() }])?;
+    validate_source(&source, stage, Some(path), defines)
+}
+
+// Validates GLSL source, `origin` being the file it was read from for resolving includes and reporting errors
+pub fn validate_source(source: &str, stage: ShaderType, origin: Option<&Path>, defines: &[(String, String)]) -> Result<StageInterface, Vec<Diagnostic>> {
+    let origin_path = origin.map(Path::to_path_buf).unwrap_or_else(|| PathBuf::from("<source>"));
+    if !is_supported(stage) {
+        return Err(vec![Diagnostic { file: origin_path, line: 0, message: format!("{:?} shaders can't be validated offline", stage) }]);
+    }
+    let preprocessed = preprocessor::preprocess_source(source, origin, defines)
+        .map_err(|e| vec![shader_error_diagnostic(e, &origin_path)])?;
+    let rewritten = rewrite(&preprocessed.source, &preprocessed.files);
+
+    let naga_stage = match stage {
+        ShaderType::Vertex => naga::ShaderStage::Vertex,
+        ShaderType::Fragment => naga::ShaderStage::Fragment,
+        _ => naga::ShaderStage::Compute,
+    };
+    let mut frontend = glsl::Frontend::default();
+    let module = frontend.parse(&glsl::Options::from(naga_stage), &rewritten.source)
+        .map_err(|errors| {
+            errors.errors.iter()
+                .map(|error| rewritten.diagnostic(error.meta, error.kind.to_string()))
+                .collect::<Vec<_>>()
+        })?;
+
+    let mut validator = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all());
+    if let Err(error) = validator.validate(&module) {
+        let span = error.spans().next().map(|(span, _)| *span).unwrap_or_default();
+        return Err(vec![rewritten.diagnostic(span, error_chain(error.as_inner()))]);
+    }
+
+    Ok(interface(&module, stage, &origin_path, &rewritten))
+}
+
+// Checks that every input of `fragment` is written by `vertex` at the same location with the same type
+pub fn check_interface(vertex: &StageInterface, fragment: &StageInterface) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for input in &fragment.inputs {
+        let message = match vertex.outputs.iter().find(|output| output.location == input.location) {
+            None => format!("input {} at location {} is not written by {}",
+                input.name, input.location, vertex.file.display()),
+            Some(output) if output.type_name != input.type_name => format!(
+                "input {} at location {} is a {}, but {} writes a {} ({}) there",
+                input.name, input.location, input.type_name, vertex.file.display(), output.type_name, output.name),
+            Some(_) => continue,
+        };
+        diagnostics.push(Diagnostic { file: input.file.clone(), line: input.line, message });
+    }
+    diagnostics
+}
+
+fn shader_error_diagnostic(error: ShaderError, origin: &Path) -> Diagnostic {
+    match error {
+        ShaderError::Include { file, line, message } => Diagnostic { file, line, message },
+        ShaderError::Io { path, error } => Diagnostic { file: path, line: 0, message: error.to_string() },
+        error => Diagnostic { file: origin.to_path_buf(), line: 0, message: error.to_string() },
+    }
+}
+
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        message = format!("{}: {}", message, error);
+        source = error.source();
+    }
+    message
+}
+
+fn interface(module: &naga::Module, stage: ShaderType, file: &Path, rewritten: &Rewritten) -> StageInterface {
+    let mut inputs = vec![];
+    let mut outputs = vec![];
+    if let Some(entry_point) = module.entry_points.first() {
+        for argument in &entry_point.function.arguments {
+            collect_locations(module, argument.name.as_deref(), argument.ty, argument.binding.as_ref(), rewritten, &mut inputs);
+        }
+        if let Some(result) = &entry_point.function.result {
+            collect_locations(module, None, result.ty, result.binding.as_ref(), rewritten, &mut outputs);
+        }
+    }
+    inputs.sort_by_key(|v| v.location);
+    outputs.sort_by_key(|v| v.location);
+    StageInterface { stage, file: file.to_path_buf(), inputs, outputs }
+}
+
+fn collect_locations(
+    module: &naga::Module,
+    name: Option<&str>,
+    ty: naga::Handle<naga::Type>,
+    binding: Option<&naga::Binding>,
+    rewritten: &Rewritten,
+    variables: &mut Vec<InterfaceVariable>,
+) {
+    match (binding, &module.types[ty].inner) {
+        (Some(naga::Binding::Location { location, .. }), inner) => {
+            let name = name.unwrap_or_default().to_string();
+            // The front-end keeps each input and output as a global of the same name, which knows where it came from
+            let span = module.global_variables.iter()
+                .find(|(_, global)| global.name.as_deref() == Some(name.as_str()))
+                .map(|(handle, _)| module.global_variables.get_span(handle))
+                .unwrap_or_default();
+            let (file, line) = rewritten.locate(span);
+            variables.push(InterfaceVariable { name, location: *location, type_name: type_name(module, inner), file, line });
+        },
+        (None, naga::TypeInner::Struct { members, .. }) => {
+            for member in members {
+                collect_locations(module, member.name.as_deref(), member.ty, member.binding.as_ref(), rewritten, variables);
+            }
+        },
+        _ => {},
+    }
+}
+
+// The GLSL name of an interface type, for comparing stages and reporting mismatches
+fn type_name(module: &naga::Module, inner: &naga::TypeInner) -> String {
+    let prefix = |scalar: naga::Scalar| match scalar.kind {
+        naga::ScalarKind::Sint => "i",
+        naga::ScalarKind::Uint => "u",
+        naga::ScalarKind::Bool => "b",
+        _ if scalar.width == 8 => "d",
+        _ => "",
+    };
+    match inner {
+        naga::TypeInner::Scalar(scalar) => match scalar.kind {
+            naga::ScalarKind::Sint => "int".to_string(),
+            naga::ScalarKind::Uint => "uint".to_string(),
+            naga::ScalarKind::Bool => "bool".to_string(),
+            _ if scalar.width == 8 => "double".to_string(),
+            _ => "float".to_string(),
+        },
+        naga::TypeInner::Vector { size, scalar } => format!("{}vec{}", prefix(*scalar), *size as u8),
+        naga::TypeInner::Matrix { columns, rows, scalar } => format!("{}mat{}x{}", prefix(*scalar), *columns as u8, *rows as u8),
+        naga::TypeInner::Array { base, size: naga::ArraySize::Constant(size), .. } =>
+            format!("{}[{}]", type_name(module, &module.types[*base].inner), size),
+        other => format!("{:?}", other),
+    }
+}
+
+// Source rewritten for naga, with the original file and line of every line in it
+struct Rewritten {
+    source: String,
+    lines: Vec<(PathBuf, usize)>,
+}
+
+impl Rewritten {
+    fn locate(&self, span: naga::Span) -> (PathBuf, usize) {
+        let line = if span.is_defined() { span.location(&self.source).line_number as usize } else { 0 };
+        match line.checked_sub(1).and_then(|i| self.lines.get(i)) {
+            Some((file, line)) => (file.clone(), *line),
+            None => (self.lines.first().map(|(file, _)| file.clone()).unwrap_or_default(), 0),
+        }
+    }
+
+    fn diagnostic(&self, span: naga::Span, message: String) -> Diagnostic {
+        let (file, line) = self.locate(span);
+        Diagnostic { file, line, message }
+    }
+}
+
+// Rewrites preprocessed source into the GLSL dialect naga accepts, following the #line directives left by
+// the preprocessor to remember where each line came from
+fn rewrite(source: &str, files: &[PathBuf]) -> Rewritten {
+    let mut output = vec![];
+    let mut lines = vec![];
+    let mut macros = vec![];
+    let mut next_binding = 0;
+    let mut file_index = 0;
+    let mut line_number = 1;
+
+    for line in source.lines() {
+        let trimmed = line.trim_start();
+        if let Some(directive) = trimmed.strip_prefix("#line") {
+            let mut numbers = directive.split_whitespace().map(|n| n.parse::<usize>().ok());
+            line_number = numbers.next().flatten().unwrap_or(line_number);
+            file_index = numbers.next().flatten().unwrap_or(file_index);
+            continue;
+        }
+        let text = if trimmed.starts_with("#version") {
+            "#version 450 core".to_string()
+        } else {
+            rewrite_uniform(line, &mut next_binding, &mut macros).unwrap_or_else(|| line.to_string())
+        };
+        output.push(text);
+        lines.push((files.get(file_index).cloned().unwrap_or_default(), line_number));
+        line_number += 1;
+    }
+
+    // Macros turn every use of a rewritten uniform back into the original type, defined right after #version
+    let insert_at = output.iter().position(|line| line.trim_start().starts_with("#version")).map_or(0, |i| i + 1);
+    let origin = lines.get(insert_at.saturating_sub(1)).cloned().unwrap_or_default();
+    for (i, (name, expansion)) in macros.into_iter().enumerate() {
+        output.insert(insert_at + i, format!("#define {} {}", name, expansion));
+        lines.insert(insert_at + i, (origin.0.clone(), 0));
+    }
+
+    Rewritten { source: output.join("\n") + "\n", lines }
+}
+
+// Rewrites a single-line declaration of loose uniforms, returning None for any other line.
+// Uniforms whose uses need rewriting too get a macro in `macros`, as (name, expansion).
+fn rewrite_uniform(line: &str, next_binding: &mut u32, macros: &mut Vec<(String, String)>) -> Option<String> {
+    let (layout, rest) = split_layout(line.trim());
+    let words: Vec<&str> = rest.split_whitespace().collect();
+    let uniform = words.iter().position(|&w| w == "uniform" || w == "buffer")?;
+    // Anything but qualifiers in front of the keyword means this is not a declaration, a comment say
+    if !words[..uniform].iter().all(|w| is_qualifier(w)) {
+        return None;
+    }
+    let is_buffer = words[uniform] == "buffer";
+
+    // Add a binding to blocks which lack one, and leave the rest of the block untouched
+    if !line.contains(';') || line.contains('{') {
+        if layout.iter().any(|q| q.starts_with("binding")) {
+            return None;
+        }
+        let mut layout = layout;
+        layout.push(format!("binding = {}", next_binding));
+        *next_binding += 1;
+        return Some(format!("layout({}) {}", layout.join(", "), words.join(" ")));
+    }
+    if is_buffer {
+        return None;
+    }
+
+    // What is left is "[qualifiers] type name[, name];", possibly with an initializer
+    let declaration = words[..uniform].iter().chain(&words[uniform + 1..]).copied().collect::<Vec<_>>().join(" ");
+    let declaration = declaration.trim_end_matches(';').trim();
+    let qualifiers: Vec<&str> = declaration.split_whitespace().take_while(|w| is_qualifier(w)).collect();
+    let type_and_names = declaration.split_whitespace().skip(qualifiers.len()).collect::<Vec<_>>().join(" ");
+    let type_end = type_and_names.find(' ')?;
+    let ty = &type_and_names[..type_end];
+    // Initializers are dropped, block members can't have them
+    let names: Vec<&str> = split_top_level(&type_and_names[type_end..])
+        .into_iter()
+        .map(|name| name.split('=').next().unwrap_or_default().trim())
+        .collect();
+
+    let layout: Vec<String> = layout.into_iter().filter(|q| !q.starts_with("location")).collect();
+    let mut declarations = vec![];
+    for name in names {
+        let mut binding = |layout: &[String]| {
+            let mut layout = layout.to_vec();
+            layout.push(format!("binding = {}", next_binding));
+            *next_binding += 1;
+            format!("layout({})", layout.join(", "))
+        };
+        let bare_name = name.split('[').next().unwrap_or(name).trim();
+        if let Some((texture, sampler)) = split_sampler(ty) {
+            declarations.push(format!("{} uniform {} {}_texture; {} uniform {} {}_sampler;",
+                binding(&[]), texture, bare_name, binding(&[]), sampler, bare_name));
+            macros.push((bare_name.to_string(), format!("{}({}_texture, {}_sampler)", ty, bare_name, bare_name)));
+        } else if ty.contains("image") {
+            declarations.push(format!("{} uniform {} {} {};", binding(&layout), qualifiers.join(" "), ty, name));
+        } else if let (Some(integer_ty), false) = (boolean_storage(ty), name.contains('[')) {
+            // Blocks can't hold booleans
+            declarations.push(format!("{} uniform _{}_block {{ {} {}_bits; }};", binding(&[]), bare_name, integer_ty, bare_name));
+            macros.push((bare_name.to_string(), format!("{}({}_bits)", ty, bare_name)));
+        } else {
+            declarations.push(format!("{} uniform _{}_block {{ {} {}; }};", binding(&[]), bare_name, ty, name));
+        }
+    }
+    Some(declarations.join(" "))
+}
+
+// Splits "layout(a, b) rest" into (["a", "b"], "rest"). Layout qualifiers may also follow the storage qualifier.
+fn split_layout(line: &str) -> (Vec<String>, String) {
+    let start = match line.find("layout") {
+        Some(start) => start,
+        None => return (vec![], line.to_string()),
+    };
+    let open = match line[start..].find('(') {
+        Some(open) => start + open,
+        None => return (vec![], line.to_string()),
+    };
+    let close = match line[open..].find(')') {
+        Some(close) => open + close,
+        None => return (vec![], line.to_string()),
+    };
+    let qualifiers = line[open + 1..close].split(',').map(|q| q.trim().to_string()).filter(|q| !q.is_empty()).collect();
+    (qualifiers, format!("{} {}", &line[..start], &line[close + 1..]))
+}
+
+// Splits on the commas which are not inside parentheses, so "a = vec2(1, 2), b" gives "a = vec2(1, 2)" and "b"
+fn split_top_level(list: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in list.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&list[start..i]);
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    parts.push(&list[start..]);
+    parts
+}
+
+fn is_qualifier(word: &str) -> bool {
+    matches!(word, "readonly" | "writeonly" | "coherent" | "volatile" | "restrict" | "lowp" | "mediump" | "highp" | "flat")
+}
+
+// The unsigned integer type a boolean type is stored as in a uniform block, e.g. uvec3 for bvec3
+fn boolean_storage(ty: &str) -> Option<String> {
+    match ty {
+        "bool" => Some("uint".to_string()),
+        t if t.starts_with("bvec") => Some(format!("u{}", &t[1..])),
+        _ => None,
+    }
+}
+
+// The separate texture and sampler types a combined sampler type splits into, e.g. sampler2DShadow into
+// texture2D and samplerShadow
+fn split_sampler(ty: &str) -> Option<(String, &'static str)> {
+    let (prefix, rest) = match ty {
+        t if t.starts_with("isampler") => ("i", &t["isampler".len()..]),
+        t if t.starts_with("usampler") => ("u", &t["usampler".len()..]),
+        t if t.starts_with("sampler") && t.len() > "sampler".len() => ("", &t["sampler".len()..]),
+        _ => return None,
+    };
+    match rest.strip_suffix("Shadow") {
+        Some(dimensions) => Some((format!("{}texture{}", prefix, dimensions), "samplerShadow")),
+        None => Some((format!("{}texture{}", prefix, rest), "sampler")),
+    }
+}
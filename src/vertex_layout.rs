@@ -0,0 +1,360 @@
+use std::ops::Range;
+
+use crate::mesh::Mesh;
+use crate::shader::Shader;
+
+// Describes how vertex data is laid out in buffers and which attribute locations it feeds, so the same mesh can be
+// uploaded in whatever form a shader wants: interleaved or one buffer per attribute, as full floats, half floats,
+// normalized integers or plain integers.
+
+// The names attribute data is looked up by, see VertexSource
+pub const POSITION: &str = "position";
+pub const NORMAL: &str = "normal";
+pub const COLOUR: &str = "colour";
+pub const UV: &str = "uv";
+pub const TANGENT: &str = "tangent";
+
+// The type each component of an attribute is stored as in the buffer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ComponentType {
+    F32,
+    F16,
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+}
+
+impl ComponentType {
+    pub fn size(self) -> usize {
+        match self {
+            ComponentType::I8 | ComponentType::U8 => 1,
+            ComponentType::F16 | ComponentType::I16 | ComponentType::U16 => 2,
+            ComponentType::F32 | ComponentType::I32 | ComponentType::U32 => 4,
+        }
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, ComponentType::F32 | ComponentType::F16)
+    }
+}
+
+impl From<ComponentType> for gl::types::GLenum {
+    fn from(component_type: ComponentType) -> gl::types::GLenum {
+        match component_type {
+            ComponentType::F32 => gl::FLOAT,
+            ComponentType::F16 => gl::HALF_FLOAT,
+            ComponentType::I8  => gl::BYTE,
+            ComponentType::U8  => gl::UNSIGNED_BYTE,
+            ComponentType::I16 => gl::SHORT,
+            ComponentType::U16 => gl::UNSIGNED_SHORT,
+            ComponentType::I32 => gl::INT,
+            ComponentType::U32 => gl::UNSIGNED_INT,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VertexAttribute {
+    // Which data of the VertexSource to upload
+    pub name: String,
+    pub location: u32,
+    // 1 to 4, missing components are read by the shader as 0 for y and z and 1 for w
+    pub components: usize,
+    pub component_type: ComponentType,
+    // Integer data is mapped to [0, 1] or [-1, 1] in the shader rather than converted as is
+    pub normalized: bool,
+    // The shader reads the attribute as an int or uint rather than a float
+    pub integer: bool,
+    // Left out, rather than being an error, when the source has no data for it
+    pub optional: bool,
+}
+
+impl VertexAttribute {
+    // A float attribute, refine it with the builder methods below
+    pub fn new(name: &str, location: u32, components: usize) -> VertexAttribute {
+        assert!((1..=4).contains(&components), "Vertex attributes have 1 to 4 components, {} has {}", name, components);
+        VertexAttribute {
+            name: name.to_string(),
+            location,
+            components,
+            component_type: ComponentType::F32,
+            normalized: false,
+            integer: false,
+            optional: false,
+        }
+    }
+
+    pub fn with_type(mut self, component_type: ComponentType) -> VertexAttribute {
+        self.component_type = component_type;
+        self
+    }
+
+    pub fn normalized(mut self) -> VertexAttribute {
+        assert!(!self.component_type.is_float(), "Only integer attributes can be normalized");
+        self.normalized = true;
+        self
+    }
+
+    pub fn integer(mut self) -> VertexAttribute {
+        assert!(!self.component_type.is_float(), "Integer attributes need an integer component type");
+        self.integer = true;
+        self
+    }
+
+    pub fn optional(mut self) -> VertexAttribute {
+        self.optional = true;
+        self
+    }
+
+    // The size of one value of the attribute in the buffer, padded to keep the next attribute 4-byte aligned
+    pub fn size(&self) -> usize {
+        (self.components * self.component_type.size()).next_multiple_of(4)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VertexLayout {
+    pub attributes: Vec<VertexAttribute>,
+    // All attributes in one buffer, one vertex after the other, rather than a buffer for each attribute
+    pub interleaved: bool,
+}
+
+impl VertexLayout {
+    pub fn interleaved(attributes: Vec<VertexAttribute>) -> VertexLayout {
+        VertexLayout { attributes, interleaved: true }
+    }
+
+    pub fn separate(attributes: Vec<VertexAttribute>) -> VertexLayout {
+        VertexLayout { attributes, interleaved: false }
+    }
+
+    // Positions, normals, colours, texture coordinates and tangents at locations 0 to 4 in separate buffers, as
+    // shaders/simple.vert expects. Texture coordinates and tangents may be left out.
+    pub fn standard() -> VertexLayout {
+        VertexLayout::separate(vec![
+            VertexAttribute::new(POSITION, 0, 3),
+            VertexAttribute::new(NORMAL, 1, 3),
+            VertexAttribute::new(COLOUR, 2, 4),
+            VertexAttribute::new(UV, 3, 2).optional(),
+            VertexAttribute::new(TANGENT, 4, 4).optional(),
+        ])
+    }
+
+    // A layout feeding every active attribute of `shader` at its location, by name
+    pub fn for_shader(shader: &Shader, interleaved: bool) -> VertexLayout {
+        let attributes = shader.attributes().iter()
+            // Built-in inputs such as gl_VertexID have no location
+            .filter(|attribute| attribute.location >= 0)
+            .map(|attribute| {
+                let (components, component_type) = match attribute.gl_type {
+                    gl::FLOAT_VEC2 => (2, ComponentType::F32),
+                    gl::FLOAT_VEC3 => (3, ComponentType::F32),
+                    gl::FLOAT_VEC4 => (4, ComponentType::F32),
+                    gl::INT => (1, ComponentType::I32),
+                    gl::INT_VEC2 => (2, ComponentType::I32),
+                    gl::INT_VEC3 => (3, ComponentType::I32),
+                    gl::INT_VEC4 => (4, ComponentType::I32),
+                    gl::UNSIGNED_INT => (1, ComponentType::U32),
+                    gl::UNSIGNED_INT_VEC2 => (2, ComponentType::U32),
+                    gl::UNSIGNED_INT_VEC3 => (3, ComponentType::U32),
+                    gl::UNSIGNED_INT_VEC4 => (4, ComponentType::U32),
+                    _ => (1, ComponentType::F32),
+                };
+                let vertex_attribute = VertexAttribute::new(&attribute.name, attribute.location as u32, components)
+                    .with_type(component_type);
+                if component_type.is_float() { vertex_attribute } else { vertex_attribute.integer() }
+            })
+            .collect();
+        VertexLayout { attributes, interleaved }
+    }
+
+    // Converts the data in `source` into the buffers this layout describes
+    pub fn pack(&self, source: &dyn VertexSource) -> Result<Vec<PackedBuffer>, String> {
+        self.pack_range(source, 0..source.vertex_count())
+    }
+
+    // Like pack, but only converts the given vertices. Used to update part of a mesh already uploaded.
+    pub fn pack_range(&self, source: &dyn VertexSource, vertices: Range<usize>) -> Result<Vec<PackedBuffer>, String> {
+        let vertex_count = source.vertex_count();
+        if vertices.start > vertices.end || vertices.end > vertex_count {
+            return Err(format!("Vertices {:?} are out of range for {} vertices", vertices, vertex_count));
+        }
+        let mut present = vec![];
+        for attribute in &self.attributes {
+            match source.attribute(&attribute.name) {
+                Some((data, components)) => {
+                    if data.len() != vertex_count * components {
+                        return Err(format!("Attribute {} has {} values, expected {} for {} vertices of {} components",
+                            attribute.name, data.len(), vertex_count * components, vertex_count, components));
+                    }
+                    present.push((attribute, data, components));
+                },
+                None if attribute.optional => {},
+                None => return Err(format!("The vertex data has no {} attribute", attribute.name)),
+            }
+        }
+
+        let groups: Vec<Vec<_>> = if self.interleaved {
+            vec![present]
+        } else {
+            present.into_iter().map(|attribute| vec![attribute]).collect()
+        };
+
+        let mut buffers = vec![];
+        for group in groups {
+            if group.is_empty() {
+                continue;
+            }
+            let stride: usize = group.iter().map(|(attribute, _, _)| attribute.size()).sum();
+            let mut data = vec![0u8; stride * vertices.len()];
+            let mut attributes = vec![];
+            let mut offset = 0;
+            for (attribute, values, source_components) in group {
+                for (i, vertex) in vertices.clone().enumerate() {
+                    let start = i * stride + offset;
+                    let source_values = &values[vertex * source_components..(vertex + 1) * source_components];
+                    write_vertex(attribute, source_values, &mut data[start..start + attribute.size()]);
+                }
+                attributes.push((attribute.clone(), offset));
+                offset += attribute.size();
+            }
+            buffers.push(PackedBuffer { data, stride, attributes });
+        }
+        Ok(buffers)
+    }
+}
+
+// Vertex data ready for upload to a single buffer, with the attributes in it and their offsets within a vertex
+#[derive(Clone, Debug)]
+pub struct PackedBuffer {
+    pub data: Vec<u8>,
+    pub stride: usize,
+    pub attributes: Vec<(VertexAttribute, usize)>,
+}
+
+// Per-vertex float data, looked up by attribute name
+pub trait VertexSource {
+    fn vertex_count(&self) -> usize;
+    // The values of the attribute for every vertex, along with how many components each vertex has
+    fn attribute(&self, name: &str) -> Option<(&[f32], usize)>;
+}
+
+impl VertexSource for Mesh {
+    fn vertex_count(&self) -> usize {
+        self.vertices.len() / 3
+    }
+
+    fn attribute(&self, name: &str) -> Option<(&[f32], usize)> {
+        let (data, components) = match name {
+            POSITION => (&self.vertices, 3),
+            NORMAL => (&self.normals, 3),
+            COLOUR | "color" => (&self.colors, 4),
+            UV => (&self.uvs, 2),
+            TANGENT => (&self.tangents, 4),
+            _ => return None,
+        };
+        // tobj leaves out normals and texture coordinates the model file doesn't have, and tangents are generated on demand
+        if data.is_empty() && self.vertex_count() > 0 { None } else { Some((data.as_slice(), components)) }
+    }
+}
+
+// Named float arrays for uploading vertex data that isn't a Mesh
+#[derive(Default)]
+pub struct VertexStreams<'a> {
+    streams: Vec<(&'a str, &'a [f32], usize)>,
+}
+
+impl<'a> VertexStreams<'a> {
+    pub fn new() -> VertexStreams<'a> {
+        VertexStreams::default()
+    }
+
+    pub fn with(mut self, name: &'a str, data: &'a [f32], components: usize) -> VertexStreams<'a> {
+        self.streams.push((name, data, components));
+        self
+    }
+}
+
+impl VertexSource for VertexStreams<'_> {
+    fn vertex_count(&self) -> usize {
+        let (_, data, components) = self.streams.iter()
+            .find(|(name, _, _)| *name == POSITION)
+            .or_else(|| self.streams.first())
+            .copied()
+            .unwrap_or(("", &[], 1));
+        data.len() / components
+    }
+
+    fn attribute(&self, name: &str) -> Option<(&[f32], usize)> {
+        self.streams.iter().find(|(n, _, _)| *n == name).map(|&(_, data, components)| (data, components))
+    }
+}
+
+fn write_vertex(attribute: &VertexAttribute, values: &[f32], out: &mut [u8]) {
+    let size = attribute.component_type.size();
+    for component in 0..attribute.components {
+        // Like OpenGL, fill in components the source doesn't have with 0, 0, 0, 1
+        let value = values.get(component).copied().unwrap_or(if component == 3 { 1.0 } else { 0.0 });
+        let bytes = &mut out[component * size..(component + 1) * size];
+        let normalized = attribute.normalized;
+        match attribute.component_type {
+            ComponentType::F32 => bytes.copy_from_slice(&value.to_ne_bytes()),
+            ComponentType::F16 => bytes.copy_from_slice(&f32_to_f16(value).to_ne_bytes()),
+            ComponentType::I8  => bytes.copy_from_slice(&(to_signed(value, normalized, i8::MAX as f32) as i8).to_ne_bytes()),
+            ComponentType::U8  => bytes.copy_from_slice(&(to_unsigned(value, normalized, u8::MAX as f32) as u8).to_ne_bytes()),
+            ComponentType::I16 => bytes.copy_from_slice(&(to_signed(value, normalized, i16::MAX as f32) as i16).to_ne_bytes()),
+            ComponentType::U16 => bytes.copy_from_slice(&(to_unsigned(value, normalized, u16::MAX as f32) as u16).to_ne_bytes()),
+            ComponentType::I32 => bytes.copy_from_slice(&(to_signed(value, normalized, i32::MAX as f32) as i32).to_ne_bytes()),
+            ComponentType::U32 => bytes.copy_from_slice(&(to_unsigned(value, normalized, u32::MAX as f32) as u32).to_ne_bytes()),
+        }
+    }
+}
+
+// Float to integer casts saturate, so only the normalized range needs clamping
+fn to_signed(value: f32, normalized: bool, max: f32) -> f32 {
+    if normalized { (value.clamp(-1.0, 1.0) * max).round() } else { value.round() }
+}
+
+fn to_unsigned(value: f32, normalized: bool, max: f32) -> f32 {
+    if normalized { (value.clamp(0.0, 1.0) * max).round() } else { value.round() }
+}
+
+// The nearest IEEE 754 half-precision float, with overflow going to infinity
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x007f_ffff;
+
+    if exponent == 0xff {
+        // Infinity, or NaN kept quiet
+        return sign | 0x7c00 | if mantissa != 0 { 0x0200 } else { 0 };
+    }
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if half_exponent <= 0 {
+        // Subnormal, or too small and flushed to zero
+        if half_exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x0080_0000;
+        let shift = (14 - half_exponent) as u32;
+        let half_mantissa = mantissa >> shift;
+        let round_bit = 1 << (shift - 1);
+        let rounded = if mantissa & round_bit != 0 && (mantissa & (3 * round_bit - 1)) != 0 { half_mantissa + 1 } else { half_mantissa };
+        return sign | rounded as u16;
+    }
+    let half = sign as u32 | ((half_exponent as u32) << 10) | (mantissa >> 13);
+    // Round to nearest, ties to even. A carry into the exponent is still the right answer.
+    let round = mantissa & 0x1fff;
+    if round > 0x1000 || (round == 0x1000 && half & 1 == 1) {
+        (half + 1) as u16
+    } else {
+        half as u16
+    }
+}
@@ -0,0 +1,128 @@
+// Helpers shared by the integration tests, each of which uses them through `mod common;`.
+#![allow(dead_code)]
+
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use gloom_rs::headless::HeadlessContext;
+use gloom_rs::mesh::Mesh;
+
+// The test harness runs tests on parallel threads, but only one headless context may exist at a time: the GL
+// function pointers are global to the process, and every EGL context shares the one surfaceless display, which
+// dropping a context terminates
+static GL_LOCK: Mutex<()> = Mutex::new(());
+
+// Something that needs an OpenGL context, holding the lock on contexts until it is dropped
+pub struct WithGl<T> {
+    value: T,
+    _guard: MutexGuard<'static, ()>,
+}
+
+impl<T> Deref for WithGl<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for WithGl<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+// Creates whatever holds the context through `create` once no other test has one. Returns None, so the test can
+// skip itself, when no headless OpenGL context can be created, unless GLOOM_REQUIRE_GL is set.
+pub fn with_gl<T, F: FnOnce() -> Result<T, String>>(test: &str, create: F) -> Option<WithGl<T>> {
+    let guard = GL_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    match create() {
+        Ok(value) => Some(WithGl { value, _guard: guard }),
+        Err(e) if std::env::var_os("GLOOM_REQUIRE_GL").is_some() => panic!("{}", e),
+        Err(e) => {
+            eprintln!("Skipping {}: {}", test, e);
+            None
+        },
+    }
+}
+
+pub fn gl_context(test: &str) -> Option<WithGl<HeadlessContext>> {
+    with_gl(test, || HeadlessContext::new(16, 16))
+}
+
+// An empty directory of its own under the system's temporary directory, removed again when dropped, so a failing
+// test doesn't leave its files behind
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!("gloom_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    // Writes `contents` to the file `name` in the directory, returning its path
+    pub fn write(&self, name: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.0.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+// A flat square of n by n quads in the xz plane, from 0 to n, facing +y
+pub fn grid(n: u32) -> (Vec<f32>, Vec<u32>) {
+    let positions = (0..=n).flat_map(|z| (0..=n).flat_map(move |x| vec![x as f32, 0.0, z as f32])).collect();
+    let index = |x: u32, z: u32| z * (n + 1) + x;
+    let indices = (0..n).flat_map(|z| (0..n).flat_map(move |x| {
+        vec![index(x, z), index(x, z + 1), index(x + 1, z + 1), index(x, z), index(x + 1, z + 1), index(x + 1, z)]
+    })).collect();
+    (positions, indices)
+}
+
+// A UV sphere, with the seam vertices doubled as a textured one would have them
+pub fn sphere(radius: f32, stacks: u32, slices: u32) -> (Vec<f32>, Vec<u32>) {
+    let mut positions = vec![];
+    for stack in 0..=stacks {
+        let polar = std::f32::consts::PI * stack as f32 / stacks as f32;
+        for slice in 0..=slices {
+            let azimuth = 2.0 * std::f32::consts::PI * slice as f32 / slices as f32;
+            positions.extend_from_slice(&[radius * polar.sin() * azimuth.cos(), radius * polar.cos(), radius * polar.sin() * azimuth.sin()]);
+        }
+    }
+    let index = |stack: u32, slice: u32| stack * (slices + 1) + slice;
+    let indices = (0..stacks).flat_map(|stack| (0..slices).flat_map(move |slice| vec![
+        index(stack, slice), index(stack, slice + 1), index(stack + 1, slice + 1),
+        index(stack, slice), index(stack + 1, slice + 1), index(stack + 1, slice),
+    ])).collect();
+    (positions, indices)
+}
+
+// A white mesh of the given triangles with normals facing +y, and no texture coordinates, tangents or material
+pub fn mesh(vertices: Vec<f32>, indices: Vec<u32>) -> Mesh {
+    let vertex_count = vertices.len() / 3;
+    Mesh {
+        vertices,
+        normals: [0.0, 1.0, 0.0].repeat(vertex_count),
+        uvs: vec![],
+        tangents: vec![],
+        colors: vec![1.0; vertex_count * 4],
+        index_count: indices.len() as i32,
+        indices,
+        material: None,
+        levels_of_detail: vec![],
+    }
+}
@@ -0,0 +1,69 @@
+// Runs small compute shaders through Shader::dispatch and reads back what they wrote.
+// Skips itself when no headless OpenGL context can be created, unless GLOOM_REQUIRE_GL is set.
+mod common;
+
+use gloom_rs::reflection::UniformError;
+use gloom_rs::shader::ShaderBuilder;
+
+const DOUBLE: &str = "#version 430 core
+layout(local_size_x = 4) in;
+layout(std430, binding = 3) buffer Values { float values[]; };
+uniform uint count;
+layout(rgba32f) uniform writeonly image2D target;
+void main() {
+    uint i = gl_GlobalInvocationID.x;
+    if (i >= count) return;
+    values[i] *= 2.0;
+    imageStore(target, ivec2(i, 0), vec4(values[i]));
+}";
+
+#[test]
+fn compute_shader_doubles_storage_buffer_and_writes_image() {
+    let _context = match common::gl_context("compute test") { Some(context) => context, None => return };
+
+    // Compute shaders are recognized by their .comp extension
+    let dir = common::TempDir::new("compute");
+    let path = dir.write("double.comp", DOUBLE);
+
+    unsafe {
+        let shader = ShaderBuilder::new()
+            .attach_path(&path)
+            .and_then(|b| b.link())
+            .unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(shader.work_group_size(), Some([4, 1, 1]));
+
+        let values: Vec<f32> = (0..10).map(|i| i as f32).collect();
+        let mut buffer = 0;
+        gl::GenBuffers(1, &mut buffer);
+        gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, buffer);
+        gl::BufferData(gl::SHADER_STORAGE_BUFFER, (values.len() * 4) as isize, values.as_ptr() as *const _, gl::DYNAMIC_COPY);
+
+        let mut texture = 0;
+        gl::GenTextures(1, &mut texture);
+        gl::BindTexture(gl::TEXTURE_2D, texture);
+        gl::TexStorage2D(gl::TEXTURE_2D, 1, gl::RGBA32F, 16, 1);
+
+        shader.set_u32("count", values.len() as u32).unwrap();
+        shader.bind_storage_buffer("Values", buffer).unwrap();
+        shader.bind_image("target", 2, texture, 0, gl::WRITE_ONLY, gl::RGBA32F).unwrap();
+        assert!(matches!(shader.bind_storage_buffer("Missing", buffer), Err(UniformError::Inactive { .. })));
+        assert!(matches!(shader.bind_image("count", 0, texture, 0, gl::WRITE_ONLY, gl::RGBA32F), Err(UniformError::TypeMismatch { .. })));
+
+        shader.dispatch_invocations([values.len() as u32, 1, 1]);
+        gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT | gl::TEXTURE_UPDATE_BARRIER_BIT);
+
+        let mut doubled = vec![0.0f32; values.len()];
+        gl::GetBufferSubData(gl::SHADER_STORAGE_BUFFER, 0, (doubled.len() * 4) as isize, doubled.as_mut_ptr() as *mut _);
+        let expected: Vec<f32> = values.iter().map(|v| v * 2.0).collect();
+        assert_eq!(doubled, expected);
+
+        let mut pixels = vec![0.0f32; 16 * 4];
+        gl::GetTexImage(gl::TEXTURE_2D, 0, gl::RGBA, gl::FLOAT, pixels.as_mut_ptr() as *mut _);
+        let red: Vec<f32> = pixels.chunks(4).take(values.len()).map(|p| p[0]).collect();
+        assert_eq!(red, expected);
+
+        gl::DeleteTextures(1, &texture);
+        gl::DeleteBuffers(1, &buffer);
+        assert_eq!(gl::GetError(), gl::NO_ERROR);
+    }
+}
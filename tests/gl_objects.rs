@@ -0,0 +1,29 @@
+// Checks the owned GL object types free what they own when dropped.
+// Skips itself when no headless OpenGL context can be created, unless GLOOM_REQUIRE_GL is set.
+mod common;
+
+use gloom_rs::gl_objects::{Buffer, Program};
+use gloom_rs::render;
+
+#[test]
+fn gl_objects_are_deleted_when_dropped() {
+    let _context = match common::gl_context("GL object test") { Some(context) => context, None => return };
+
+    unsafe {
+        let vao = render::create_vao(&[0.0; 9], &[0.0; 9], &[1.0; 12], &[0, 1, 2]);
+        let vao_id = vao.id();
+        let buffer_ids: Vec<u32> = vao.buffers().iter().map(Buffer::id).collect();
+        assert_eq!(buffer_ids.len(), 4);
+        assert_eq!(gl::IsVertexArray(vao_id), gl::TRUE);
+        assert!(buffer_ids.iter().all(|&id| gl::IsBuffer(id) == gl::TRUE));
+        drop(vao);
+        assert_eq!(gl::IsVertexArray(vao_id), gl::FALSE);
+        assert!(buffer_ids.iter().all(|&id| gl::IsBuffer(id) == gl::FALSE));
+
+        let program = Program::new();
+        let program_id = program.id();
+        assert_eq!(gl::IsProgram(program_id), gl::TRUE);
+        drop(program);
+        assert_eq!(gl::IsProgram(program_id), gl::FALSE);
+    }
+}
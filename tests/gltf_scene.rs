@@ -0,0 +1,228 @@
+// Checks glTF scenes come in with their hierarchy, transforms, meshes, materials and textures, from .gltf and .glb
+// files alike, and turn into scene graph nodes. The upload test skips itself when no headless OpenGL context can
+// be created, unless GLOOM_REQUIRE_GL is set.
+extern crate nalgebra_glm as glm;
+
+mod common;
+
+use base64::Engine;
+
+use gloom_rs::gltf_scene::GltfScene;
+use gloom_rs::render::{self, UploadedScene};
+use gloom_rs::scene_graph::SceneGraph;
+use gloom_rs::texture::{Filter, Wrap};
+
+// A unit quad in the xy plane: positions, texture coordinates, six u16 indices for triangles and four for a strip
+fn quad_buffer() -> Vec<u8> {
+    let positions = [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0];
+    let uvs = [0.0f32, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0];
+    let indices = [0u16, 1, 2, 0, 2, 3, 0, 1, 3, 2];
+    let mut buffer: Vec<u8> = positions.iter().chain(&uvs).flat_map(|x| x.to_le_bytes()).collect();
+    buffer.extend(indices.iter().flat_map(|i| i.to_le_bytes()));
+    buffer
+}
+
+// The accessors and buffer views of quad_buffer, with `extra_views` after them
+fn quad_json(buffer_uri: Option<&str>, buffer_length: usize, extra_views: &str) -> String {
+    let uri = buffer_uri.map_or(String::new(), |uri| format!(r#""uri": "{}", "#, uri));
+    format!(r#"
+        "buffers": [{{ {}"byteLength": {} }}],
+        "bufferViews": [
+            {{ "buffer": 0, "byteOffset": 0, "byteLength": 48 }},
+            {{ "buffer": 0, "byteOffset": 48, "byteLength": 32 }},
+            {{ "buffer": 0, "byteOffset": 80, "byteLength": 12 }},
+            {{ "buffer": 0, "byteOffset": 92, "byteLength": 8 }}{}
+        ],
+        "accessors": [
+            {{ "bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] }},
+            {{ "bufferView": 1, "componentType": 5126, "count": 4, "type": "VEC2" }},
+            {{ "bufferView": 2, "componentType": 5123, "count": 6, "type": "SCALAR" }},
+            {{ "bufferView": 3, "componentType": 5123, "count": 4, "type": "SCALAR" }}
+        ]"#, uri, buffer_length, extra_views)
+}
+
+// A helicopter-like hierarchy: a body with a rotor turned a quarter around y and an unnamed, scaled node drawing
+// the quad twice, once as a strip
+fn helicopter_gltf(buffer_uri: &str) -> String {
+    format!(r#"{{
+        "asset": {{ "version": "2.0" }},
+        "scene": 0,
+        "scenes": [{{ "nodes": [0] }}],
+        "nodes": [
+            {{ "name": "Body", "translation": [1, 2, 3], "children": [1, 2] }},
+            {{ "name": "Main_Rotor", "translation": [0, 2.2, 0], "rotation": [0, 0.70710677, 0, 0.70710677], "mesh": 0 }},
+            {{ "scale": [2, 2, 2], "mesh": 1 }}
+        ],
+        "meshes": [
+            {{ "primitives": [{{ "attributes": {{ "POSITION": 0, "TEXCOORD_0": 1 }}, "indices": 2, "material": 0 }}] }},
+            {{ "primitives": [
+                {{ "attributes": {{ "POSITION": 0 }}, "indices": 2 }},
+                {{ "attributes": {{ "POSITION": 0 }}, "indices": 3, "mode": 5 }}
+            ] }}
+        ],
+        "materials": [{{
+            "name": "Paint",
+            "pbrMetallicRoughness": {{ "baseColorFactor": [0.5, 0.25, 1, 1], "metallicFactor": 0.2, "roughnessFactor": 0.7 }},
+            "emissiveFactor": [0.1, 0, 0],
+            "alphaMode": "MASK",
+            "alphaCutoff": 0.3,
+            "doubleSided": true
+        }}],
+        {}
+    }}"#, quad_json(Some(buffer_uri), 100, ""))
+}
+
+// A binary glTF file: the header, then the JSON and BIN chunks, each padded to four bytes
+fn glb(json: &str, bin: &[u8]) -> Vec<u8> {
+    let mut json = json.as_bytes().to_vec();
+    json.resize(json.len().div_ceil(4) * 4, b' ');
+    let mut bin = bin.to_vec();
+    bin.resize(bin.len().div_ceil(4) * 4, 0);
+    let length = 12 + 8 + json.len() + 8 + bin.len();
+    let mut glb = b"glTF".to_vec();
+    for value in [2, length as u32, json.len() as u32, 0x4E4F534A] {
+        glb.extend_from_slice(&value.to_le_bytes());
+    }
+    glb.extend(json);
+    glb.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+    glb.extend_from_slice(&0x004E4942u32.to_le_bytes());
+    glb.extend(bin);
+    glb
+}
+
+// A quad with a 2x1 PNG in the binary chunk used as its base colour and normal map, sampled without filtering.
+// `cut` bytes of the PNG are left out of the buffer, though not out of its buffer view.
+fn textured_glb(cut: usize) -> Vec<u8> {
+    let mut png = vec![];
+    let image = image::RgbaImage::from_fn(2, 1, |x, _| image::Rgba([255 * x as u8, 128, 255, 255]));
+    image::DynamicImage::ImageRgba8(image).write_to(&mut png, image::ImageOutputFormat::Png).unwrap();
+    let mut bin = quad_buffer();
+    let json = format!(r#"{{
+        "asset": {{ "version": "2.0" }},
+        "scenes": [{{ "nodes": [0] }}],
+        "nodes": [{{ "name": "Sign", "mesh": 0 }}],
+        "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0, "TEXCOORD_0": 1 }}, "indices": 2, "material": 0 }}] }}],
+        "materials": [{{
+            "pbrMetallicRoughness": {{ "baseColorTexture": {{ "index": 0 }} }},
+            "normalTexture": {{ "index": 0, "scale": 0.5 }}
+        }}],
+        "samplers": [{{ "magFilter": 9728, "minFilter": 9728, "wrapS": 33071, "wrapT": 33071 }}],
+        "images": [{{ "bufferView": 4, "mimeType": "image/png" }}],
+        "textures": [{{ "sampler": 0, "source": 0 }}],
+        {}
+    }}"#, quad_json(None, 100 + png.len() - cut, &format!(r#", {{ "buffer": 0, "byteOffset": 100, "byteLength": {} }}"#, png.len())));
+    bin.extend(&png[..png.len() - cut]);
+    glb(&json, &bin)
+}
+
+fn data_uri(bytes: &[u8]) -> String {
+    format!("data:application/octet-stream;base64,{}", base64::engine::general_purpose::STANDARD.encode(bytes))
+}
+
+#[test]
+fn hierarchies_transforms_and_materials_are_imported() {
+    let scene = GltfScene::from_slice(helicopter_gltf(&data_uri(&quad_buffer())).as_bytes(), std::path::Path::new("")).unwrap();
+
+    assert_eq!(scene.roots, [0]);
+    let names: Vec<&str> = scene.nodes.iter().map(|node| node.name.as_str()).collect();
+    assert_eq!(names, ["Body", "Main_Rotor", ""]);
+    assert_eq!(scene.nodes[0].children, [1, 2]);
+    let rotor = &scene.nodes[scene.node("Main_Rotor").unwrap()];
+    assert_eq!(rotor.translation, glm::vec3(0.0, 2.2, 0.0));
+    assert!((glm::quat_rotate_vec3(&rotor.rotation, &glm::vec3(1.0, 0.0, 0.0)) - glm::vec3(0.0, 0.0, -1.0)).norm() < 1e-5);
+    assert_eq!((rotor.mesh, scene.nodes[2].mesh, scene.nodes[2].scale), (Some(0), Some(1), glm::vec3(2.0, 2.0, 2.0)));
+
+    let material = &scene.materials[0];
+    assert_eq!((material.name.as_str(), material.base_color, material.metallic, material.roughness), ("Paint", [0.5, 0.25, 1.0, 1.0], 0.2, 0.7));
+    assert_eq!((material.emissive, material.alpha_cutoff, material.double_sided), ([0.1, 0.0, 0.0], Some(0.3), true));
+
+    let painted = &scene.meshes[0][0];
+    assert_eq!(painted.material, Some(0));
+    // Texture coordinates are flipped, and the base colour colours the vertices
+    assert_eq!(painted.mesh.uvs, [0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0]);
+    assert_eq!(&painted.mesh.colors[..4], &[0.5, 0.25, 1.0, 1.0]);
+    assert_eq!(painted.mesh.material.as_ref().unwrap().diffuse, [0.5, 0.25, 1.0]);
+    // Without normals of its own the quad gets flat ones, facing +z
+    assert_eq!(painted.mesh.normals.chunks(3).collect::<Vec<_>>(), vec![&[0.0, 0.0, 1.0]; painted.mesh.vertices.len() / 3]);
+
+    // Primitives without a material are white, and strips become triangles of the same winding
+    let [plain, strip] = [&scene.meshes[1][0], &scene.meshes[1][1]];
+    assert_eq!((plain.material, &plain.mesh.colors[..4]), (None, &[1.0, 1.0, 1.0, 1.0][..]));
+    let corner = |mesh: &gloom_rs::mesh::Mesh, i: usize| mesh.vertices[mesh.indices[i] as usize * 3..mesh.indices[i] as usize * 3 + 2].to_vec();
+    let strip_corners: Vec<Vec<f32>> = (0..6).map(|i| corner(&strip.mesh, i)).collect();
+    assert_eq!(strip_corners, [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [0.0, 1.0], [1.0, 0.0], [1.0, 1.0]]);
+}
+
+#[test]
+fn binary_files_carry_their_buffers_and_images() {
+    let scene = GltfScene::from_slice(&textured_glb(0), std::path::Path::new("")).unwrap();
+    assert_eq!(scene.textures.len(), 1);
+    let texture = &scene.textures[0];
+    assert_eq!(texture.image.dimensions(), (2, 1));
+    assert_eq!(texture.image.get_pixel(1, 0).0, [255, 128, 255, 255]);
+    assert_eq!((texture.options.wrap, texture.options.filter, texture.options.mipmaps), (Wrap::ClampToEdge, Filter::Nearest, false));
+
+    let material = &scene.materials[0];
+    assert_eq!((material.base_color_texture, material.normal_texture, material.normal_scale), (Some(0), Some(0), 0.5));
+    // glTF's defaults for what the material leaves out
+    assert_eq!((material.base_color, material.metallic, material.roughness), ([1.0; 4], 1.0, 1.0));
+    // The normal map needs tangents, which are generated
+    let mesh = &scene.meshes[0][0].mesh;
+    assert_eq!(mesh.tangents.len(), mesh.vertices.len() / 3 * 4);
+}
+
+#[test]
+fn image_views_past_the_end_of_their_buffer_are_errors() {
+    let error = GltfScene::from_slice(&textured_glb(5), std::path::Path::new("")).err().unwrap();
+    assert!(error.contains("image 0: buffer view 4 is out of range"), "{}", error);
+}
+
+#[test]
+fn external_buffers_are_read_next_to_the_file() {
+    let dir = common::TempDir::new("gltf");
+    dir.write("quad data.bin", quad_buffer());
+    dir.write("helicopter.gltf", helicopter_gltf("quad%20data.bin"));
+    let scene = GltfScene::load(dir.join("helicopter.gltf")).unwrap();
+    assert_eq!(scene.meshes[0][0].mesh.indices.len(), 6);
+
+    std::fs::remove_file(dir.join("quad data.bin")).unwrap();
+    let error = GltfScene::load(dir.join("helicopter.gltf")).err().unwrap();
+    assert!(error.contains("helicopter.gltf") && error.contains("quad data.bin"), "{}", error);
+}
+
+#[test]
+fn scenes_become_scene_graph_nodes() {
+    let _context = match common::gl_context("glTF upload test") { Some(context) => context, None => return };
+
+    let helicopter = GltfScene::from_slice(helicopter_gltf(&data_uri(&quad_buffer())).as_bytes(), std::path::Path::new("")).unwrap();
+    let sign = GltfScene::from_slice(&textured_glb(0), std::path::Path::new("")).unwrap();
+    let (helicopter, sign) = unsafe { (UploadedScene::new(&helicopter), UploadedScene::new(&sign)) };
+    assert_eq!(helicopter.vertex_arrays().count(), 3);
+    assert_eq!(sign.textures().count(), 1);
+
+    let mut graph = SceneGraph::new();
+    let instance = helicopter.instantiate(&mut graph);
+    let names: Vec<&str> = instance.parts.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["Body", "Main_Rotor"]);
+    let body = instance.part("Body").unwrap();
+    let rotor = instance.part("Main_Rotor").unwrap();
+    assert_eq!(graph.children(instance.root), [body]);
+    assert_eq!(graph.children(body).len(), 2);
+    // The unnamed node draws nothing itself, but has a child for each of its two primitives
+    let scaled = graph.children(body)[1];
+    assert_eq!((graph[scaled].index_count, graph.children(scaled).len()), (-1, 2));
+    assert_eq!(graph[rotor].index_count, 6);
+
+    // The rotor turns around its own origin, which the asset puts above the body
+    graph[rotor].rotation = glm::vec3(0.0, 1.0, 0.0);
+    render::update_node_transformations(&mut graph, instance.root, &glm::identity());
+    let origin = graph[rotor].current_transformation_matrix * glm::vec4(0.0, 0.0, 0.0, 1.0);
+    assert!((origin.xyz() - glm::vec3(1.0, 4.2, 3.0)).norm() < 1e-5);
+    let corner = graph[rotor].current_transformation_matrix * glm::vec4(1.0, 0.0, 0.0, 1.0);
+    assert!((corner.xyz() - glm::vec3(1.0, 4.2, 3.0) - glm::vec3(0.0, 0.0, -1.0)).norm() < 1e-5);
+
+    let sign_instance = sign.instantiate(&mut graph);
+    let sign_node = &graph[sign_instance.part("Sign").unwrap()];
+    let texture = sign.textures().next().unwrap().id();
+    assert_eq!((sign_node.texture_id, sign_node.normal_map_id), (texture, texture));
+}
@@ -0,0 +1,141 @@
+// Golden-image regression tests for the renderer. Each test renders a fixed frame of the lunar scene offscreen
+// and compares it against a reference PNG in tests/golden, see gloom_rs::golden for how to update them.
+//
+// The tests skip themselves when no headless OpenGL context can be created (set GLOOM_REQUIRE_GL to make that
+// an error instead). The test using the real models is ignored, as they aren't in the repository; run it with
+// --ignored where resources/ exists, after recording its references with GLOOM_UPDATE_GOLDEN.
+extern crate nalgebra_glm as glm;
+
+mod common;
+
+use std::path::Path;
+
+use gloom_rs::golden::{self, Tolerance};
+use gloom_rs::headless::HeadlessRenderer;
+use gloom_rs::lunar_scene::{self, LunarScene};
+use gloom_rs::mesh::{Mesh, Model, ModelPart};
+
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 120;
+
+fn render_frames<F: FnOnce() -> LunarScene>(create_scene: F, times: &[f32]) -> Option<Vec<image::RgbaImage>> {
+    let mut renderer = common::with_gl("golden image test", || HeadlessRenderer::with_scene(WIDTH, HEIGHT, create_scene))?;
+    Some(times.iter().map(|&t| renderer.render(t)).collect())
+}
+
+fn check_all(name: &str, times: &[f32], images: &[image::RgbaImage]) {
+    let failures: Vec<String> = times.iter().zip(images)
+        .filter_map(|(t, image)| golden::check_golden(&format!("{}_{:.2}", name, t), image, &Tolerance::default()).err())
+        .collect();
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+fn flat_mesh(vertices: Vec<f32>, normals: Vec<f32>, indices: Vec<u32>, color: [f32; 4]) -> Mesh {
+    let colors = color.iter().cloned().cycle().take(vertices.len() / 3 * 4).collect();
+    let index_count = indices.len() as i32;
+    Mesh { vertices, normals, uvs: vec![], tangents: vec![], colors, indices, index_count, material: None, levels_of_detail: vec![] }
+}
+
+// A rolling height field standing in for the lunar surface
+fn terrain_grid(size: usize, spacing: f32) -> Mesh {
+    let height = |x: f32, z: f32| -10.0 + 2.0 * (x * 0.1).sin() * (z * 0.1).cos();
+    let (mut vertices, mut normals, mut indices) = (vec![], vec![], vec![]);
+    for j in 0..=size {
+        for i in 0..=size {
+            let x = (i as f32 - size as f32 / 2.0) * spacing;
+            let z = (j as f32 - size as f32 / 2.0) * spacing;
+            vertices.extend_from_slice(&[x, height(x, z), z]);
+            let dx = height(x + 0.01, z) - height(x - 0.01, z);
+            let dz = height(x, z + 0.01) - height(x, z - 0.01);
+            let normal = glm::normalize(&glm::vec3(-dx, 0.02, -dz));
+            normals.extend_from_slice(&[normal.x, normal.y, normal.z]);
+        }
+    }
+    let row = size as u32 + 1;
+    for j in 0..size as u32 {
+        for i in 0..size as u32 {
+            let a = j * row + i;
+            indices.extend_from_slice(&[a, a + row, a + 1, a + 1, a + row, a + row + 1]);
+        }
+    }
+    flat_mesh(vertices, normals, indices, [1.0, 1.0, 1.0, 1.0])
+}
+
+// An axis aligned box with outward facing, counter-clockwise faces
+fn cuboid(center: [f32; 3], half_extents: [f32; 3], color: [f32; 4]) -> Mesh {
+    let (mut vertices, mut normals, mut indices) = (vec![], vec![], vec![]);
+    for axis in 0..3 {
+        for &sign in &[-1.0f32, 1.0] {
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            let base = vertices.len() as u32 / 3;
+            for &(a, b) in &[(-1.0f32, -1.0f32), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                let mut corner = [0.0; 3];
+                corner[axis] = sign;
+                corner[u] = a * sign;
+                corner[v] = b;
+                for k in 0..3 {
+                    vertices.push(center[k] + corner[k] * half_extents[k]);
+                    normals.push(if k == axis { sign } else { 0.0 });
+                }
+            }
+            indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+    }
+    flat_mesh(vertices, normals, indices, color)
+}
+
+fn box_helicopter() -> Model {
+    Model::from_parts(vec![
+        ModelPart::new(lunar_scene::HELICOPTER_BODY,       cuboid([0.0, 1.0, 0.0], [1.0, 1.0, 3.0], [0.3, 0.3, 0.3, 1.0])),
+        ModelPart::new(lunar_scene::HELICOPTER_MAIN_ROTOR, cuboid([0.0, 2.2, 0.0], [4.0, 0.05, 0.3], [0.3, 0.1, 0.1, 1.0])),
+        ModelPart::new(lunar_scene::HELICOPTER_TAIL_ROTOR, cuboid([0.35, 2.3, 10.4], [0.05, 1.0, 0.2], [0.1, 0.3, 0.1, 1.0])),
+        ModelPart::new(lunar_scene::HELICOPTER_DOOR,       cuboid([1.0, 1.0, 0.0], [0.05, 0.6, 0.6], [0.1, 0.1, 0.3, 1.0])),
+    ])
+}
+
+#[test]
+fn generated_scene_matches_golden_images() {
+    let times = [0.0, 1.5, 4.0];
+    let scene = || unsafe { LunarScene::from_models(&Model::from_parts(vec![ModelPart::new("terrain", terrain_grid(40, 5.0))]), &box_helicopter(), 5) };
+    if let Some(images) = render_frames(scene, &times) {
+        check_all("generated_scene", &times, &images);
+    }
+}
+
+#[test]
+#[ignore = "needs the lunar scene models in resources/, which aren't in the repository"]
+fn lunar_scene_matches_golden_images() {
+    assert!(Path::new(lunar_scene::TERRAIN_PATH).exists() && Path::new(lunar_scene::HELICOPTER_PATH).exists(),
+        "the lunar scene models are not in resources/");
+    let times = [0.0, 2.0, 5.0];
+    if let Some(images) = render_frames(|| unsafe { LunarScene::load(5) }, &times) {
+        check_all("lunar_scene", &times, &images);
+    }
+}
+
+#[test]
+fn comparison_reports_pixels_beyond_tolerance() {
+    let expected = image::RgbaImage::from_pixel(4, 4, image::Rgba([100, 100, 100, 255]));
+    let mut actual = expected.clone();
+    actual.put_pixel(1, 1, image::Rgba([102, 100, 100, 255]));
+    actual.put_pixel(2, 2, image::Rgba([140, 100, 100, 255]));
+
+    let tolerance = Tolerance { channel: 2, max_mismatched_ratio: 0.0 };
+    let comparison = golden::compare_images(&actual, &expected, &tolerance);
+    assert_eq!(comparison.mismatched_pixels, 1);
+    assert_eq!(comparison.max_difference, 40);
+    assert_eq!(comparison.diff_image.get_pixel(2, 2), &image::Rgba([255, 0, 0, 255]));
+    assert!(!comparison.passes(&tolerance));
+    assert!(comparison.passes(&Tolerance { channel: 2, max_mismatched_ratio: 0.1 }));
+}
+
+#[test]
+fn missing_references_fail() {
+    if std::env::var_os(golden::UPDATE_ENV).is_some() {
+        return;
+    }
+    let image = image::RgbaImage::from_pixel(4, 4, image::Rgba([100, 100, 100, 255]));
+    let error = golden::check_golden("no_such_reference", &image, &Tolerance::default()).unwrap_err();
+    assert!(error.contains("no_such_reference.png"), "{}", error);
+    assert!(!golden::golden_path("no_such_reference").exists());
+}
@@ -0,0 +1,152 @@
+// Checks texture coordinates and MTL materials make it from OBJ files into meshes.
+mod common;
+
+use gloom_rs::mesh::{self, Material, Mesh};
+use gloom_rs::vertex_layout::{VertexSource, UV};
+
+const OBJ: &str = "mtllib quad.mtl
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+o plain
+f 1/1/1 3/3/1 4/4/1
+o painted
+usemtl painted
+f 1/1/1 2/2/1 3/3/1
+";
+
+const MTL: &str = "newmtl painted
+Ka 0.1 0.2 0.3
+Kd 0.5 0.25 1.0
+Ks 1 1 1
+Ns 32
+d 0.5
+map_Kd textures/albedo.png
+";
+
+#[test]
+fn obj_meshes_carry_uvs_and_materials() {
+    let dir = common::TempDir::new("mesh");
+    let obj = dir.write("quad.obj", OBJ);
+    dir.write("quad.mtl", MTL);
+
+    let (models, materials) = mesh::load_obj(obj.to_str().unwrap()).unwrap();
+    assert_eq!(models.len(), 2);
+    assert_eq!(materials.len(), 1);
+    let material = &materials[0];
+    assert_eq!(material.name, "painted");
+    assert_eq!((material.ambient, material.diffuse, material.specular), ([0.1, 0.2, 0.3], [0.5, 0.25, 1.0], [1.0; 3]));
+    assert_eq!((material.shininess, material.dissolve), (32.0, 0.5));
+    assert_eq!(material.diffuse_texture, Some(dir.join("textures/albedo.png")));
+    assert_eq!(material.specular_texture, None);
+
+    let painted = Mesh::from(models[1].mesh.clone(), &materials, [1.0; 4]);
+    assert_eq!(painted.material.as_ref(), Some(material));
+    assert_eq!(&painted.colors[..4], &[0.5, 0.25, 1.0, 0.5]);
+    assert_eq!(painted.uvs, vec![0.0, 0.0, 1.0, 0.0, 1.0, 1.0]);
+    assert_eq!(painted.attribute(UV), Some((&painted.uvs[..], 2)));
+
+    let plain = Mesh::from(models[0].mesh.clone(), &materials, [0.1, 0.2, 0.3, 1.0]);
+    assert_eq!(plain.material, None);
+    assert_eq!(&plain.colors[..4], &Material::flat([0.1, 0.2, 0.3, 1.0]).color());
+}
+
+const PARTS_OBJ: &str = "mtllib parts.mtl
+v 0 0 0
+v 1 0 0
+v 1 1 0
+o body
+usemtl red
+f 1 2 3
+usemtl blue
+f 1 3 2
+o rotor
+f 1 2 3
+";
+
+const PARTS_MTL: &str = "newmtl red
+Kd 1 0 0
+newmtl blue
+Kd 0 0 1
+";
+
+#[test]
+fn models_are_split_into_named_parts() {
+    let dir = common::TempDir::new("model");
+    let obj = dir.write("parts.obj", PARTS_OBJ);
+    dir.write("parts.mtl", PARTS_MTL);
+    let model = mesh::Model::load(obj.to_str().unwrap(), |_| [1.0; 4]).unwrap();
+
+    assert_eq!(model.part_names().collect::<Vec<_>>(), vec!["body", "rotor"]);
+    assert_eq!(model.materials.len(), 2);
+    // Switching material splits the meshes of a part, not the part itself
+    let body = model.part("body").unwrap();
+    assert_eq!(body.meshes.len(), 2);
+    assert_eq!(&body.meshes[0].colors[..4], &[1.0, 0.0, 0.0, 1.0]);
+    assert_eq!(&body.meshes[1].colors[..4], &[0.0, 0.0, 1.0, 1.0]);
+    // The material stays in effect across objects, as OBJ files intend
+    assert_eq!(model.part("rotor").unwrap().meshes[0].material.as_ref().map(|m| m.name.as_str()), Some("blue"));
+    assert!(model.part("door").is_none());
+
+    assert!(mesh::Model::load("no/such/model.obj", |_| [1.0; 4]).is_err());
+}
+
+// Each triangle as the position, normal and, if `uvs`, texture coordinates of its corners, rounded so that values
+// written as text compare equal
+fn triangles(mesh: &Mesh, uvs: bool) -> Vec<[Vec<i32>; 3]> {
+    let corner = |v: u32| {
+        let v = v as usize;
+        let mut attributes = [&mesh.vertices[v * 3..v * 3 + 3], &mesh.normals[v * 3..v * 3 + 3]].concat();
+        if uvs {
+            attributes.extend_from_slice(&mesh.uvs[v * 2..v * 2 + 2]);
+        }
+        attributes.iter().map(|x| (x * 1000.0).round() as i32).collect::<Vec<_>>()
+    };
+    mesh.indices.chunks(3).map(|t| [corner(t[0]), corner(t[1]), corner(t[2])]).collect()
+}
+
+#[test]
+fn meshes_are_saved_in_every_format() {
+    let dir = common::TempDir::new("save");
+    let material = Material { name: "green".to_string(), diffuse: [0.0, 1.0, 0.0], diffuse_texture: Some(dir.join("grass.png")), ..Material::flat([1.0; 4]) };
+    let original = Mesh {
+        vertices: vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0],
+        normals: [0.0, 0.0, 1.0].repeat(4),
+        uvs: vec![0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0],
+        tangents: vec![],
+        colors: [0.0, 1.0, 0.0, 1.0].repeat(4),
+        indices: vec![0, 1, 2, 0, 2, 3],
+        index_count: 6,
+        material: Some(material.clone()),
+        levels_of_detail: vec![],
+    };
+    let meshes: Vec<Mesh> = ["quad.obj", "quad.ply", "quad.stl"].iter().map(|name| {
+        original.save(dir.join(name)).unwrap();
+        mesh::Model::from_file(dir.join(name).to_str().unwrap(), |_| [1.0; 4]).unwrap().parts.remove(0).meshes.remove(0)
+    }).collect();
+    assert!(original.save(dir.join("quad.fbx")).is_err());
+    // The MTL file written along with the OBJ file is left alone, along with the OBJ file itself
+    assert!(original.save(dir.join("quad.obj")).unwrap_err().contains("quad.mtl already exists"));
+    assert_eq!(std::fs::read_to_string(dir.join("quad.mtl")).unwrap(), material.to_mtl(&dir));
+
+    // OBJ and PLY keep every attribute of every corner, STL only positions and facet normals
+    assert_eq!(triangles(&meshes[0], true), triangles(&original, true));
+    assert_eq!(triangles(&meshes[1], true), triangles(&original, true));
+    assert_eq!(triangles(&meshes[2], false), triangles(&original, false));
+    // OBJ colours meshes by their material and PLY by their vertices, while STL has no colours
+    assert_eq!(meshes[0].colors, [0.0, 1.0, 0.0, 1.0].repeat(4));
+    assert_eq!(meshes[1].colors, [0.0, 1.0, 0.0, 1.0].repeat(4));
+    assert_eq!(meshes[2].colors, [1.0; 16]);
+    // The OBJ file keeps the material along with its texture, and PLY keeps texture coordinates
+    let obj_material = meshes[0].material.as_ref().unwrap();
+    assert_eq!((obj_material.name.as_str(), obj_material.diffuse), ("green", [0.0, 1.0, 0.0]));
+    assert_eq!(obj_material.diffuse_texture, material.diffuse_texture);
+    assert_eq!(meshes[1].uvs.len(), 8);
+    assert!(meshes[2].uvs.is_empty());
+}
@@ -0,0 +1,203 @@
+// Checks models come back from their mesh cache as they went in, and that Model::load only trusts fresh, intact
+// caches.
+mod common;
+
+use std::time::{Duration, SystemTime};
+
+use gloom_rs::mesh::{Material, Mesh, Model, ModelPart};
+use gloom_rs::mesh_cache::{self, MeshCache};
+
+const OBJ: &str = "v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+o quad
+f 1 2 3
+f 1 3 4
+";
+
+// A bumpy grid with every attribute, so the levels of detail have something to simplify
+fn grid(n: u32, material: Option<Material>) -> Mesh {
+    let (mut vertices, indices) = common::grid(n);
+    for p in vertices.chunks_mut(3) {
+        p[1] = p[0] * p[2] * 0.01;
+    }
+    let vertex_count = vertices.len() / 3;
+    Mesh {
+        uvs: vertices.chunks(3).flat_map(|p| vec![p[0] / n as f32, p[2] / n as f32]).collect(),
+        tangents: [1.0, 0.0, 0.0, 1.0].repeat(vertex_count),
+        colors: vertices.chunks(3).flat_map(|p| vec![p[0], p[2], 0.5, 1.0]).collect(),
+        material,
+        ..common::mesh(vertices, indices)
+    }
+}
+
+fn assert_same(a: &Mesh, b: &Mesh) {
+    assert_eq!(a.vertices, b.vertices);
+    assert_eq!(a.normals, b.normals);
+    assert_eq!(a.uvs, b.uvs);
+    assert_eq!(a.tangents, b.tangents);
+    assert_eq!(a.colors, b.colors);
+    assert_eq!(a.indices, b.indices);
+    assert_eq!(a.index_count, b.index_count);
+    assert_eq!(a.material, b.material);
+    assert_eq!(a.levels_of_detail.len(), b.levels_of_detail.len());
+    for ((a_ratio, a), (b_ratio, b)) in a.levels_of_detail.iter().zip(&b.levels_of_detail) {
+        assert_eq!(a_ratio, b_ratio);
+        assert_same(a, b);
+    }
+}
+
+#[test]
+fn models_round_trip_through_the_cache() {
+    let textured = Material {
+        name: "textured".to_string(),
+        diffuse_texture: Some("textures/albedo.png".into()),
+        normal_texture: Some("textures/normals.png".into()),
+        ..Material::flat([0.5, 0.25, 1.0, 0.75])
+    };
+    let mut detailed = grid(16, Some(textured.clone()));
+    detailed.generate_levels_of_detail(&[0.25, 0.05]);
+    // Odd string lengths, which the format pads, and a mesh without any optional attributes
+    let bare = Mesh { normals: vec![], uvs: vec![], tangents: vec![], colors: vec![], ..grid(1, None) };
+    let model = Model {
+        parts: vec![ModelPart { name: "terrain".to_string(), meshes: vec![detailed, grid(2, None)] }, ModelPart::new("bare", bare)],
+        materials: vec![textured],
+    };
+
+    let dir = common::TempDir::new("mesh_cache");
+    let path = dir.join("model.meshcache");
+    let sources = ["model.mtl".into()];
+    mesh_cache::write(&model, |part| if part == "bare" { [0.0; 4] } else { [1.0; 4] }, &sources, &path).unwrap();
+    let cache = MeshCache::open(&path).unwrap();
+    let loaded = cache.model();
+
+    assert_eq!(cache.part_names().collect::<Vec<_>>(), ["terrain", "bare"]);
+    assert_eq!(cache.sources(), sources);
+    assert!(cache.has_fallback_colors(|part| if part == "bare" { [0.0; 4] } else { [1.0; 4] }));
+    assert!(!cache.has_fallback_colors(|_| [1.0; 4]));
+    assert_eq!(loaded.materials, model.materials);
+    assert_eq!(loaded.parts.len(), 2);
+    for (loaded, part) in loaded.parts.iter().zip(&model.parts) {
+        assert_eq!(loaded.name, part.name);
+        assert_eq!(loaded.meshes.len(), part.meshes.len());
+        for (a, b) in loaded.meshes.iter().zip(&part.meshes) {
+            assert_same(a, b);
+        }
+    }
+
+    // The views read the mapped file in place, along with the bounds worked out when it was written
+    let views = cache.meshes(0);
+    let levels = &views[0].levels_of_detail;
+    assert_eq!(levels.iter().map(|(ratio, _)| *ratio).collect::<Vec<_>>(), [0.25, 0.05]);
+    assert!(levels[1].1.indices.len() < levels[0].1.indices.len());
+    let (center, radius) = model.parts[0].meshes[0].bounding_sphere();
+    assert_eq!(views[0].bounding_sphere, (center, radius));
+    assert_eq!(views[0].material.map(|material| material.name.as_str()), Some("textured"));
+    assert!(cache.meshes(1)[0].normals.is_empty());
+}
+
+#[test]
+fn open_caches_keep_their_data_when_rewritten() {
+    let dir = common::TempDir::new("mesh_cache_rewritten");
+    let path = dir.join("model.meshcache");
+    let original = grid(4, None);
+    mesh_cache::write(&Model::from_parts(vec![ModelPart::new("grid", grid(4, None))]), |_| [1.0; 4], &[], &path).unwrap();
+    let cache = MeshCache::open(&path).unwrap();
+    let views = cache.meshes(0);
+
+    // A smaller model in its place, which would cut the mapped data short if written over the old file
+    mesh_cache::write(&Model::from_parts(vec![ModelPart::new("grid", grid(1, None))]), |_| [1.0; 4], &[], &path).unwrap();
+    assert_eq!((views[0].positions, views[0].colors, views[0].indices), (&original.vertices[..], &original.colors[..], &original.indices[..]));
+    assert_eq!(MeshCache::open(&path).unwrap().meshes(0)[0].indices.len(), 6);
+    assert_eq!(std::fs::read_dir(&*dir).unwrap().count(), 1);
+}
+
+#[test]
+fn damaged_caches_are_rejected() {
+    let dir = common::TempDir::new("mesh_cache_damaged");
+    let path = dir.join("model.meshcache");
+    let bytes = mesh_cache::encode(&Model::from_parts(vec![ModelPart::new("grid", grid(2, None))]), |_| [1.0; 4], &[]);
+    let error = |bytes: &[u8]| {
+        std::fs::write(&path, bytes).unwrap();
+        MeshCache::open(&path).err().unwrap_or_else(|| panic!("a damaged cache was accepted"))
+    };
+
+    assert!(error(&bytes[..bytes.len() - 4]).contains("truncated"));
+    assert!(error(&[&bytes[..], &[0; 4]].concat()).contains("after the last part"));
+    assert!(error(b"not a cache at all").contains("not a mesh cache"));
+    let mut newer = bytes.clone();
+    newer[8] = mesh_cache::VERSION as u8 + 1;
+    assert!(error(&newer).contains("version"));
+    // The last index points past the 9 vertices of the grid
+    let mut out_of_range = bytes.clone();
+    let last = out_of_range.len() - 4;
+    out_of_range[last..].copy_from_slice(&9u32.to_le_bytes());
+    assert!(error(&out_of_range).contains("past its last vertex"));
+}
+
+#[test]
+fn models_load_from_fresh_caches_only() {
+    let dir = common::TempDir::new("mesh_cache_fresh");
+    let obj_path = dir.write("quad.obj", OBJ);
+    let obj = obj_path.to_str().unwrap();
+    let white = |_: &str| [1.0, 1.0, 1.0, 1.0];
+    let part_names = |model: &Model| model.part_names().map(str::to_string).collect::<Vec<_>>();
+
+    assert!(!mesh_cache::is_fresh(obj));
+    let (cache_path, reports) = mesh_cache::convert(obj, white, &[0.5]).unwrap();
+    assert_eq!(cache_path, dir.join("quad.meshcache"));
+    assert_eq!(reports.iter().map(|(part, _)| part.as_str()).collect::<Vec<_>>(), ["quad"]);
+    assert!(mesh_cache::is_fresh(obj));
+    let converted = MeshCache::open(&cache_path).unwrap().model();
+    assert_eq!(part_names(&converted), ["quad"]);
+    assert_eq!(converted.parts[0].meshes[0].levels_of_detail.len(), 1);
+
+    // A fresh cache is read in place of the OBJ file, whatever it holds
+    mesh_cache::write(&Model::from_parts(vec![ModelPart::new("cached", grid(1, None))]), white, &[], &cache_path).unwrap();
+    assert_eq!(part_names(&Model::load(obj, white).unwrap()), ["cached"]);
+    // Unless it was written with other fallback colours
+    assert_eq!(part_names(&Model::load(obj, |_| [1.0, 0.0, 0.0, 1.0]).unwrap()), ["quad"]);
+
+    // Once the OBJ file changes, the cache is out of date
+    let later = SystemTime::now() + Duration::from_secs(10);
+    std::fs::File::options().write(true).open(&obj_path).unwrap().set_modified(later).unwrap();
+    assert!(!mesh_cache::is_fresh(obj));
+    assert_eq!(part_names(&Model::load(obj, white).unwrap()), ["quad"]);
+
+    // And a damaged cache is passed over, however fresh
+    let bytes = mesh_cache::encode(&Model::from_parts(vec![ModelPart::new("cached", grid(1, None))]), white, &[]);
+    std::fs::write(&cache_path, &bytes[..bytes.len() - 4]).unwrap();
+    std::fs::File::options().write(true).open(&cache_path).unwrap().set_modified(later).unwrap();
+    assert!(mesh_cache::is_fresh(obj));
+    assert_eq!(part_names(&Model::load(obj, white).unwrap()), ["quad"]);
+    std::fs::write(&cache_path, b"GLOOMMSH").unwrap();
+    assert!(!mesh_cache::is_fresh(obj));
+}
+
+#[test]
+fn caches_go_stale_with_their_material_libraries() {
+    let dir = common::TempDir::new("mesh_cache_mtl");
+    let obj_path = dir.write("quad.obj", format!("mtllib quad.mtl\nusemtl red\n{}", OBJ));
+    let obj = obj_path.to_str().unwrap();
+    dir.write("quad.mtl", "newmtl red\nKd 1 0 0\n");
+
+    mesh_cache::convert(obj, |_| [1.0; 4], &[]).unwrap();
+    assert!(mesh_cache::is_fresh(obj));
+    assert_eq!(MeshCache::open(mesh_cache::cache_path(obj)).unwrap().sources(), [std::path::PathBuf::from("quad.mtl")]);
+
+    // Editing the MTL file outdates the cache as editing the OBJ file would
+    let later = SystemTime::now() + Duration::from_secs(10);
+    dir.write("quad.mtl", "newmtl red\nKd 0 1 0\n");
+    std::fs::File::options().write(true).open(dir.join("quad.mtl")).unwrap().set_modified(later).unwrap();
+    assert!(!mesh_cache::is_fresh(obj));
+    let model = Model::load(obj, |_| [1.0; 4]).unwrap();
+    assert_eq!(model.materials[0].diffuse, [0.0, 1.0, 0.0]);
+
+    // As does removing it
+    mesh_cache::convert(obj, |_| [1.0; 4], &[]).unwrap();
+    std::fs::File::options().write(true).open(mesh_cache::cache_path(obj)).unwrap().set_modified(later).unwrap();
+    assert!(mesh_cache::is_fresh(obj));
+    std::fs::remove_file(dir.join("quad.mtl")).unwrap();
+    assert!(!mesh_cache::is_fresh(obj));
+}
@@ -0,0 +1,94 @@
+// Checks generated normals follow the crease angle and weighting, and fill in for OBJ files without any.
+mod common;
+
+use std::f32::consts::PI;
+
+use gloom_rs::mesh::{self, Mesh};
+use gloom_rs::normals::{self, NormalOptions, Weighting};
+
+// A unit cube around the origin with its 8 corners shared between faces
+fn cube() -> (Vec<f32>, Vec<u32>) {
+    let positions = (0..8).flat_map(|i| vec![
+        if i & 1 == 0 { -0.5 } else { 0.5 },
+        if i & 2 == 0 { -0.5 } else { 0.5 },
+        if i & 4 == 0 { -0.5 } else { 0.5 },
+    ]).collect();
+    let indices = vec![
+        0, 2, 3, 0, 3, 1, // -z
+        4, 5, 7, 4, 7, 6, // +z
+        0, 4, 6, 0, 6, 2, // -x
+        1, 3, 7, 1, 7, 5, // +x
+        0, 1, 5, 0, 5, 4, // -y
+        2, 6, 7, 2, 7, 3, // +y
+    ];
+    (positions, indices)
+}
+
+fn normal(normals: &[f32], vertex: u32) -> [f32; 3] {
+    let i = vertex as usize * 3;
+    [normals[i], normals[i + 1], normals[i + 2]]
+}
+
+fn close(a: [f32; 3], b: [f32; 3]) -> bool {
+    a.iter().zip(&b).all(|(a, b)| (a - b).abs() < 1e-5)
+}
+
+#[test]
+fn flat_normals_split_every_corner_of_a_cube() {
+    let (positions, indices) = cube();
+    for options in &[NormalOptions::flat(), NormalOptions::default()] {
+        let generated = normals::generate(&positions, &indices, options);
+        // Each face keeps its two triangles together, with four vertices of its own
+        assert_eq!(generated.remap.len(), 24);
+        for (t, triangle) in generated.indices.chunks(3).enumerate() {
+            let face = t / 2;
+            let mut expected = [0.0; 3];
+            expected[[2, 2, 0, 0, 1, 1][face]] = if face % 2 == 0 { -1.0 } else { 1.0 };
+            assert!(triangle.iter().all(|&v| close(normal(&generated.normals, v), expected)), "face {}", face);
+        }
+    }
+}
+
+#[test]
+fn angle_weighted_smooth_normals_ignore_triangulation() {
+    let (positions, indices) = cube();
+    let generated = normals::generate(&positions, &indices, &NormalOptions::smooth(PI));
+    assert_eq!(generated.remap.len(), 8);
+    for (vertex, &original) in generated.remap.iter().enumerate() {
+        let corner = normal(&positions, original);
+        let expected = corner.map(|c| c * 2.0 / 3f32.sqrt());
+        assert!(close(normal(&generated.normals, vertex as u32), expected));
+    }
+
+    // Corner 1 has two triangles on the +x face, but only one on the -y and -z faces
+    for weighting in &[Weighting::Uniform, Weighting::Area] {
+        let generated = normals::generate(&positions, &indices, &NormalOptions::smooth(PI).weighting(*weighting));
+        let corner = generated.remap.iter().position(|&v| v == 1).unwrap() as u32;
+        let [x, y, z] = normal(&generated.normals, corner);
+        assert!(x > -y && x > -z, "{:?} weighting should lean towards +x", weighting);
+    }
+}
+
+#[test]
+fn obj_meshes_without_normals_get_them_generated() {
+    let dir = common::TempDir::new("normals");
+    let path = dir.write("tent.obj", "v 0 0 0\nv 1 0 0\nv 0 0 -1\nv 1 0 -1\nv 0.5 1 -0.5\nvt 0 0\nvt 1 0\nvt 0 1\nvt 1 1\nvt 0.5 0.5\nf 1/1 2/2 5/5\nf 2/2 4/4 5/5\n");
+    let (models, materials) = mesh::load_obj(path.to_str().unwrap()).unwrap();
+
+    let tent = Mesh::from(models[0].mesh.clone(), &materials, [1.0; 4]);
+    // The two sides meet at 78 degrees, over the default crease angle, so the shared vertices are split
+    assert_eq!(tent.vertices.len(), 6 * 3);
+    assert_eq!(tent.normals.len(), tent.vertices.len());
+    assert_eq!(tent.uvs.len(), 6 * 2);
+    assert_eq!(tent.colors.len(), 6 * 4);
+    assert_eq!(tent.index_count, 6);
+    assert!(close(normal(&tent.normals, tent.indices[0]), [0.0, 0.4472136, 0.8944272]));
+
+    let mut smoothed = Mesh::from(models[0].mesh.clone(), &materials, [1.0; 4]);
+    smoothed.generate_normals(&NormalOptions::smooth(PI / 2.0));
+    // The vertices stay split, but now share a normal
+    assert_eq!(smoothed.vertices.len(), 6 * 3);
+    let apex: Vec<[f32; 3]> = (0..6).filter(|&v| smoothed.vertices[v * 3 + 1] == 1.0).map(|v| normal(&smoothed.normals, v as u32)).collect();
+    assert_eq!(apex.len(), 2);
+    assert!(close(apex[0], apex[1]));
+}
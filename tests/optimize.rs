@@ -0,0 +1,124 @@
+// Checks the optimizations only reorder and merge, and that they actually improve the cache use they measure.
+mod common;
+
+use std::collections::HashSet;
+
+use gloom_rs::mesh::Mesh;
+use gloom_rs::optimize::{self, DEFAULT_CACHE_SIZE};
+
+// Every triangle on its own, as in a triangle soup
+fn unindexed(positions: &[f32], indices: &[u32]) -> (Vec<f32>, Vec<u32>) {
+    let positions = indices.iter().flat_map(|&v| positions[v as usize * 3..v as usize * 3 + 3].to_vec()).collect();
+    (positions, (0..indices.len() as u32).collect())
+}
+
+// The same triangles in a different order, from a fixed pseudo-random sequence
+fn shuffled(indices: &[u32]) -> Vec<u32> {
+    let mut triangles: Vec<&[u32]> = indices.chunks(3).collect();
+    let mut state = 12345u32;
+    for i in (1..triangles.len()).rev() {
+        state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+        triangles.swap(i, (state >> 8) as usize % (i + 1));
+    }
+    triangles.concat()
+}
+
+// The triangles by the positions of their corners, with -0 as 0, starting from the smallest so a rotation of the
+// corners, which keeps the winding, compares equal
+fn triangles(positions: &[f32], indices: &[u32]) -> Vec<[[u32; 3]; 3]> {
+    let mut triangles: Vec<[[u32; 3]; 3]> = indices.chunks(3).map(|t| {
+        let corner = |v: u32| [0, 1, 2].map(|i| (positions[v as usize * 3 + i] + 0.0).to_bits());
+        let corners = [corner(t[0]), corner(t[1]), corner(t[2])];
+        let first = (0..3).min_by_key(|&i| corners[i]).unwrap();
+        [corners[first], corners[(first + 1) % 3], corners[(first + 2) % 3]]
+    }).collect();
+    triangles.sort();
+    triangles
+}
+
+#[test]
+fn identical_vertices_are_merged() {
+    let (positions, indices) = common::sphere(1.0, 8, 16);
+    let (soup, soup_indices) = unindexed(&positions, &indices);
+    let colors = [1.0, 0.5, 0.25, 1.0].repeat(soup.len() / 3);
+    let (remap, deduplicated) = optimize::deduplicate(&[(&soup, 3), (&[], 2), (&colors, 4)], &soup_indices);
+    // Corners at the same position are merged into one vertex, including the doubled ones at the seam and poles
+    let distinct: HashSet<Vec<u32>> = positions.chunks(3).map(|p| p.iter().map(|x| (x + 0.0).to_bits()).collect()).collect();
+    assert_eq!(remap.len(), distinct.len());
+    let merged = gloom_rs::normals::remap(&soup, 3, &remap);
+    assert_eq!(triangles(&merged, &deduplicated), triangles(&soup, &soup_indices));
+
+    // Vertices differing in any attribute stay apart
+    let mut colors = colors;
+    colors[0] = 0.0;
+    let (remap_with_color, _) = optimize::deduplicate(&[(&soup, 3), (&colors, 4)], &soup_indices);
+    assert_eq!(remap_with_color.len(), remap.len() + 1);
+}
+
+#[test]
+fn vertex_cache_order_transforms_fewer_vertices() {
+    let (positions, indices) = common::sphere(1.0, 32, 64);
+    let vertex_count = positions.len() / 3;
+    let scrambled = shuffled(&indices);
+    let before = optimize::analyze_vertex_cache(&scrambled, vertex_count, DEFAULT_CACHE_SIZE);
+    let ordered = optimize::optimize_vertex_cache(&scrambled, vertex_count);
+    let after = optimize::analyze_vertex_cache(&ordered, vertex_count, DEFAULT_CACHE_SIZE);
+
+    assert_eq!(triangles(&positions, &ordered), triangles(&positions, &indices));
+    assert!(before.acmr > 2.5, "ACMR {} before", before.acmr);
+    assert!(after.acmr < 0.8, "ACMR {} after", after.acmr);
+    assert!(after.atvr < 1.6 && after.atvr < before.atvr);
+}
+
+#[test]
+fn overdraw_order_draws_the_outside_first() {
+    // A small sphere inside a large one, which is drawn after it to begin with
+    let (inner, inner_indices) = common::sphere(0.5, 16, 32);
+    let (outer, outer_indices) = common::sphere(1.0, 16, 32);
+    let inner_count = inner.len() as u32 / 3;
+    let positions = [inner, outer].concat();
+    let indices: Vec<u32> = inner_indices.iter().copied().chain(outer_indices.iter().map(|v| v + inner_count)).collect();
+    let vertex_count = positions.len() / 3;
+
+    let ordered = optimize::optimize_vertex_cache(&indices, vertex_count);
+    let before = optimize::analyze_vertex_cache(&ordered, vertex_count, DEFAULT_CACHE_SIZE);
+    let reordered = optimize::optimize_overdraw(&positions, &ordered, optimize::DEFAULT_OVERDRAW_THRESHOLD);
+    let after = optimize::analyze_vertex_cache(&reordered, vertex_count, DEFAULT_CACHE_SIZE);
+
+    assert_eq!(triangles(&positions, &reordered), triangles(&positions, &indices));
+    assert!(reordered[..outer_indices.len()].iter().all(|&v| v >= inner_count));
+    assert!(after.acmr <= before.acmr * 1.1, "ACMR {} went to {}", before.acmr, after.acmr);
+}
+
+#[test]
+fn vertex_fetch_order_follows_first_use() {
+    let indices = [5, 2, 7, 2, 7, 0, 0, 7, 9];
+    let (remap, reordered) = optimize::optimize_vertex_fetch(&indices, 10);
+    assert_eq!(remap, [5, 2, 7, 0, 9]);
+    assert_eq!(reordered, [0, 1, 2, 1, 2, 3, 3, 2, 4]);
+}
+
+#[test]
+fn meshes_are_optimized_in_place() {
+    let (positions, indices) = common::sphere(1.0, 24, 48);
+    let (soup, soup_indices) = unindexed(&positions, &shuffled(&indices));
+    let vertex_count = soup.len() / 3;
+    let normals: Vec<f32> = soup.clone();
+    let colors = soup.chunks(3).flat_map(|p| vec![p[0], p[1], p[2], 1.0]).collect();
+    let mut mesh = Mesh { normals, colors, ..common::mesh(soup, soup_indices) };
+    let original = triangles(&mesh.vertices, &mesh.indices);
+
+    let report = mesh.optimize();
+    assert_eq!(triangles(&mesh.vertices, &mesh.indices), original);
+    assert_eq!(mesh.index_count as usize, mesh.indices.len());
+    assert_eq!(report.vertices_before, vertex_count);
+    assert_eq!(report.vertices_after, mesh.vertices.len() / 3);
+    assert!(report.vertices_after < vertex_count / 4);
+    // A triangle soup transforms every vertex exactly once, but has three of them for every triangle
+    assert_eq!((report.before.acmr, report.before.atvr), (3.0, 1.0));
+    assert!(report.after.acmr < 0.8 && report.after.atvr < 1.6, "{}", report);
+    // The other attributes follow their vertices
+    assert!(mesh.vertices.chunks(3).zip(mesh.colors.chunks(4)).all(|(p, c)| p == &c[..3]));
+    assert_eq!(mesh.normals, mesh.vertices);
+    assert!(report.to_string().contains("ACMR"));
+}
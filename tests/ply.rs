@@ -0,0 +1,91 @@
+// Checks PLY files in every encoding load with their colours, and that meshes come back from them as they went in.
+use gloom_rs::mesh::{Encoding, Mesh};
+use gloom_rs::ply;
+
+const ASCII: &str = "ply
+format ascii 1.0
+comment a quad and a triangle, with byte colours
+element vertex 5
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 2
+property list uchar int vertex_indices
+element edge 1
+property int vertex1
+property int vertex2
+end_header
+0 0 0 255 0 0
+1 0 0 0 255 0
+1 1 0 0 0 255
+0 1 0 255 255 255
+2 2 1 0 0 0
+4 0 1 2 3
+3 1 4 2
+0 1
+";
+
+fn quad() -> Mesh {
+    Mesh {
+        vertices: vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0],
+        normals: [0.0, 0.0, 1.0].repeat(4),
+        uvs: vec![0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0],
+        tangents: vec![],
+        colors: vec![1.0, 0.0, 0.0, 1.0, 0.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.5, 0.2, 0.4, 0.6, 1.0],
+        indices: vec![0, 1, 2, 0, 2, 3],
+        index_count: 6,
+        material: None,
+        levels_of_detail: vec![],
+    }
+}
+
+#[test]
+fn ascii_files_load_with_vertex_colours() {
+    let mesh = ply::parse(ASCII.as_bytes(), [0.5; 4]).unwrap();
+    assert_eq!(mesh.vertices.len(), 15);
+    // The quad is split into a fan, and the edge element is skipped
+    assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3, 1, 4, 2]);
+    assert_eq!(mesh.index_count, 9);
+    assert_eq!(&mesh.colors[..8], &[1.0, 0.0, 0.0, 1.0, 0.0, 1.0, 0.0, 1.0]);
+    assert!(mesh.uvs.is_empty());
+    // Normals are generated, as the file has none
+    assert_eq!(mesh.normals.len(), mesh.vertices.len());
+    assert!(mesh.normals[..3].iter().zip([0.0, 0.0, 1.0]).all(|(a, b)| (a - b).abs() < 1e-5));
+
+    assert!(ply::parse(b"ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nend_header\n0\n", [1.0; 4]).is_err());
+    assert!(ply::parse(b"not a ply file", [1.0; 4]).is_err());
+}
+
+#[test]
+fn meshes_round_trip_in_every_encoding() {
+    let original = quad();
+    for encoding in [Encoding::Ascii, Encoding::Binary] {
+        let mesh = ply::parse(&ply::encode(&original, encoding), [0.0; 4]).unwrap();
+        assert_eq!(mesh.vertices, original.vertices, "{:?}", encoding);
+        assert_eq!(mesh.normals, original.normals);
+        assert_eq!(mesh.uvs, original.uvs);
+        assert_eq!(mesh.indices, original.indices);
+        // Colours are stored as bytes
+        assert!(mesh.colors.iter().zip(&original.colors).all(|(a, b)| (a - b).abs() <= 1.0 / 255.0), "{:?}", mesh.colors);
+    }
+}
+
+#[test]
+fn big_endian_files_load() {
+    let mut bytes = b"ply\nformat binary_big_endian 1.0\nelement vertex 3\nproperty double x\nproperty double y\nproperty double z\nelement face 1\nproperty list uchar ushort vertex_index\nend_header\n".to_vec();
+    for position in [[0.0f64, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]] {
+        bytes.extend(position.iter().flat_map(|x| x.to_be_bytes()));
+    }
+    bytes.push(3);
+    bytes.extend([0u16, 1, 2].iter().flat_map(|i| i.to_be_bytes()));
+    let mesh = ply::parse(&bytes, [0.25, 0.5, 0.75, 1.0]).unwrap();
+    assert_eq!(mesh.vertices, [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+    assert_eq!(mesh.indices, [0, 1, 2]);
+    assert_eq!(mesh.colors, [0.25, 0.5, 0.75, 1.0].repeat(3));
+
+    // A file cut short in its faces
+    assert!(ply::parse(&bytes[..bytes.len() - 1], [1.0; 4]).is_err());
+}
@@ -0,0 +1,101 @@
+// Checks #include and #define handling, and that compiler errors in included files point back at them. The
+// compile test skips itself when no headless OpenGL context can be created, unless GLOOM_REQUIRE_GL is set.
+mod common;
+
+use gloom_rs::preprocessor;
+use gloom_rs::shader::{ShaderBuilder, ShaderError, ShaderType};
+
+fn defines(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs.iter().map(|&(name, value)| (name.to_string(), value.to_string())).collect()
+}
+
+#[test]
+fn defines_follow_the_version() {
+    let source = "// a comment before the version\n#version 430 core\nvoid main() {}\n";
+    let preprocessed = preprocessor::preprocess_source(source, None, &defines(&[("LIGHTS", "4"), ("SHADOWS", "")])).unwrap();
+    let lines: Vec<&str> = preprocessed.source.lines().collect();
+    assert_eq!(lines[..6], ["// a comment before the version", "#version 430 core", "#define LIGHTS 4", "#define SHADOWS ",
+        "#line 3 0", "void main() {}"]);
+}
+
+#[test]
+fn included_lines_keep_their_file_and_line() {
+    let dir = common::TempDir::new("preprocessor_lines");
+    dir.write("lighting.glsl", "#version 430 core\nfloat light() {\n    return 1.0;\n}\n");
+    let main = dir.write("main.frag", "#version 430 core\nout vec4 color;\n#include \"lighting.glsl\"\nvoid main() { color = vec4(light()); }\n");
+    let preprocessed = preprocessor::preprocess_file(&main, &[]).unwrap();
+
+    assert_eq!(preprocessed.files, [main.clone(), dir.join("lighting.glsl")]);
+    let lines: Vec<&str> = preprocessed.source.lines().collect();
+    // Source string 1 is the included file, and the including file carries on after the directive. The included
+    // file's own #version is blanked, keeping its line numbers.
+    assert_eq!(lines, ["#version 430 core", "#line 2 0", "out vec4 color;", "#line 1 1", "", "float light() {", "    return 1.0;",
+        "}", "#line 4 0", "void main() { color = vec4(light()); }"]);
+    assert!(preprocessed.source_string_legend().contains("1 = "));
+}
+
+#[test]
+fn include_cycles_are_errors() {
+    let dir = common::TempDir::new("preprocessor_cycle");
+    dir.write("a.glsl", "// a\n#include \"b.glsl\"\n");
+    dir.write("b.glsl", "\n\n#include \"a.glsl\"\n");
+    let main = dir.write("main.frag", "#version 430 core\n#include \"a.glsl\"\n#include \"a.glsl\"\n");
+
+    match preprocessor::preprocess_file(&main, &[]) {
+        Err(ShaderError::Include { file, line, message }) => {
+            assert_eq!((file, line), (dir.join("b.glsl"), 3));
+            assert!(message.contains("recursively"), "{}", message);
+        },
+        other => panic!("expected an include error, got {:?}", other.map(|p| p.source)),
+    }
+
+    // Including the same file twice without a cycle is fine
+    dir.write("a.glsl", "float a() { return 1.0; }\n");
+    assert_eq!(preprocessor::preprocess_file(&main, &[]).unwrap().files.len(), 2);
+}
+
+#[test]
+fn includes_in_comments_and_disabled_regions_are_left_alone() {
+    let dir = common::TempDir::new("preprocessor_disabled");
+    dir.write("used.glsl", "float used;\n");
+    let main = dir.write("main.frag", "#version 430 core
+/* an example:
+#include \"missing.glsl\"
+*/
+#if 0
+#ifdef ANYTHING
+#include \"missing.glsl\"
+#endif
+#include \"missing.glsl\"
+#else
+#include \"used.glsl\"
+#endif
+// #include \"missing.glsl\"
+");
+    let preprocessed = preprocessor::preprocess_file(&main, &[]).unwrap();
+    assert_eq!(preprocessed.files, [main.clone(), dir.join("used.glsl")]);
+    assert!(preprocessed.source.contains("float used;"));
+    assert_eq!(preprocessed.source.matches("#include \"missing.glsl\"").count(), 4);
+}
+
+#[test]
+fn compile_errors_point_at_the_included_file() {
+    let _context = match common::gl_context("preprocessor compile test") { Some(context) => context, None => return };
+
+    let dir = common::TempDir::new("preprocessor_compile");
+    dir.write("broken.glsl", "float broken() {\n    return 1.0;\n    this is not glsl;\n}\n");
+    let main = dir.write("main.frag", "#version 430 core\nout vec4 color;\n#include \"broken.glsl\"\nvoid main() { color = vec4(broken()); }\n");
+    let error = unsafe {
+        ShaderBuilder::new().attach_path(&main).and_then(|builder| builder.link())
+    }.err().expect("the shader should fail to compile");
+
+    match error {
+        ShaderError::Compile { stage: ShaderType::Fragment, file, log } => {
+            assert_eq!(file, Some(main));
+            // Drivers write the source string number and line as "1:3", or "1(3)"
+            assert!(log.contains("1:3") || log.contains("1(3)"), "{}", log);
+            assert!(log.contains(&format!("1 = {}", dir.join("broken.glsl").display())), "{}", log);
+        },
+        other => panic!("expected a compile error, got {}", other),
+    }
+}
@@ -0,0 +1,95 @@
+// Builds the same program twice through a ProgramCache and checks the second build is loaded from disk, and that
+// cache keys stay the same from one build to the next.
+// The GL test skips itself when no headless OpenGL context can be created or the driver can't save programs,
+// unless GLOOM_REQUIRE_GL is set.
+mod common;
+
+use gloom_rs::program_cache::{CacheKey, ProgramBinary, ProgramCache};
+use gloom_rs::shader::{ShaderBuilder, ShaderType};
+
+const VERTEX: &str = "#version 430 core
+in layout(location=0) vec3 position;
+uniform mat4 transform;
+void main() { gl_Position = transform * vec4(position, 1.0); }";
+
+const FRAGMENT: &str = "#version 430 core
+uniform vec4 tint;
+out vec4 color;
+void main() { color = tint; }";
+
+const KEY_ID: u64 = 0x29d1_4786_dee1_51f6;
+
+unsafe fn build(cache: &ProgramCache, tint_name: &str) -> gloom_rs::shader::Shader {
+    ShaderBuilder::new()
+        .cache(cache.clone())
+        .compile_shader(VERTEX, ShaderType::Vertex)
+        .and_then(|b| b.compile_shader(&FRAGMENT.replace("tint", tint_name), ShaderType::Fragment))
+        .and_then(|b| b.link())
+        .unwrap_or_else(|e| panic!("{}", e))
+}
+
+fn cache_files(cache: &ProgramCache) -> usize {
+    std::fs::read_dir(cache.dir()).map(|entries| entries.count()).unwrap_or(0)
+}
+
+#[test]
+fn linked_programs_are_cached_and_reloaded() {
+    let _context = match common::gl_context("program cache test") { Some(context) => context, None => return };
+
+    unsafe {
+        if !ProgramCache::is_supported() {
+            eprintln!("Skipping program cache test: the driver has no program binary formats");
+            return;
+        }
+
+        let dir = common::TempDir::new("program_cache");
+        let cache = ProgramCache::new(&*dir);
+
+        let first = build(&cache, "tint");
+        assert!(!first.loaded_from_cache());
+        assert_eq!(cache_files(&cache), 1);
+        let second = build(&cache, "tint");
+        assert!(second.loaded_from_cache());
+        assert_eq!(cache_files(&cache), 1);
+        // The cached program is fully usable, reflection included
+        assert!(second.uniforms().contains_key("tint"));
+        assert!(second.uniforms().contains_key("transform"));
+
+        // Changing a source misses the cache
+        let third = build(&cache, "colour");
+        assert!(!third.loaded_from_cache());
+        assert_eq!(cache_files(&cache), 2);
+        assert!(third.uniforms().contains_key("colour"));
+
+        // A corrupt entry falls back to compiling from source
+        for entry in std::fs::read_dir(cache.dir()).unwrap() {
+            std::fs::write(entry.unwrap().path(), b"GLPB\x02\x00\x00\x00garbage").unwrap();
+        }
+        let fourth = build(&cache, "tint");
+        assert!(!fourth.loaded_from_cache());
+        assert!(fourth.uniforms().contains_key("tint"));
+
+        assert_eq!(gl::GetError(), gl::NO_ERROR);
+        drop((first, second, third, fourth));
+        cache.clear().unwrap();
+    }
+}
+
+#[test]
+fn keys_are_stable_and_checked() {
+    let driver = ["Mesa", "llvmpipe", "4.5 (Core Profile) Mesa 24.0"];
+    let key = ProgramCache::key_for_driver(&driver, &[(gl::VERTEX_SHADER, VERTEX), (gl::FRAGMENT_SHADER, FRAGMENT)]);
+    // Pinned, as the key names files written by earlier builds, possibly made with another Rust release
+    assert_eq!(key.id, KEY_ID);
+    assert_ne!(ProgramCache::key_for_driver(&driver, &[(gl::FRAGMENT_SHADER, VERTEX), (gl::VERTEX_SHADER, FRAGMENT)]), key);
+    assert_ne!(ProgramCache::key_for_driver(&driver[..2], &[(gl::VERTEX_SHADER, VERTEX), (gl::FRAGMENT_SHADER, FRAGMENT)]), key);
+
+    // An entry only loads for the digest it was stored with, even when the file name matches
+    let dir = common::TempDir::new("program_cache_keys");
+    let cache = ProgramCache::new(&*dir);
+    cache.store(key, &ProgramBinary { format: 7, data: vec![1, 2, 3] }).unwrap();
+    let loaded = cache.load(key).unwrap();
+    assert_eq!((loaded.format, loaded.data), (7, vec![1, 2, 3]));
+    assert!(cache.load(CacheKey { digest: key.digest ^ 1, ..key }).is_none());
+    cache.clear().unwrap();
+}
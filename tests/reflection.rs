@@ -0,0 +1,62 @@
+// Checks the uniforms and attributes Shader reflects after linking, and the type checks of its setters.
+// Skips itself when no headless OpenGL context can be created, unless GLOOM_REQUIRE_GL is set.
+extern crate nalgebra_glm as glm;
+
+mod common;
+
+use gloom_rs::reflection::UniformError;
+use gloom_rs::shader::{ShaderBuilder, ShaderType};
+
+const VERTEX: &str = "#version 430 core
+in layout(location=0) vec3 position;
+in layout(location=2) vec4 colour;
+uniform mat4 transform;
+uniform float offsets[4];
+out vec4 outColour;
+void main() {
+    gl_Position = transform * vec4(position + offsets[0] + offsets[3], 1.0);
+    outColour = colour;
+}";
+
+const FRAGMENT: &str = "#version 430 core
+in vec4 outColour;
+uniform sampler2D albedo;
+uniform vec3 tint;
+uniform int unused;
+out vec4 color;
+void main() {
+    color = outColour * texture(albedo, vec2(0.5)) * vec4(tint, 1.0);
+}";
+
+#[test]
+fn shader_reflects_uniforms_and_checks_setter_types() {
+    let _context = match common::gl_context("reflection test") { Some(context) => context, None => return };
+
+    unsafe {
+        let shader = ShaderBuilder::new()
+            .compile_shader(VERTEX, ShaderType::Vertex)
+            .and_then(|b| b.compile_shader(FRAGMENT, ShaderType::Fragment))
+            .and_then(|b| b.link())
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        let mut names: Vec<&str> = shader.uniforms().keys().map(String::as_str).collect();
+        names.sort_unstable();
+        assert_eq!(names, ["albedo", "offsets", "tint", "transform"]);
+        assert_eq!(shader.uniforms()["transform"].gl_type, gl::FLOAT_MAT4);
+        assert_eq!(shader.uniforms()["offsets"].array_size, 4);
+
+        let attributes: Vec<(&str, i32)> = shader.attributes().iter().map(|a| (a.name.as_str(), a.location)).collect();
+        assert_eq!(attributes, [("position", 0), ("colour", 2)]);
+
+        shader.set_mat4("transform", &glm::identity()).unwrap();
+        shader.set_vec3("tint", &glm::vec3(1.0, 0.5, 0.25)).unwrap();
+        shader.set_sampler("albedo", 1).unwrap();
+        shader.set_f32("offsets[3]", 2.0).unwrap();
+
+        assert!(matches!(shader.set_f32("tint", 1.0), Err(UniformError::TypeMismatch { .. })));
+        assert!(matches!(shader.set_i32("albedo", 1), Err(UniformError::TypeMismatch { .. })));
+        assert!(matches!(shader.set_i32("unused", 1), Err(UniformError::Inactive { .. })));
+        assert!(matches!(shader.set_f32("offsets[4]", 1.0), Err(UniformError::IndexOutOfBounds { .. })));
+        assert_eq!(gl::GetError(), gl::NO_ERROR);
+    }
+}
@@ -0,0 +1,159 @@
+// Checks the scene graph keeps its hierarchy consistent as nodes are attached, moved and removed, that handles to
+// removed nodes stay invalid when their slots are reused, and that nodes turn the way they are told to.
+extern crate nalgebra_glm as glm;
+
+use std::f32::consts::FRAC_PI_2;
+
+use gloom_rs::scene_graph::{self, RotationOrder, SceneGraph, SceneNode};
+
+fn close(a: &glm::Vec3, b: &glm::Vec3) -> bool {
+    glm::distance(a, b) < 1e-5
+}
+
+// Where the node's rotation takes `v`, by its matrix and by its quaternion, which have to agree
+fn rotated(node: &SceneNode, v: glm::Vec3) -> glm::Vec3 {
+    let by_matrix = (node.rotation_matrix() * glm::vec4(v.x, v.y, v.z, 0.0)).xyz();
+    let by_quat = glm::quat_rotate_vec3(&node.rotation_quat(), &v);
+    assert!(close(&by_matrix, &by_quat), "{:?} and {:?}", by_matrix, by_quat);
+    by_matrix
+}
+
+#[test]
+fn handles_to_removed_nodes_stay_invalid() {
+    let mut graph = SceneGraph::new();
+    let root = graph.add(SceneNode::from_vao(1, 3));
+    let child = graph.add(SceneNode::from_vao(2, 3));
+    let grandchild = graph.add(SceneNode::from_vao(3, 3));
+    graph.add_child(root, child);
+    graph.add_child(child, grandchild);
+
+    // Removing a node takes its whole subtree with it and detaches it from its parent
+    let removed = graph.remove(child);
+    assert_eq!(removed.vao_id, 2);
+    assert_eq!(graph.len(), 1);
+    assert!(graph.children(root).is_empty());
+    assert!(!graph.contains(child) && !graph.contains(grandchild));
+
+    // New nodes reuse the freed slots, but the old handles don't see them
+    let reused = [graph.add(SceneNode::from_vao(4, 3)), graph.add(SceneNode::from_vao(5, 3))];
+    assert_eq!(graph.len(), 3);
+    assert!(graph.get(child).is_none() && graph.get_mut(grandchild).is_none());
+    assert!(reused.iter().all(|&node| graph.contains(node) && node != child && node != grandchild));
+    assert_eq!(graph[reused[0]].vao_id + graph[reused[1]].vao_id, 9);
+}
+
+#[test]
+#[should_panic(expected = "Invalid scene node handle")]
+fn indexing_with_a_stale_handle_panics() {
+    let mut graph = SceneGraph::new();
+    let node = graph.add(SceneNode::new());
+    graph.remove(node);
+    graph.add(SceneNode::new());
+    let _ = &graph[node];
+}
+
+#[test]
+fn reparenting_moves_the_whole_subtree() {
+    let mut graph = SceneGraph::new();
+    let [a, b, c, d] = [(); 4].map(|_| graph.add(SceneNode::new()));
+    graph.add_child(a, b);
+    graph.add_child(b, c);
+    graph.add_child(a, d);
+
+    graph.reparent(b, d);
+    assert_eq!(graph.children(a), [d]);
+    assert_eq!(graph.children(d), [b]);
+    assert_eq!(graph.parent(b), Some(d));
+    assert_eq!(graph.parent(c), Some(b));
+    assert!(graph.is_ancestor(a, c) && graph.is_ancestor(d, c) && !graph.is_ancestor(c, d));
+
+    graph.detach(d);
+    assert_eq!(graph.parent(d), None);
+    assert!(graph.children(a).is_empty());
+    assert_eq!(graph.descendants(d), [d, b, c]);
+}
+
+#[test]
+#[should_panic(expected = "itself or one of its descendants")]
+fn attaching_a_node_below_itself_panics() {
+    let mut graph = SceneGraph::new();
+    let [a, b, c] = [(); 3].map(|_| graph.add(SceneNode::new()));
+    graph.add_child(a, b);
+    graph.add_child(b, c);
+    graph.add_child(c, a);
+}
+
+#[test]
+fn descendants_are_listed_depth_first_in_child_order() {
+    let mut graph = SceneGraph::new();
+    let [root, a, b, a1, a2, b1] = [(); 6].map(|_| graph.add(SceneNode::new()));
+    graph.add_child(root, a);
+    graph.add_child(root, b);
+    graph.add_child(a, a1);
+    graph.add_child(a, a2);
+    graph.add_child(b, b1);
+
+    assert_eq!(graph.descendants(root), [root, a, a1, a2, b, b1]);
+    assert_eq!(graph.descendants(b), [b, b1]);
+    assert_eq!(graph.descendants(a2), [a2]);
+}
+
+#[test]
+fn euler_angles_apply_in_their_rotation_order() {
+    let x = glm::vec3(1.0, 0.0, 0.0);
+    let node = |rotation: glm::Vec3, rotation_order: RotationOrder| {
+        let mut node = SceneNode::new();
+        node.rotation = rotation;
+        node.rotation_order = rotation_order;
+        node
+    };
+    // A quarter turn around Y takes X to -Z, and a quarter turn around X takes -Z on to +Y
+    assert!(close(&rotated(&node(glm::vec3(FRAC_PI_2, FRAC_PI_2, 0.0), RotationOrder::XYZ), x), &glm::vec3(0.0, 0.0, -1.0)));
+    assert!(close(&rotated(&node(glm::vec3(FRAC_PI_2, FRAC_PI_2, 0.0), RotationOrder::YXZ), x), &glm::vec3(0.0, 1.0, 0.0)));
+    // A quarter turn around Z takes X to +Y, and one around X takes +Y on to +Z
+    assert!(close(&rotated(&node(glm::vec3(FRAC_PI_2, 0.0, FRAC_PI_2), RotationOrder::ZXY), x), &glm::vec3(0.0, 0.0, 1.0)));
+    assert!(close(&rotated(&node(glm::vec3(FRAC_PI_2, 0.0, FRAC_PI_2), RotationOrder::XZY), x), &glm::vec3(0.0, 1.0, 0.0)));
+    assert_eq!(SceneNode::new().rotation_order, RotationOrder::XYZ);
+}
+
+#[test]
+fn nodes_look_at_their_targets() {
+    let forward = glm::vec3(0.0, 0.0, -1.0);
+    let up = glm::vec3(0.0, 1.0, 0.0);
+    let mut node = SceneNode::new();
+    node.position = glm::vec3(1.0, 2.0, 3.0);
+
+    node.look_at(&glm::vec3(1.0, 2.0, -7.0), &up);
+    assert!(close(&rotated(&node, forward), &forward) && close(&rotated(&node, up), &up));
+    node.look_at(&glm::vec3(6.0, 2.0, 3.0), &up);
+    assert!(close(&rotated(&node, forward), &glm::vec3(1.0, 0.0, 0.0)) && close(&rotated(&node, up), &up));
+
+    // Looking straight along `up` still gives a proper rotation
+    node.look_at(&glm::vec3(1.0, 12.0, 3.0), &up);
+    assert!(close(&rotated(&node, forward), &up));
+    assert!((glm::quat_length(&node.rotation_quat()) - 1.0).abs() < 1e-5);
+    // While looking at its own position, or turning around no axis, leaves the node as it was
+    let before = node.rotation_quat();
+    node.look_at(&glm::vec3(1.0, 2.0, 3.0), &up);
+    node.rotate_around_axis(1.0, &glm::zero());
+    assert_eq!(node.rotation_quat(), before);
+
+    node.rotate_around_axis(FRAC_PI_2, &glm::vec3(0.0, 0.0, 2.0));
+    assert!(close(&rotated(&node, forward), &glm::vec3(-1.0, 0.0, 0.0)));
+}
+
+#[test]
+fn slerp_takes_the_shorter_way_around() {
+    let identity = glm::quat_identity();
+    let quarter_turn = glm::quat_angle_axis(FRAC_PI_2, &glm::vec3(0.0, 1.0, 0.0));
+    let eighth_turn = glm::vec3(std::f32::consts::FRAC_1_SQRT_2, 0.0, -std::f32::consts::FRAC_1_SQRT_2);
+    let x = glm::vec3(1.0, 0.0, 0.0);
+
+    assert!(close(&glm::quat_rotate_vec3(&scene_graph::slerp(&identity, &quarter_turn, 0.5), &x), &eighth_turn));
+    // The negated quaternion is the same rotation, which must not send the interpolation the long way round
+    assert!(close(&glm::quat_rotate_vec3(&scene_graph::slerp(&identity, &-quarter_turn, 0.5), &x), &eighth_turn));
+
+    let mut node = SceneNode::new();
+    node.slerp_towards(&quarter_turn, 1.0);
+    assert!(close(&rotated(&node, x), &glm::vec3(0.0, 0.0, -1.0)));
+}
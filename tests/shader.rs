@@ -0,0 +1,106 @@
+// Checks how ShaderBuilder reports errors, and that hot reloading swaps the program only when the new sources build.
+// Skips itself when no headless OpenGL context can be created, unless GLOOM_REQUIRE_GL is set.
+mod common;
+
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use gloom_rs::shader::{ShaderBuilder, ShaderError, ShaderType};
+
+const VERTEX: &str = "#version 430 core
+in layout(location=0) vec3 position;
+void main() { gl_Position = vec4(position, 1.0); }";
+
+const FRAGMENT: &str = "#version 430 core
+uniform vec4 tint;
+out vec4 color;
+void main() { color = tint; }
+";
+
+// Rewrites a file with a modification time in the future, so the change is seen even on coarse file system clocks
+fn rewrite(path: &Path, contents: &str, seconds_ahead: u64) {
+    std::fs::write(path, contents).unwrap();
+    let file = std::fs::File::options().write(true).open(path).unwrap();
+    file.set_modified(SystemTime::now() + Duration::from_secs(seconds_ahead)).unwrap();
+}
+
+unsafe fn current_program() -> u32 {
+    let mut program = 0;
+    gl::GetIntegerv(gl::CURRENT_PROGRAM, &mut program);
+    program as u32
+}
+
+#[test]
+fn builder_errors_name_what_failed() {
+    let _context = match common::gl_context("shader error test") { Some(context) => context, None => return };
+    let dir = common::TempDir::new("shader_errors");
+    let text = dir.write("notes.txt", "");
+
+    unsafe {
+        match ShaderBuilder::new().attach_path(&text) {
+            Err(ShaderError::UnknownExtension { path }) => assert_eq!(path, text),
+            _ => panic!("expected an unknown extension error"),
+        }
+        match ShaderBuilder::new().attach_path(&dir.join("missing.frag")) {
+            Err(ShaderError::Io { path, .. }) => assert_eq!(path, dir.join("missing.frag")),
+            _ => panic!("expected an IO error"),
+        }
+        let broken = ShaderBuilder::new().compile_shader("#version 430 core\nvoid main() { nonsense; }", ShaderType::Vertex)
+            .and_then(|builder| builder.link());
+        match broken {
+            Err(ShaderError::Compile { stage: ShaderType::Vertex, file: None, log }) => assert!(!log.is_empty()),
+            _ => panic!("expected a compile error"),
+        }
+        // The fragment shader reads an input the vertex shader never writes
+        let mismatched = ShaderBuilder::new()
+            .compile_shader(VERTEX, ShaderType::Vertex)
+            .and_then(|builder| builder.compile_shader("#version 430 core\nin vec3 normal;\nout vec4 color;\nvoid main() { color = vec4(normal, 1.0); }", ShaderType::Fragment))
+            .and_then(|builder| builder.link());
+        match mismatched {
+            Err(error @ ShaderError::Link { .. }) => assert!(error.to_string().contains("failed to link"), "{}", error),
+            _ => panic!("expected a link error"),
+        }
+    }
+}
+
+#[test]
+fn reloads_keep_the_old_program_until_the_new_one_builds() {
+    let _context = match common::gl_context("shader reload test") { Some(context) => context, None => return };
+    let dir = common::TempDir::new("shader_reload");
+    let fragment = dir.write("tint.frag", FRAGMENT);
+
+    unsafe {
+        // The vertex stage comes from a string, which reloads have to keep
+        let mut shader = ShaderBuilder::new()
+            .compile_shader(VERTEX, ShaderType::Vertex).unwrap()
+            .attach_path(&fragment).unwrap()
+            .link().unwrap();
+        shader.activate();
+        let original = shader.program_id();
+        assert!(!shader.reload_if_changed());
+
+        // A broken source keeps the program as it was, and isn't retried until the file changes again
+        rewrite(&fragment, "#version 430 core\nout vec4 color;\nvoid main() { color = missing; }\n", 10);
+        assert!(!shader.reload_if_changed());
+        assert_eq!(shader.program_id(), original);
+        assert_eq!(current_program(), original);
+        assert!(matches!(shader.reload(), Err(ShaderError::Compile { stage: ShaderType::Fragment, .. })));
+        assert!(!shader.reload_if_changed());
+
+        // A fixed source replaces the program, which takes the old one's place as the current program
+        rewrite(&fragment, &FRAGMENT.replace("color = tint;", "color = tint * strength;").replace("out vec4", "uniform float strength;\nout vec4"), 20);
+        assert!(shader.reload_if_changed());
+        assert_ne!(shader.program_id(), original);
+        assert_eq!(current_program(), shader.program_id());
+        assert!(shader.uniforms().contains_key("strength"));
+        assert_eq!(shader.attribute_location("position"), Some(0));
+
+        // A program which isn't current stays that way
+        gl::UseProgram(0);
+        let replaced = shader.program_id();
+        rewrite(&fragment, FRAGMENT, 30);
+        assert!(shader.reload_if_changed());
+        assert_ne!(shader.program_id(), replaced);
+        assert_eq!(current_program(), 0);
+    }
+}
@@ -0,0 +1,148 @@
+// Checks the simplifier reaches its targets while keeping borders and seams, and that nodes pick a level of
+// detail by their size on screen.
+extern crate nalgebra_glm as glm;
+
+mod common;
+
+use std::collections::HashSet;
+
+use gloom_rs::mesh::{Mesh, Model, ModelPart};
+use gloom_rs::render::{LodLevel, UploadedModel};
+use gloom_rs::scene_graph::{LevelOfDetail, SceneGraph, SceneNode};
+use gloom_rs::simplify::{self, SimplifyOptions};
+
+fn used_positions(positions: &[f32], indices: &[u32]) -> HashSet<[u32; 3]> {
+    indices.iter().map(|&v| {
+        let p = &positions[v as usize * 3..v as usize * 3 + 3];
+        [p[0], p[1], p[2]].map(f32::to_bits)
+    }).collect()
+}
+
+fn is_border(positions: &[f32], vertex: u32, n: u32) -> bool {
+    let (x, z) = (positions[vertex as usize * 3], positions[vertex as usize * 3 + 2]);
+    x == 0.0 || z == 0.0 || x == n as f32 || z == n as f32
+}
+
+#[test]
+fn flat_grids_collapse_to_their_corners() {
+    let (positions, indices) = common::grid(10);
+    let simplified = simplify::simplify(&positions, &indices, 0, &SimplifyOptions { max_error: 1e-3, lock_borders: false });
+    assert_eq!(simplified.indices.len(), 6);
+    assert!(simplified.error < 1e-3);
+    let corners: HashSet<[u32; 3]> = [[0.0, 0.0, 0.0], [10.0, 0.0, 0.0], [0.0, 0.0, 10.0], [10.0, 0.0, 10.0]]
+        .iter().map(|p: &[f32; 3]| p.map(f32::to_bits)).collect();
+    assert_eq!(used_positions(&positions, &simplified.indices), corners);
+}
+
+#[test]
+fn locked_borders_and_seams_stay_put() {
+    let (positions, indices) = common::grid(10);
+    let locked = simplify::simplify(&positions, &indices, 0, &SimplifyOptions { max_error: f32::INFINITY, lock_borders: true });
+    let border: HashSet<u32> = (0..121).filter(|&v| is_border(&positions, v, 10)).collect();
+    assert_eq!(border.len(), 40);
+    let used: HashSet<u32> = locked.indices.iter().copied().collect();
+    assert!(border.is_subset(&used));
+    assert!(locked.indices.len() < indices.len());
+
+    // Give the column at x = 5 a second set of vertices, used by the quads to its right, as a texture seam would
+    let (mut positions, mut indices) = common::grid(10);
+    let seam: Vec<u32> = (0..=10).map(|z| z * 11 + 5).collect();
+    for (i, &vertex) in seam.iter().enumerate() {
+        let p = positions[vertex as usize * 3..vertex as usize * 3 + 3].to_vec();
+        positions.extend_from_slice(&p);
+        let duplicate = 121 + i as u32;
+        for triangle in indices.chunks_mut(3) {
+            let right_of_seam = triangle.iter().any(|&v| positions[v as usize * 3] > 5.0);
+            for corner in triangle.iter_mut().filter(|corner| **corner == vertex) {
+                if right_of_seam {
+                    *corner = duplicate;
+                }
+            }
+        }
+    }
+    let simplified = simplify::simplify(&positions, &indices, 0, &SimplifyOptions::default());
+    let used: HashSet<u32> = simplified.indices.iter().copied().collect();
+    for (i, &vertex) in seam.iter().enumerate() {
+        assert!(used.contains(&vertex) && used.contains(&(121 + i as u32)), "seam vertex {} was collapsed", vertex);
+    }
+}
+
+#[test]
+fn lod_chains_shrink_a_curved_mesh() {
+    // A bumpy 32 by 32 heightfield, with its heights as colours so the vertices can be told apart afterwards
+    let (mut vertices, indices) = common::grid(32);
+    for p in vertices.chunks_mut(3) {
+        p[1] = (p[0] * 0.4).sin() * (p[2] * 0.3).cos();
+    }
+    let colors = vertices.chunks(3).flat_map(|p| vec![p[1], 0.0, 0.0, 1.0]).collect();
+    let mesh = Mesh { colors, ..common::mesh(vertices, indices) };
+
+    let chain = mesh.lod_chain(&[1024, 256, 64], &SimplifyOptions::default());
+    assert_eq!(chain.len(), 3);
+    let mut previous = mesh.indices.len();
+    for (level, &target) in chain.iter().zip(&[1024, 256, 64]) {
+        assert!(level.indices.len() / 3 <= target, "{} triangles left, {} wanted", level.indices.len() / 3, target);
+        assert!(level.indices.len() < previous);
+        previous = level.indices.len();
+        // Unused vertices are dropped, and every vertex keeps its own attributes
+        let vertex_count = level.vertices.len() / 3;
+        assert_eq!(level.indices.iter().copied().collect::<HashSet<_>>().len(), vertex_count);
+        assert_eq!((level.normals.len(), level.colors.len()), (vertex_count * 3, vertex_count * 4));
+        assert!(level.vertices.chunks(3).zip(level.colors.chunks(4)).all(|(p, c)| p[1] == c[0]));
+    }
+
+    let (center, radius) = mesh.bounding_sphere();
+    assert!((center.x - 16.0).abs() < 1e-5 && (center.z - 16.0).abs() < 1e-5);
+    assert!(radius >= 16.0 * 2f32.sqrt());
+}
+
+#[test]
+fn nodes_switch_to_coarser_levels_as_they_shrink_on_screen() {
+    let mut node = SceneNode::from_vao(1, 300);
+    node.bounding_sphere = (glm::zero(), 1.0);
+    node.levels_of_detail = vec![
+        LevelOfDetail { vao_id: 2, index_count: 30, max_screen_size: 0.5 },
+        LevelOfDetail { vao_id: 3, index_count: 3, max_screen_size: 0.1 },
+    ];
+    let projection = glm::perspective(1.0, std::f32::consts::FRAC_PI_2, 0.1, 1000.0);
+    let at_distance = |distance: f32| {
+        let view = glm::look_at(&glm::vec3(0.0, 0.0, distance), &glm::zero(), &glm::vec3(0.0, 1.0, 0.0));
+        node.screen_size(&(projection * view))
+    };
+
+    // With a 90 degree field of view, a unit sphere 10 away covers a tenth of the viewport height
+    assert!((at_distance(10.0) - 0.1).abs() < 1e-4);
+    assert_eq!(at_distance(0.5), f32::INFINITY);
+    assert_eq!(node.mesh_for_screen_size(at_distance(1.5)), (1, 300));
+    assert_eq!(node.mesh_for_screen_size(at_distance(5.0)), (2, 30));
+    assert_eq!(node.mesh_for_screen_size(at_distance(50.0)), (3, 3));
+}
+
+#[test]
+fn loaded_levels_are_used_only_for_the_ratios_they_were_made_for() {
+    let _context = match common::gl_context("loaded_levels_are_used_only_for_the_ratios_they_were_made_for") {
+        Some(context) => context,
+        None => return,
+    };
+    let (vertices, indices) = common::grid(8);
+    let mut mesh = common::mesh(vertices, indices);
+    // A stand-in level with a single triangle, which simplifying the grid would never give
+    let (mut vertices, mut indices) = common::grid(1);
+    vertices.truncate(9);
+    indices.truncate(3);
+    mesh.levels_of_detail = vec![(0.5, common::mesh(vertices, indices))];
+    let model = Model { parts: vec![ModelPart::new("grid", mesh)], materials: vec![] };
+
+    let level_index_count = |ratio: f32| {
+        let uploaded = unsafe { UploadedModel::with_levels_of_detail(&model, &[LodLevel { triangle_ratio: ratio, max_screen_size: 0.5 }]) };
+        let mut graph = SceneGraph::new();
+        let instance = uploaded.instantiate(&mut graph);
+        let node = graph.get(instance.part("grid").unwrap()).unwrap();
+        assert_eq!(node.levels_of_detail.len(), 1);
+        node.levels_of_detail[0].index_count
+    };
+    assert_eq!(level_index_count(0.5), 3);
+    // Any other ratio simplifies the grid's 128 triangles again
+    let index_count = level_index_count(0.25);
+    assert!(index_count > 3 && index_count <= 32 * 3, "{} indices", index_count);
+}
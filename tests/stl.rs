@@ -0,0 +1,69 @@
+// Checks STL files in either encoding load as flat shaded meshes, and that meshes come back from them as they went in.
+use gloom_rs::mesh::{Encoding, Mesh};
+use gloom_rs::stl;
+
+const ASCII: &str = "solid square
+  facet normal 0 0 0
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 1 1 0
+    endloop
+  endfacet
+  facet normal 0 0 2
+    outer loop
+      vertex 0 0 0
+      vertex 1 1 0
+      vertex 0 1 0
+    endloop
+  endfacet
+  facet normal 0 -1 0
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 0 1
+    endloop
+  endfacet
+endsolid square
+";
+
+// The corners of every triangle, rounded off
+fn triangles(mesh: &Mesh) -> Vec<[[i32; 3]; 3]> {
+    let corner = |v: u32| [0, 1, 2].map(|i| (mesh.vertices[v as usize * 3 + i] * 1000.0).round() as i32);
+    mesh.indices.chunks(3).map(|t| [corner(t[0]), corner(t[1]), corner(t[2])]).collect()
+}
+
+#[test]
+fn facets_keep_their_own_normals() {
+    let mesh = stl::parse(ASCII.as_bytes(), [1.0, 0.0, 0.0, 1.0]).unwrap();
+    assert_eq!(mesh.indices.len(), 9);
+    // The square's corners are shared, while the corner at the fold is split by its normal
+    assert_eq!(mesh.vertices.len() / 3, 7);
+    for triangle in mesh.indices.chunks(3) {
+        let normal = |v: u32| mesh.normals[v as usize * 3..v as usize * 3 + 3].to_vec();
+        assert!(triangle.iter().all(|&v| normal(v) == normal(triangle[0])));
+    }
+    // A zero normal is computed from the winding, a given one is normalized
+    assert_eq!(&mesh.normals[mesh.indices[0] as usize * 3..][..3], &[0.0, 0.0, 1.0]);
+    assert_eq!(&mesh.normals[mesh.indices[6] as usize * 3..][..3], &[0.0, -1.0, 0.0]);
+    assert_eq!(mesh.colors, [1.0, 0.0, 0.0, 1.0].repeat(7));
+
+    assert!(stl::parse(b"solid broken\n  facet normal 0 0 1\n    outer loop\n      vertex 0 0 0\n", [1.0; 4]).is_err());
+}
+
+#[test]
+fn meshes_round_trip_in_either_encoding() {
+    let original = stl::parse(ASCII.as_bytes(), [1.0; 4]).unwrap();
+    for encoding in [Encoding::Ascii, Encoding::Binary] {
+        let bytes = stl::encode(&original, encoding);
+        let mesh = stl::parse(&bytes, [1.0; 4]).unwrap();
+        assert_eq!(triangles(&mesh), triangles(&original), "{:?}", encoding);
+        assert_eq!(mesh.normals, original.normals);
+    }
+
+    // A binary file whose header starts with "solid" is still read as binary
+    let mut bytes = stl::encode(&original, Encoding::Binary);
+    bytes[..5].copy_from_slice(b"solid");
+    assert_eq!(triangles(&stl::parse(&bytes, [1.0; 4]).unwrap()), triangles(&original));
+    assert!(stl::parse(&bytes[..bytes.len() - 10], [1.0; 4]).is_err());
+}
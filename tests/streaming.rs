@@ -0,0 +1,78 @@
+// Checks vertex data can be replaced in place, in part or in whole, and streamed through a ring buffer.
+// Skips itself when no headless OpenGL context can be created, unless GLOOM_REQUIRE_GL is set.
+mod common;
+
+use gloom_rs::gl_objects::Buffer;
+use gloom_rs::render;
+use gloom_rs::streaming::{RingBuffer, StreamingMesh};
+use gloom_rs::vertex_layout::{VertexAttribute, VertexLayout, VertexStreams, POSITION};
+
+unsafe fn read_floats(buffer: &Buffer, byte_offset: usize, count: usize) -> Vec<f32> {
+    let mut data = vec![0.0f32; count];
+    gl::BindBuffer(gl::COPY_READ_BUFFER, buffer.id());
+    gl::GetBufferSubData(gl::COPY_READ_BUFFER, byte_offset as isize, (count * 4) as isize, data.as_mut_ptr() as *mut std::ffi::c_void);
+    data
+}
+
+fn positions_layout() -> VertexLayout {
+    VertexLayout::separate(vec![VertexAttribute::new(POSITION, 0, 3)])
+}
+
+#[test]
+fn vertices_are_updated_without_recreating_the_vertex_array() {
+    let _context = match common::gl_context("vertex update test") { Some(context) => context, None => return };
+    let layout = positions_layout();
+    let positions: Vec<f32> = (0..9).map(|i| i as f32).collect();
+    unsafe {
+        let mut vao = render::create_vao_with_layout(&VertexStreams::new().with(POSITION, &positions, 3), &[0, 1, 2], &layout).unwrap();
+        let vao_id = vao.id();
+        let buffer_id = vao.buffers()[0].id();
+
+        // Only the last two vertices are taken from the new data
+        let moved: Vec<f32> = positions.iter().map(|p| p + 100.0).collect();
+        render::update_vertices(&vao, &VertexStreams::new().with(POSITION, &moved, 3), &layout, 1..3).unwrap();
+        assert_eq!(read_floats(&vao.buffers()[0], 0, 9), vec![0.0, 1.0, 2.0, 103.0, 104.0, 105.0, 106.0, 107.0, 108.0]);
+        assert!(render::update_vertices(&vao, &VertexStreams::new().with(POSITION, &moved, 3), &layout, 2..4).is_err());
+
+        // Orphaning may change the vertex count, but keeps the same objects
+        let grown: Vec<f32> = (0..12).map(|i| -(i as f32)).collect();
+        render::replace_vertices(&mut vao, &VertexStreams::new().with(POSITION, &grown, 3), &layout, gl::DYNAMIC_DRAW).unwrap();
+        render::replace_indices(&mut vao, &[0, 1, 2, 0, 2, 3], gl::DYNAMIC_DRAW);
+        assert_eq!(vao.id(), vao_id);
+        assert_eq!(vao.buffers()[0].id(), buffer_id);
+        assert_eq!(vao.buffers()[0].size(), 48);
+        assert_eq!(read_floats(&vao.buffers()[0], 0, 12), grown);
+        assert_eq!(gl::GetError(), gl::NO_ERROR);
+    }
+}
+
+#[test]
+fn streaming_mesh_cycles_through_ring_sections() {
+    let _context = match common::gl_context("streaming mesh test") { Some(context) => context, None => return };
+    unsafe {
+        if !RingBuffer::is_supported() {
+            eprintln!("Skipping streaming mesh test: persistently mapped buffers are not supported");
+            return;
+        }
+        let mut mesh = StreamingMesh::new(&positions_layout(), 3, &[0, 1, 2]).unwrap();
+        let mut offsets = vec![];
+        for frame in 0..4 {
+            let positions: Vec<f32> = (0..9).map(|i| (frame * 10 + i) as f32).collect();
+            mesh.update(&VertexStreams::new().with(POSITION, &positions, 3)).unwrap();
+
+            let mut offset = 0;
+            gl::BindVertexArray(mesh.vao().id());
+            gl::GetIntegeri_v(gl::VERTEX_BINDING_OFFSET, 0, &mut offset);
+            gl::BindVertexArray(0);
+            assert_eq!(read_floats(mesh.ring().buffer(), offset as usize, 9), positions);
+            offsets.push(offset);
+        }
+        // Three sections, so the fourth frame reuses the first one
+        assert_eq!(offsets[0], offsets[3]);
+        assert!(offsets[0] != offsets[1] && offsets[1] != offsets[2] && offsets[0] != offsets[2]);
+
+        let too_many = [0.0; 12];
+        assert!(mesh.update(&VertexStreams::new().with(POSITION, &too_many, 3)).is_err());
+        assert_eq!(gl::GetError(), gl::NO_ERROR);
+    }
+}
@@ -0,0 +1,54 @@
+// Checks MikkTSpace tangents follow the texture coordinates, with vertices split where mirrored UVs meet.
+mod common;
+
+use gloom_rs::mesh::{self, Mesh};
+use gloom_rs::tangents;
+
+// Two quads side by side facing +z, sharing the vertices at x = 1. The right one mirrors its texture.
+const POSITIONS: [f32; 18] = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 2.0, 1.0, 0.0];
+const UVS: [f32; 12] = [0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 0.0, 1.0];
+const INDICES: [u32; 12] = [0, 1, 4, 0, 4, 3, 1, 2, 5, 1, 5, 4];
+
+fn normals() -> Vec<f32> {
+    [0.0, 0.0, 1.0].repeat(6)
+}
+
+fn tangent(tangents: &[f32], vertex: u32) -> [f32; 4] {
+    let i = vertex as usize * 4;
+    [tangents[i], tangents[i + 1], tangents[i + 2], tangents[i + 3]]
+}
+
+#[test]
+fn tangents_follow_uvs_and_split_mirrored_seams() {
+    let generated = tangents::generate(&POSITIONS, &normals(), &UVS, &INDICES).unwrap();
+    // Both shared vertices are split in two
+    assert_eq!(generated.remap.len(), 8);
+    for (t, triangle) in generated.indices.chunks(3).enumerate() {
+        let expected = if t < 2 { [1.0, 0.0, 0.0, 1.0] } else { [-1.0, 0.0, 0.0, -1.0] };
+        for &vertex in triangle {
+            let found = tangent(&generated.tangents, vertex);
+            assert!(found.iter().zip(&expected).all(|(a, b)| (a - b).abs() < 1e-5), "{:?} in triangle {}", found, t);
+        }
+    }
+
+    assert!(tangents::generate(&POSITIONS, &normals(), &[], &INDICES).is_err());
+}
+
+#[test]
+fn normal_mapped_obj_meshes_get_tangents() {
+    let dir = common::TempDir::new("tangents");
+    dir.write("quad.mtl", "newmtl bumpy\nKd 1 1 1\nmap_Bump bumps.png\n");
+    let path = dir.write("quad.obj", "mtllib quad.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nusemtl bumpy\nf 1/1 2/2 3/3 4/4\n");
+    let (models, materials) = mesh::load_obj(path.to_str().unwrap()).unwrap();
+
+    let quad = Mesh::from(models[0].mesh.clone(), &materials, [1.0; 4]);
+    assert_eq!(quad.tangents.len(), 4 * 4);
+    assert_eq!(quad.normals.len(), 4 * 3);
+    assert!(quad.tangents.chunks(4).all(|t| (t[0] - 1.0).abs() < 1e-5 && t[3] == 1.0));
+
+    // Without a normal map, tangents are only made on request
+    let mut plain = Mesh::from(models[0].mesh.clone(), &[], [1.0; 4]);
+    assert!(plain.tangents.is_empty());
+    plain.generate_tangents().unwrap();
+    assert_eq!(plain.tangents, quad.tangents);
+}
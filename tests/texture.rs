@@ -0,0 +1,86 @@
+// Checks textures are uploaded the right way up with mipmaps and sampling options, and bound through shaders.
+// Skips itself when no headless OpenGL context can be created, unless GLOOM_REQUIRE_GL is set.
+mod common;
+
+use gloom_rs::shader::{ShaderBuilder, ShaderType};
+use gloom_rs::texture::{Filter, Texture, TextureOptions, Wrap};
+
+const FRAGMENT_SOURCE: &str = "#version 430 core
+uniform sampler2D albedo;
+out vec4 color;
+void main() { color = texture(albedo, vec2(0.5)); }
+";
+
+const VERTEX_SOURCE: &str = "#version 430 core
+void main() { gl_Position = vec4(0.0); }
+";
+
+// Top row red and green, bottom row blue and white
+fn checker() -> image::RgbaImage {
+    image::RgbaImage::from_fn(4, 2, |x, y| match (x < 2, y == 0) {
+        (true, true) => image::Rgba([255, 0, 0, 255]),
+        (false, true) => image::Rgba([0, 255, 0, 255]),
+        (true, false) => image::Rgba([0, 0, 255, 255]),
+        (false, false) => image::Rgba([255, 255, 255, 255]),
+    })
+}
+
+unsafe fn parameter(texture: &Texture, name: gl::types::GLenum) -> i32 {
+    let mut value = 0;
+    gl::GetTextureParameteriv(texture.id(), name, &mut value);
+    value
+}
+
+#[test]
+fn textures_load_with_mipmaps_and_options() {
+    let _context = match common::gl_context("texture test") { Some(context) => context, None => return };
+
+    let dir = common::TempDir::new("texture");
+    let path = dir.join("checker.png");
+    checker().save(&path).unwrap();
+    unsafe {
+        let texture = Texture::load(&path, &TextureOptions::default()).unwrap();
+        assert_eq!((texture.width(), texture.height(), texture.levels()), (4, 2, 3));
+        assert_eq!(parameter(&texture, gl::TEXTURE_MIN_FILTER), gl::LINEAR_MIPMAP_LINEAR as i32);
+        assert_eq!(parameter(&texture, gl::TEXTURE_WRAP_S), gl::REPEAT as i32);
+
+        // The bottom row of the image comes first, and the smallest mipmap averages all of it
+        let mut pixels = vec![0u8; 4 * 2 * 4];
+        gl::GetTextureImage(texture.id(), 0, gl::RGBA, gl::UNSIGNED_BYTE, pixels.len() as i32, pixels.as_mut_ptr() as *mut _);
+        assert_eq!(&pixels[..4], &[0, 0, 255, 255]);
+        assert_eq!(&pixels[28..], &[0, 255, 0, 255]);
+        let mut smallest = [0u8; 4];
+        gl::GetTextureImage(texture.id(), 2, gl::RGBA, gl::UNSIGNED_BYTE, 4, smallest.as_mut_ptr() as *mut _);
+        assert!(smallest.iter().zip(&[128, 128, 128, 255]).all(|(&a, &b): (&u8, &i32)| (a as i32 - b).abs() <= 1), "{:?}", smallest);
+
+        let options = TextureOptions::default().wrap(Wrap::ClampToEdge).filter(Filter::Nearest).mipmaps(false).srgb(true).anisotropy(64.0);
+        let texture = Texture::from_image(&checker(), &options);
+        assert_eq!(texture.levels(), 1);
+        assert_eq!(parameter(&texture, gl::TEXTURE_MIN_FILTER), gl::NEAREST as i32);
+        assert_eq!(parameter(&texture, gl::TEXTURE_MAG_FILTER), gl::NEAREST as i32);
+        assert_eq!(parameter(&texture, gl::TEXTURE_WRAP_T), gl::CLAMP_TO_EDGE as i32);
+        let mut format = 0;
+        gl::GetTextureLevelParameteriv(texture.id(), 0, gl::TEXTURE_INTERNAL_FORMAT, &mut format);
+        assert_eq!(format, gl::SRGB8_ALPHA8 as i32);
+        if Texture::max_anisotropy() > 0.0 {
+            let mut anisotropy = 0.0;
+            gl::GetTextureParameterfv(texture.id(), 0x84FE, &mut anisotropy);
+            assert_eq!(anisotropy, Texture::max_anisotropy().min(64.0));
+        }
+
+        let shader = ShaderBuilder::new()
+            .compile_shader(VERTEX_SOURCE, ShaderType::Vertex).unwrap()
+            .compile_shader(FRAGMENT_SOURCE, ShaderType::Fragment).unwrap()
+            .link()
+            .unwrap();
+        shader.bind_texture("albedo", &texture, 3).unwrap();
+        let mut unit = 0;
+        gl::GetUniformiv(shader.program_id(), shader.get_uniform_location("albedo"), &mut unit);
+        assert_eq!(unit, 3);
+        let mut bound = 0;
+        gl::GetIntegerv(gl::TEXTURE_BINDING_2D, &mut bound);
+        assert_eq!(bound as u32, texture.id());
+        assert!(shader.bind_texture("missing", &texture, 0).is_err());
+        assert_eq!(gl::GetError(), gl::NO_ERROR);
+    }
+}
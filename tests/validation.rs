@@ -0,0 +1,87 @@
+// Offline shader validation, which needs no OpenGL context and so always runs
+mod common;
+
+use std::path::Path;
+
+use gloom_rs::shader::ShaderType;
+use gloom_rs::validation::{self, Diagnostic};
+
+// A fresh directory under the system temp dir holding the given files
+fn shader_dir(name: &str, files: &[(&str, &str)]) -> common::TempDir {
+    let dir = common::TempDir::new(&format!("validation_{}", name));
+    for (file, source) in files {
+        dir.write(file, source);
+    }
+    dir
+}
+
+#[test]
+fn project_shaders_are_valid() {
+    let report = validation::validate_directory(Path::new("shaders"));
+    let errors: Vec<String> = report.diagnostics.iter().map(Diagnostic::to_string).collect();
+    assert!(report.is_ok(), "\n{}", errors.join("\n"));
+    assert!(report.validated.len() >= 2);
+}
+
+#[test]
+fn errors_point_at_the_original_file_and_line() {
+    let dir = shader_dir("errors", &[
+        ("lighting.glsl", "vec3 shade(vec3 normal) {\n    return normal * brightness;\n}\n"),
+        ("lit.frag", "#version 430 core\n\nin layout(location=1) vec3 normal;\n#include \"lighting.glsl\"\nout vec4 color;\nvoid main() { color = vec4(shade(normal), 1.0); }\n"),
+        ("typo.vert", "#version 430 core\nin layout(location=0) vec3 position;\nvoid main() {\n    gl_Position = vec4(positon, 1.0);\n}\n"),
+    ]);
+
+    let frag = validation::validate_file(&dir.join("lit.frag"), ShaderType::Fragment, &[]).unwrap_err();
+    assert_eq!((frag[0].file.file_name().unwrap().to_str(), frag[0].line), (Some("lighting.glsl"), 2), "{:?}", frag);
+
+    let vert = validation::validate_file(&dir.join("typo.vert"), ShaderType::Vertex, &[]).unwrap_err();
+    assert_eq!((vert[0].file.as_path(), vert[0].line), (dir.join("typo.vert").as_path(), 4), "{:?}", vert);
+    assert!(vert[0].to_string().contains("typo.vert:4:"));
+
+    // Defines are applied as ShaderBuilder would
+    let defined = [("brightness".to_string(), "0.5".to_string())];
+    validation::validate_file(&dir.join("lit.frag"), ShaderType::Fragment, &defined).unwrap();
+}
+
+#[test]
+fn loose_uniforms_samplers_booleans_and_images_are_accepted() {
+    let fragment = "#version 430 core
+in layout(location=0) vec2 uv;
+uniform layout(location=3) mat4 transform;
+uniform float exposure = max(1.0, 0.5), gamma;
+uniform sampler2D albedo;
+uniform sampler2DShadow shadows;
+layout(std140) uniform Lights {
+    vec4 directions[4];
+};
+layout(rgba8) uniform readonly image2D mask;
+uniform bool lit_only;
+uniform bvec2 flip;
+out vec4 color;
+void main() {
+    float lit = texture(shadows, vec3(uv, 0.5));
+    if (lit_only && any(flip)) {
+        lit = 1.0;
+    }
+    color = transform * texture(albedo, uv) * exposure * gamma * lit * directions[0] * imageLoad(mask, ivec2(0));
+}
+";
+    let interface = validation::validate_source(fragment, ShaderType::Fragment, None, &[])
+        .unwrap_or_else(|e| panic!("{:?}", e));
+    assert_eq!(interface.inputs.len(), 1);
+    assert_eq!((interface.inputs[0].name.as_str(), interface.inputs[0].type_name.as_str(), interface.inputs[0].line), ("uv", "vec2", 2));
+}
+
+#[test]
+fn mismatched_stage_interfaces_are_reported() {
+    let dir = shader_dir("interface", &[
+        ("mesh.vert", "#version 430 core\nout layout(location=0) vec3 normal;\nout layout(location=1) vec4 colour;\nvoid main() { normal = vec3(0); colour = vec4(1); gl_Position = vec4(0); }\n"),
+        ("mesh.frag", "#version 430 core\nin layout(location=0) vec4 normal;\nin layout(location=1) vec4 colour;\nin layout(location=2) vec2 uv;\nout vec4 color;\nvoid main() { color = normal * colour * uv.x; }\n"),
+    ]);
+    let report = validation::validate_directory(&dir);
+    let lines: Vec<(usize, bool)> = report.diagnostics.iter()
+        .map(|d| (d.line, d.file.ends_with("mesh.frag")))
+        .collect();
+    assert_eq!(lines, [(2, true), (4, true)], "{:?}", report.diagnostics);
+    assert!(report.diagnostics[0].message.contains("vec3"));
+}
@@ -0,0 +1,81 @@
+// Packing vertex data into buffers, which needs no OpenGL context
+use gloom_rs::vertex_layout::{self, ComponentType, VertexAttribute, VertexLayout, VertexStreams};
+
+const POSITIONS: [f32; 6] = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+const UVS: [f32; 4] = [0.0, 0.5, 1.0, 0.25];
+
+fn floats(bytes: &[u8]) -> Vec<f32> {
+    bytes.chunks(4).map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]])).collect()
+}
+
+#[test]
+fn interleaved_layout_packs_each_vertex_together() {
+    let streams = VertexStreams::new()
+        .with(vertex_layout::POSITION, &POSITIONS, 3)
+        .with(vertex_layout::UV, &UVS, 2);
+    let layout = VertexLayout::interleaved(vec![
+        VertexAttribute::new(vertex_layout::POSITION, 0, 3),
+        VertexAttribute::new(vertex_layout::UV, 3, 2),
+    ]);
+    let buffers = layout.pack(&streams).unwrap();
+    assert_eq!(buffers.len(), 1);
+    assert_eq!(buffers[0].stride, 20);
+    assert_eq!(buffers[0].attributes.iter().map(|(a, offset)| (a.location, *offset)).collect::<Vec<_>>(), [(0, 0), (3, 12)]);
+    assert_eq!(floats(&buffers[0].data), [1.0, 2.0, 3.0, 0.0, 0.5, 4.0, 5.0, 6.0, 1.0, 0.25]);
+
+    let separate = VertexLayout::separate(layout.attributes.clone()).pack(&streams).unwrap();
+    assert_eq!(separate.iter().map(|b| b.stride).collect::<Vec<_>>(), [12, 8]);
+    assert_eq!(floats(&separate[1].data), UVS);
+}
+
+#[test]
+fn integer_and_half_float_formats_are_converted() {
+    let colours = [1.0, 0.5, 0.0, -1.0];
+    let streams = VertexStreams::new()
+        .with(vertex_layout::POSITION, &POSITIONS[..3], 3)
+        .with(vertex_layout::COLOUR, &colours, 4)
+        .with("bone", &[7.0, 300.0], 2);
+    let layout = VertexLayout::interleaved(vec![
+        VertexAttribute::new(vertex_layout::POSITION, 0, 3).with_type(ComponentType::F16),
+        VertexAttribute::new(vertex_layout::COLOUR, 1, 4).with_type(ComponentType::U8).normalized(),
+        VertexAttribute::new("bone", 2, 2).with_type(ComponentType::U8).integer(),
+    ]);
+    let buffer = &layout.pack(&streams).unwrap()[0];
+    // Three halves padded to 8 bytes, then four bytes, then two bytes padded to 4
+    assert_eq!(buffer.stride, 16);
+    let halves: Vec<u16> = buffer.data[..6].chunks(2).map(|b| u16::from_ne_bytes([b[0], b[1]])).collect();
+    assert_eq!(halves, [0x3c00, 0x4000, 0x4200]);
+    assert_eq!(&buffer.data[8..12], &[255, 128, 0, 0]);
+    // Integer attributes saturate rather than wrap
+    assert_eq!(&buffer.data[12..14], &[7, 255]);
+}
+
+#[test]
+fn half_floats_round_to_nearest() {
+    assert_eq!(vertex_layout::f32_to_f16(0.0), 0);
+    assert_eq!(vertex_layout::f32_to_f16(-2.0), 0xc000);
+    assert_eq!(vertex_layout::f32_to_f16(65504.0), 0x7bff);
+    assert_eq!(vertex_layout::f32_to_f16(1e6), 0x7c00);
+    assert_eq!(vertex_layout::f32_to_f16(1.0 + 1.0 / 2048.0), 0x3c00);
+    assert_eq!(vertex_layout::f32_to_f16(1.0 + 3.0 / 2048.0), 0x3c02);
+    // The smallest subnormal half
+    assert_eq!(vertex_layout::f32_to_f16(5.960_464_5e-8), 0x0001);
+}
+
+#[test]
+fn optional_attributes_may_be_missing() {
+    let streams = VertexStreams::new().with(vertex_layout::POSITION, &POSITIONS, 3);
+    let mut layout = VertexLayout::interleaved(vec![
+        VertexAttribute::new(vertex_layout::POSITION, 0, 3),
+        VertexAttribute::new(vertex_layout::TANGENT, 4, 4).optional(),
+    ]);
+    assert_eq!(layout.pack(&streams).unwrap()[0].stride, 12);
+
+    layout.attributes[1].optional = false;
+    assert_eq!(layout.pack(&streams).unwrap_err(), "The vertex data has no tangent attribute");
+
+    let short = VertexStreams::new()
+        .with(vertex_layout::POSITION, &POSITIONS, 3)
+        .with(vertex_layout::TANGENT, &[0.0; 4], 4);
+    assert!(layout.pack(&short).is_err());
+}